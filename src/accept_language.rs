@@ -0,0 +1,211 @@
+// src/accept_language.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Accept-Language parsing (RFC 9110 section 12.5.4) and language range
+//! matching (RFC 4647).
+
+/// One entry of an Accept-Language field: a language range and its weight.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LanguageRange {
+    pub range: String,
+    /// Between 0 and 1. Zero means "not this one".
+    pub quality: f32,
+}
+
+/// RFC 4647 section 2.1: `*` or up to eight letters, followed by any number
+/// of `-` and up to eight letters or digits.
+pub fn is_valid_range(range: &str) -> bool {
+    if range == "*" {
+        return true;
+    }
+    let mut subtags = range.split('-');
+    let first = subtags.next().unwrap_or_default();
+    if first.is_empty() || first.len() > 8
+        || !first.bytes().all(|b| b.is_ascii_alphabetic()) {
+        return false;
+    }
+    return subtags.all(|s| !s.is_empty() && s.len() <= 8
+        && s.bytes().all(|b| b.is_ascii_alphanumeric()));
+}
+
+/// RFC 9110 qvalue: 0 or 1 with up to three decimals.
+fn parse_quality(input: &str) -> Option<f32> {
+    let (whole, fraction) = match input.split_once('.') {
+        Some((whole, fraction)) => (whole, fraction),
+        None => (input, ""),
+    };
+    if fraction.len() > 3 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    return match whole {
+        "0" => Some(format!("0.{fraction}0").parse().ok()?),
+        "1" if fraction.bytes().all(|b| b == b'0') => Some(1.0),
+        _ => None,
+    };
+}
+
+/// Parses an Accept-Language field into ranges, best first. Ranges with the
+/// same weight keep the order they were sent in. Malformed entries are
+/// skipped rather than spoiling the whole field.
+pub fn parse(header: &str) -> Vec<LanguageRange> {
+    let mut output: Vec<LanguageRange> = Vec::new();
+    for entry in header.split(',') {
+        let mut parts = entry.split(';').map(|p| p.trim());
+        let range = parts.next().unwrap_or_default();
+        if range.is_empty() || !is_valid_range(range) {
+            continue;
+        }
+        let mut quality = Some(1.0);
+        for parameter in parts {
+            if let Some((name, value)) = parameter.split_once('=')
+                && name.trim().eq_ignore_ascii_case("q") {
+                quality = parse_quality(value.trim());
+            }
+        }
+        if let Some(quality) = quality {
+            output.push(LanguageRange { range: String::from(range), quality });
+        }
+    }
+    // sort_by is stable, so equal weights stay in order.
+    output.sort_by(|a, b| b.quality.total_cmp(&a.quality));
+    return output;
+}
+
+/// RFC 4647 section 3.3.1 basic filtering: the range is the tag, or a prefix
+/// of it ending at a `-`. `*` matches everything.
+pub fn basic_match(range: &str, tag: &str) -> bool {
+    if range == "*" {
+        return true;
+    }
+    if range.len() > tag.len() {
+        return false;
+    }
+    return tag[..range.len()].eq_ignore_ascii_case(range)
+        && (tag.len() == range.len() || tag.as_bytes()[range.len()] == b'-');
+}
+
+/// The tags that any wanted range matches by basic filtering, best range
+/// first. Tags a range with q=0 matches are left out.
+pub fn filter<'a>(ranges: &[LanguageRange], tags: &[&'a str]) -> Vec<&'a str> {
+    let mut output: Vec<&'a str> = Vec::new();
+    for range in ranges.iter().filter(|r| r.quality > 0.0) {
+        for tag in tags {
+            if basic_match(&range.range, tag) && !output.contains(tag)
+                && !refused(ranges, tag) {
+                output.push(tag);
+            }
+        }
+    }
+    return output;
+}
+
+/// RFC 4647 section 3.4 lookup: for each range, best first, look for a tag
+/// equal to it, then to it with the last subtag cut off, and so on. A
+/// single letter subtag left at the end is cut off along with the one after
+/// it. `*` is skipped. None if nothing matches; the caller has a default.
+pub fn lookup<'a>(ranges: &[LanguageRange], tags: &[&'a str])
+    -> Option<&'a str>
+{
+    for range in ranges.iter().filter(|r| r.quality > 0.0) {
+        if range.range == "*" {
+            continue;
+        }
+        let mut candidate = range.range.as_str();
+        loop {
+            if let Some(tag) = tags.iter()
+                .find(|t| t.eq_ignore_ascii_case(candidate))
+                && !refused(ranges, tag) {
+                return Some(tag);
+            }
+            let Some(index) = candidate.rfind('-') else {
+                break;
+            };
+            candidate = &candidate[..index];
+            if candidate.len() >= 2
+                && candidate.as_bytes()[candidate.len() - 2] == b'-' {
+                candidate = &candidate[..candidate.len() - 2];
+            }
+        }
+    }
+    return None;
+}
+
+/// True if the client said it does not want `tag` with a q=0 range. The
+/// most specific range that matches decides, so `de;q=0, de-CH` still
+/// allows de-CH.
+fn refused(ranges: &[LanguageRange], tag: &str) -> bool {
+    return ranges.iter()
+        .filter(|r| basic_match(&r.range, tag))
+        .max_by_key(|r| if r.range == "*" { 0 } else { r.range.len() })
+        .is_some_and(|r| r.quality == 0.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(header: &str) -> Vec<(String, f32)> {
+        return parse(header).into_iter()
+            .map(|r| (r.range, r.quality))
+            .collect();
+    }
+
+    #[test]
+    fn parses_quality_values() {
+        assert_eq!(ranges("fr;q=0.5, de-AT, en;Q=0.75, *;q=0"), [
+            (String::from("de-AT"), 1.0),
+            (String::from("en"), 0.75),
+            (String::from("fr"), 0.5),
+            (String::from("*"), 0.0),
+        ]);
+        // Equal weights keep their order.
+        assert_eq!(ranges("b;q=0.5, a;q=0.500"), [
+            (String::from("b"), 0.5),
+            (String::from("a"), 0.5),
+        ]);
+        // Bad entries are skipped, the rest still count.
+        assert_eq!(ranges("de;q=2, fr;q=0.1234, en_US, x-, it;q=1.000"),
+            [(String::from("it"), 1.0)]);
+        assert_eq!(ranges("en;q=.5"), []);
+    }
+
+    #[test]
+    fn filters_by_prefix() {
+        assert!(basic_match("de", "de-AT"));
+        assert!(basic_match("DE", "de"));
+        assert!(!basic_match("de", "dea"));
+        assert!(!basic_match("de-AT", "de"));
+        let tags = ["en", "de", "de-CH", "fr"];
+        assert_eq!(filter(&parse("de, *;q=0.1"), &tags),
+            ["de", "de-CH", "en", "fr"]);
+        assert_eq!(filter(&parse("de;q=0, de-CH, *"), &tags),
+            ["de-CH", "en", "fr"]);
+    }
+
+    #[test]
+    fn looks_up_the_best_tag() {
+        let tags = ["en", "de", "fr"];
+        let pick = |header: &str| lookup(&parse(header), &tags);
+        assert_eq!(pick("de-AT, fr;q=0.5"), Some("de"));
+        assert_eq!(pick("fr;q=0, de;q=0.1"), Some("de"));
+        assert_eq!(pick("fr;q=0"), None);
+        assert_eq!(pick("*"), None);
+        assert_eq!(pick("it, FR-ca"), Some("fr"));
+        // A singleton such as the x in fr-x-private is cut with its subtag.
+        assert_eq!(pick("fr-x-private"), Some("fr"));
+        assert_eq!(pick("de-CH;q=0.9, de;q=0"), None);
+        assert_eq!(pick(""), None);
+    }
+}
@@ -0,0 +1,179 @@
+// src/access.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Allow and deny lists of client address ranges.
+//!
+//! The diagnostic page shows the whole environment, which says a lot about
+//! the server. On a shared host these lists keep it to the people who should
+//! see it. The client is the one the trusted proxies resolve to, which is
+//! plain REMOTE_ADDR when no proxies are trusted.
+
+use std::fmt;
+use std::net::IpAddr;
+
+use crate::cidr;
+use crate::cidr::Cidr;
+use crate::config;
+use crate::forwarded;
+use crate::log;
+use crate::response::Response;
+
+pub const ALLOW_VARIABLE: &str = "TESTCGI_ALLOW";
+pub const DENY_VARIABLE: &str = "TESTCGI_DENY";
+
+/// Deny rules are checked first and win. If there are any allow rules a
+/// client has to match one of them; with only deny rules everyone else is
+/// let in.
+#[derive(Clone, Debug, Default)]
+pub struct AccessList {
+    pub allow: Vec<Cidr>,
+    pub deny: Vec<Cidr>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rule {
+    Allow(Cidr),
+    Deny(Cidr),
+    /// No allow rule matched and there were some.
+    NotAllowed,
+    /// No rule matched and there were no allow rules.
+    Default,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Rule::Allow(cidr) => write!(f, "allow {cidr}"),
+            Rule::Deny(cidr) => write!(f, "deny {cidr}"),
+            Rule::NotAllowed => write!(f, "not in the allow list"),
+            Rule::Default => write!(f, "default allow"),
+        };
+    }
+}
+
+impl Rule {
+    pub fn is_allowed(&self) -> bool {
+        return matches!(self, Rule::Allow(_) | Rule::Default);
+    }
+}
+
+impl AccessList {
+    pub fn parse(allow: &str, deny: &str) -> Result<AccessList, String> {
+        return Ok(AccessList {
+            allow: cidr::parse_list(allow)?,
+            deny: cidr::parse_list(deny)?,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.allow.is_empty() && self.deny.is_empty();
+    }
+
+    /// The rule that decides for `client`. A client without a usable
+    /// address, such as a proxy reporting `unknown`, matches no range.
+    pub fn check(&self, client: Option<IpAddr>) -> Rule {
+        if let Some(ip) = client {
+            if let Some(range) = cidr::find(&self.deny, &ip) {
+                return Rule::Deny(*range);
+            }
+            if let Some(range) = cidr::find(&self.allow, &ip) {
+                return Rule::Allow(*range);
+            }
+        }
+        if self.allow.is_empty() {
+            return Rule::Default;
+        }
+        return Rule::NotAllowed;
+    }
+}
+
+/// Reads TESTCGI_ALLOW and TESTCGI_DENY. None when neither is set.
+pub fn from_env() -> Result<Option<AccessList>, String> {
+    let allow = config::get(ALLOW_VARIABLE).unwrap_or_default();
+    let deny = config::get(DENY_VARIABLE).unwrap_or_default();
+    let list = match AccessList::parse(&allow, &deny) {
+        Ok(list) => list,
+        Err(message) => {
+            return Err(format!("{ALLOW_VARIABLE}/{DENY_VARIABLE}: {message}"));
+        }
+    };
+    if list.is_empty() {
+        return Ok(None);
+    }
+    return Ok(Some(list));
+}
+
+/// Checks the client against the configured lists. Returns the response to
+/// send instead of handling the request: 403 for a denied client, or 500 if
+/// the lists cannot be read, since failing open would defeat the point.
+/// Denials are logged to stderr with the rule that matched.
+pub fn enforce() -> Option<Response> {
+    let list = match from_env() {
+        Ok(Some(list)) => list,
+        Ok(None) => return None,
+        Err(message) => {
+            log::error("access control is misconfigured",
+                &[("error", &message)]);
+            return Some(Response::text(500, &message));
+        }
+    };
+    let client = match forwarded::from_env() {
+        Ok(client) => client,
+        Err(message) => {
+            log::error("cannot resolve the client address",
+                &[("error", &message)]);
+            return Some(Response::text(500, &message));
+        }
+    };
+    let rule = list.check(client.resolved.client_ip);
+    if rule.is_allowed() {
+        return None;
+    }
+    log::warn("access denied", &[("client", &client.resolved.client),
+        ("remote_addr", &client.remote_addr), ("rule", &rule)]);
+    return Some(Response::text(403, "Access denied."));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(input: &str) -> Option<IpAddr> {
+        return Some(input.parse().unwrap());
+    }
+
+    #[test]
+    fn deny_wins() {
+        let list = AccessList::parse("10.0.0.0/8, ::1", "10.0.0.66").unwrap();
+        let deny = Cidr::parse("10.0.0.66").unwrap();
+        assert_eq!(list.check(ip("10.0.0.66")), Rule::Deny(deny));
+        assert!(!list.check(ip("10.0.0.66")).is_allowed());
+        let allow = Cidr::parse("10.0.0.0/8").unwrap();
+        assert_eq!(list.check(ip("10.0.0.65")), Rule::Allow(allow));
+        assert_eq!(list.check(ip("::ffff:10.0.0.65")), Rule::Allow(allow));
+        assert_eq!(list.check(ip("192.0.2.1")), Rule::NotAllowed);
+        assert_eq!(list.check(None), Rule::NotAllowed);
+    }
+
+    #[test]
+    fn deny_only() {
+        let list = AccessList::parse("", "192.0.2.0/24").unwrap();
+        assert!(!list.check(ip("192.0.2.9")).is_allowed());
+        assert_eq!(list.check(ip("198.51.100.1")), Rule::Default);
+        assert_eq!(list.check(None), Rule::Default);
+        assert!(AccessList::parse("", "").unwrap().is_empty());
+        assert!(AccessList::parse("10.0.0.0/+8", "").is_err());
+    }
+}
@@ -0,0 +1,258 @@
+// src/access_log.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! testcgi's own record of each request, kept apart from the web server's.
+//!
+//! Each entry is one line, written to the file with a single `write` on a
+//! descriptor opened with O_APPEND. The kernel then places the whole line at
+//! the end of the file, so CGI processes running at the same time cannot
+//! mix parts of their lines together.
+
+use std::env;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use crate::config;
+use crate::forwarded;
+use crate::http_date::format_clf_date;
+use crate::http_date::format_rfc3339;
+use crate::json::quote;
+use crate::request_id;
+use crate::url::Url;
+
+pub const FILE_VARIABLE: &str = "TESTCGI_ACCESS_LOG";
+pub const FORMAT_VARIABLE: &str = "TESTCGI_ACCESS_LOG_FORMAT";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// `host ident user [time] "request" status bytes`
+    Common,
+    /// Common followed by `"referer" "user-agent"`.
+    Combined,
+    /// One JSON object per line.
+    Json,
+}
+
+impl Format {
+    /// Every name `parse` takes, in any case, for the access_log.format
+    /// setting.
+    pub const NAMES: [&str; 3] = ["common", "combined", "json"];
+
+    pub fn parse(input: &str) -> Option<Format> {
+        return match input.trim().to_ascii_lowercase().as_str() {
+            "common" => Some(Format::Common),
+            "combined" => Some(Format::Combined),
+            "json" => Some(Format::Json),
+            _ => None,
+        };
+    }
+}
+
+pub struct Entry {
+    pub time: SystemTime,
+    /// The client address, resolved through trusted proxies.
+    pub remote_host: String,
+    pub user: Option<String>,
+    pub method: String,
+    /// The path and query.
+    pub target: String,
+    pub protocol: String,
+    pub status: u16,
+    /// Body bytes, not counting the header.
+    pub bytes: u64,
+    pub duration: Duration,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+}
+
+impl Entry {
+    /// Fills in the request side from the meta-variables.
+    pub fn from_env(status: u16, bytes: u64, time: SystemTime,
+        duration: Duration) -> Entry
+    {
+        let get = |name: &str| env::var(name).ok().filter(|v| !v.is_empty());
+        let remote_host = match forwarded::from_env() {
+            Ok(client) => client.resolved.client,
+            Err(_) => get("REMOTE_ADDR").unwrap_or_default(),
+        };
+        return Entry {
+            time,
+            remote_host,
+            user: get("REMOTE_USER"),
+            method: get("REQUEST_METHOD").unwrap_or_default(),
+            target: Url::from_env().request_target(),
+            protocol: get("SERVER_PROTOCOL")
+                .unwrap_or_else(|| String::from("HTTP/1.0")),
+            status,
+            bytes,
+            duration,
+            referer: get("HTTP_REFERER"),
+            user_agent: get("HTTP_USER_AGENT"),
+            request_id: Some(request_id::current().id.clone()),
+        };
+    }
+
+    /// The line to log, ending in a newline.
+    ///
+    /// Common and Combined are followed by the duration in microseconds and
+    /// the quoted request ID, the way Apache's `%D` and `%{UNIQUE_ID}e` are
+    /// usually tacked on. Log parsers ignore fields past the ones they know.
+    pub fn format(&self, format: Format) -> String {
+        if format == Format::Json {
+            return self.json();
+        }
+        let or_dash = |v: &Option<String>| match v {
+            Some(v) => escape(v),
+            None => String::from("-"),
+        };
+        let bytes = if self.bytes == 0 {
+            String::from("-")
+        } else {
+            self.bytes.to_string()
+        };
+        let mut line = format!("{} - {} [{}] \"{} {} {}\" {} {bytes}",
+            escape(&self.remote_host), or_dash(&self.user),
+            format_clf_date(self.time), escape(&self.method),
+            escape(&self.target), escape(&self.protocol), self.status);
+        if format == Format::Combined {
+            line.push_str(&format!(" \"{}\" \"{}\"", or_dash(&self.referer),
+                or_dash(&self.user_agent)));
+        }
+        line.push_str(&format!(" {} \"{}\"\n", self.duration.as_micros(),
+            or_dash(&self.request_id)));
+        return line;
+    }
+
+    fn json(&self) -> String {
+        let or_null = |v: &Option<String>| match v {
+            Some(v) => quote(v),
+            None => String::from("null"),
+        };
+        return format!("{{\"time\":{},\"remote_addr\":{},\"user\":{},\
+            \"method\":{},\"target\":{},\"protocol\":{},\"status\":{},\
+            \"bytes\":{},\"duration_ms\":{:.3},\"referer\":{},\
+            \"user_agent\":{},\"request_id\":{}}}\n",
+            quote(&format_rfc3339(self.time)), quote(&self.remote_host),
+            or_null(&self.user), quote(&self.method), quote(&self.target),
+            quote(&self.protocol), self.status, self.bytes,
+            self.duration.as_secs_f64() * 1000.0, or_null(&self.referer),
+            or_null(&self.user_agent), or_null(&self.request_id));
+    }
+}
+
+/// Escapes a field the way Apache does: quotes and backslashes get a
+/// backslash and anything unprintable becomes `\xhh`, so a client cannot
+/// forge a log line.
+pub fn escape(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        match byte {
+            b'"' => output.push_str("\\\""),
+            b'\\' => output.push_str("\\\\"),
+            0x20..=0x7e => output.push(byte as char),
+            _ => output.push_str(&format!("\\x{byte:02x}")),
+        }
+    }
+    return output;
+}
+
+/// Appends one line with one write call.
+pub fn append(path: &Path, line: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+    let written = file.write(line.as_bytes())?;
+    if written != line.len() {
+        return Err(io::Error::new(io::ErrorKind::WriteZero, format!(
+            "only {written} of {} bytes reached the access log", line.len())));
+    }
+    return Ok(());
+}
+
+/// Logs the request if access_log.file is set. `started` is when the
+/// request began; the duration runs from then until now.
+pub fn record(status: u16, bytes: u64, started: SystemTime) -> io::Result<()> {
+    let Some(path) = config::get(FILE_VARIABLE) else {
+        return Ok(());
+    };
+    let format = config::get(FORMAT_VARIABLE)
+        .and_then(|v| Format::parse(&v))
+        .unwrap_or(Format::Combined);
+    let duration = started.elapsed().unwrap_or_default();
+    let entry = Entry::from_env(status, bytes, started, duration);
+    return append(Path::new(&path), &entry.format(format));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::UNIX_EPOCH;
+
+    fn entry() -> Entry {
+        return Entry {
+            time: UNIX_EPOCH + Duration::from_secs(784_111_777),
+            remote_host: String::from("192.0.2.1"),
+            user: None,
+            method: String::from("GET"),
+            target: String::from("/a?\"b\""),
+            protocol: String::from("HTTP/1.1"),
+            status: 200,
+            bytes: 0,
+            duration: Duration::from_micros(1500),
+            referer: Some(String::from("https://e/\r\nfake")),
+            user_agent: None,
+            request_id: Some(String::from("r1")),
+        };
+    }
+
+    #[test]
+    fn formats_lines() {
+        let entry = entry();
+        assert_eq!(entry.format(Format::Common), "192.0.2.1 - - \
+            [06/Nov/1994:08:49:37 +0000] \"GET /a?\\\"b\\\" HTTP/1.1\" 200 - \
+            1500 \"r1\"\n");
+        assert_eq!(entry.format(Format::Combined), "192.0.2.1 - - \
+            [06/Nov/1994:08:49:37 +0000] \"GET /a?\\\"b\\\" HTTP/1.1\" 200 - \
+            \"https://e/\\x0d\\x0afake\" \"-\" 1500 \"r1\"\n");
+        assert_eq!(entry.format(Format::Json), "{\"time\":\
+            \"1994-11-06T08:49:37.000Z\",\"remote_addr\":\"192.0.2.1\",\
+            \"user\":null,\"method\":\"GET\",\"target\":\"/a?\\\"b\\\"\",\
+            \"protocol\":\"HTTP/1.1\",\"status\":200,\"bytes\":0,\
+            \"duration_ms\":1.500,\"referer\":\"https://e/\\r\\nfake\",\
+            \"user_agent\":null,\"request_id\":\"r1\"}\n");
+    }
+
+    #[test]
+    fn escapes_fields() {
+        assert_eq!(escape("a\"b\\c\tü"), "a\\\"b\\\\c\\x09\\xc3\\xbc");
+        assert_eq!(Format::parse(" JSON "), Some(Format::Json));
+        assert_eq!(Format::parse("xml"), None);
+    }
+
+    #[test]
+    fn appends_lines() {
+        let path = env::temp_dir().join(format!("testcgi-access-log-{}",
+            std::process::id()));
+        _ = fs::remove_file(&path);
+        append(&path, "one\n").unwrap();
+        append(&path, "two\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+        _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,192 @@
+// src/authorization.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Getting at the Authorization request header from a CGI program.
+//!
+//! RFC 3875 section 4.1.18 lets servers leave credentials out of the
+//! environment, and most do by default. Apache needs `CGIPassAuth On`, or a
+//! rewrite rule that lands in REDIRECT_HTTP_AUTHORIZATION instead.
+
+use std::env;
+
+/// The meta-variables the Authorization header can show up in, best first.
+pub const AUTHORIZATION_VARIABLES: [&str; 2] =
+    ["HTTP_AUTHORIZATION", "REDIRECT_HTTP_AUTHORIZATION"];
+
+/// The raw Authorization header, if the server passed it on.
+pub fn authorization_header() -> Option<String> {
+    for name in AUTHORIZATION_VARIABLES {
+        match env::var(name) {
+            Ok(value) if !value.trim().is_empty() => return Some(value),
+            _ => continue,
+        }
+    }
+    return None;
+}
+
+/// Splits `Scheme credentials` into the scheme and the rest. None if the
+/// scheme is not `scheme`, compared without regard to case.
+pub fn strip_scheme<'a>(header: &'a str, scheme: &str) -> Option<&'a str> {
+    let header = header.trim();
+    let (name, rest) = match header.split_once([' ', '\t']) {
+        Some((name, rest)) => (name, rest.trim()),
+        None => (header, ""),
+    };
+    if !name.eq_ignore_ascii_case(scheme) {
+        return None;
+    }
+    return Some(rest);
+}
+
+/// Quotes a value for an auth-param, escaping `"` and `\` as RFC 9110
+/// quoted-string requires. Control characters other than tab cannot appear
+/// in a quoted-string at all, so they are dropped.
+pub fn quote(value: &str) -> String {
+    let mut output = String::with_capacity(value.len() + 2);
+    output.push('"');
+    for c in value.chars() {
+        if c.is_control() && c != '\t' {
+            continue;
+        }
+        if c == '"' || c == '\\' {
+            output.push('\\');
+        }
+        output.push(c);
+    }
+    output.push('"');
+    return output;
+}
+
+/// A note for 401 pages explaining why the server may not have handed us the
+/// credentials the browser sent.
+pub fn missing_header_hint() -> String {
+    let mut hint = String::from("No Authorization header reached testcgi.");
+    match env::var("AUTH_TYPE") {
+        Ok(auth_type) if !auth_type.is_empty() => {
+            hint.push_str(&format!(" The server already handled {auth_type} \
+                authentication itself (REMOTE_USER={}).",
+                env::var("REMOTE_USER").unwrap_or_default()));
+        }
+        _ => {}
+    }
+    hint.push_str(" If your browser did send one, the server is not passing \
+        it to CGI programs; for Apache try `CGIPassAuth On`.");
+    return hint;
+}
+
+/// Parses a comma separated auth-param list such as
+/// `username="Mufasa", realm="http-auth@example.org", nc=00000001`.
+///
+/// Names are lower cased. Quoted values have their quoting removed. None if
+/// the list is malformed, for example a quote that never closes.
+pub fn parse_auth_params(input: &str) -> Option<Vec<(String, String)>> {
+    let mut output: Vec<(String, String)> = Vec::new();
+    let mut chars = input.chars().peekable();
+    loop {
+        while matches!(chars.peek(), Some(' ' | '\t' | ',')) {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut name = String::new();
+        while let Some(&c) = chars.peek() {
+            if c == '=' || c == ' ' || c == '\t' {
+                break;
+            }
+            name.push(c.to_ascii_lowercase());
+            chars.next();
+        }
+        while matches!(chars.peek(), Some(' ' | '\t')) {
+            chars.next();
+        }
+        if chars.next() != Some('=') || name.is_empty() {
+            return None;
+        }
+        while matches!(chars.peek(), Some(' ' | '\t')) {
+            chars.next();
+        }
+
+        let mut value = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => value.push(chars.next()?),
+                    c => value.push(c),
+                }
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c == ',' || c == ' ' || c == '\t' {
+                    break;
+                }
+                value.push(c);
+                chars.next();
+            }
+        }
+        output.push((name, value));
+
+        while matches!(chars.peek(), Some(' ' | '\t')) {
+            chars.next();
+        }
+        match chars.next() {
+            None => break,
+            Some(',') => continue,
+            Some(_) => return None,
+        }
+    }
+    return Some(output);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        return pairs.iter()
+            .map(|(n, v)| (String::from(*n), String::from(*v)))
+            .collect();
+    }
+
+    #[test]
+    fn strips_the_scheme() {
+        assert_eq!(strip_scheme(" basic  dXNlcg== ", "Basic"),
+            Some("dXNlcg=="));
+        assert_eq!(strip_scheme("Bearer\tx", "bearer"), Some("x"));
+        assert_eq!(strip_scheme("Basic", "Basic"), Some(""));
+        assert_eq!(strip_scheme("Basically x", "Basic"), None);
+    }
+
+    #[test]
+    fn quotes_values() {
+        assert_eq!(quote("a \"b\" \\c"), "\"a \\\"b\\\" \\\\c\"");
+    }
+
+    #[test]
+    fn parses_auth_params() {
+        assert_eq!(parse_auth_params("username=\"Mufasa\", \
+            realm=\"a\\\"b, c\",NC=00000001 ,, qop = auth").unwrap(),
+            params(&[("username", "Mufasa"), ("realm", "a\"b, c"),
+                ("nc", "00000001"), ("qop", "auth")]));
+        assert_eq!(parse_auth_params("").unwrap(), params(&[]));
+        assert_eq!(parse_auth_params("a=\"open"), None);
+        assert_eq!(parse_auth_params("=x"), None);
+        assert_eq!(parse_auth_params("a"), None);
+        assert_eq!(parse_auth_params("a=b c=d"), None);
+    }
+}
@@ -0,0 +1,130 @@
+// src/base64.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Base64 from RFC 4648, in both the standard and the URL-safe alphabet.
+
+const STANDARD: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+const URL_SAFE: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Standard base64 with `=` padding, as used by HTTP Basic authentication.
+pub fn encode(data: &[u8]) -> String {
+    return encode_with(data, STANDARD, true);
+}
+
+/// URL-safe base64 without padding, as used by JWTs.
+pub fn encode_url(data: &[u8]) -> String {
+    return encode_with(data, URL_SAFE, false);
+}
+
+/// Decodes standard base64. Padding is optional but has to be right if it
+/// is there. Whitespace is not allowed.
+pub fn decode(input: &str) -> Option<Vec<u8>> {
+    return decode_with(input, STANDARD);
+}
+
+/// Decodes URL-safe base64, with or without padding.
+pub fn decode_url(input: &str) -> Option<Vec<u8>> {
+    return decode_with(input, URL_SAFE);
+}
+
+fn encode_with(data: &[u8], alphabet: &[u8; 64], pad: bool) -> String {
+    let mut output = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b0 = chunk[0] as u32;
+        let b1 = chunk.get(1).copied().unwrap_or(0) as u32;
+        let b2 = chunk.get(2).copied().unwrap_or(0) as u32;
+        let triple = (b0 << 16) | (b1 << 8) | b2;
+        let characters = chunk.len() + 1;
+        for i in 0..4 {
+            if i < characters {
+                let index = (triple >> (18 - 6 * i)) & 0x3f;
+                output.push(alphabet[index as usize] as char);
+            } else if pad {
+                output.push('=');
+            }
+        }
+    }
+    return output;
+}
+
+fn decode_with(input: &str, alphabet: &[u8; 64]) -> Option<Vec<u8>> {
+    let input = input.as_bytes();
+    let unpadded = match input.iter().position(|b| *b == b'=') {
+        Some(position) => {
+            // Padding only ever fills out the last group of four.
+            if !input.len().is_multiple_of(4)
+                || input.len() - position > 2
+                || !input[position..].iter().all(|b| *b == b'=') {
+                return None;
+            }
+            &input[..position]
+        }
+        None => input,
+    };
+    if unpadded.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut output = Vec::with_capacity(unpadded.len() * 3 / 4);
+    let mut accumulator: u32 = 0;
+    let mut bits: u32 = 0;
+    for byte in unpadded {
+        let value = alphabet.iter().position(|a| a == byte)? as u32;
+        accumulator = (accumulator << 6) | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((accumulator >> bits) as u8);
+            accumulator &= (1 << bits) - 1;
+        }
+    }
+    // Leftover bits have to be zero or the encoding was not canonical.
+    if accumulator != 0 {
+        return None;
+    }
+    return Some(output);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_rfc_4648_vectors() {
+        let vectors = [("", ""), ("f", "Zg=="), ("fo", "Zm8="),
+            ("foo", "Zm9v"), ("foob", "Zm9vYg=="), ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy")];
+        for (plain, encoded) in vectors {
+            assert_eq!(encode(plain.as_bytes()), encoded);
+            assert_eq!(decode(encoded).unwrap(), plain.as_bytes());
+            assert_eq!(decode(encoded.trim_end_matches('=')).unwrap(),
+                plain.as_bytes());
+        }
+        assert_eq!(encode_url(&[0xfb, 0xff]), "-_8");
+        assert_eq!(decode_url("-_8").unwrap(), [0xfb, 0xff]);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        for bad in ["Zg=", "Zg===", "Z===", "Zm9v=", "Z=g=", "Zh==", "Z",
+            "Zm 9v", "-_8"] {
+            assert_eq!(decode(bad), None, "{bad}");
+        }
+        assert_eq!(decode_url("+/8"), None);
+    }
+}
@@ -0,0 +1,147 @@
+// src/basic_auth.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! HTTP Basic authentication from RFC 7617.
+
+use std::path::Path;
+
+use crate::authorization::authorization_header;
+use crate::authorization::missing_header_hint;
+use crate::authorization::quote;
+use crate::authorization::strip_scheme;
+use crate::base64;
+use crate::config;
+use crate::hmac::constant_time_eq;
+use crate::htpasswd::Htpasswd;
+use crate::response::Response;
+
+pub struct Credentials {
+    pub user: String,
+    pub password: String,
+}
+
+/// Decodes an `Authorization: Basic dXNlcjpwYXNz` header.
+///
+/// None if the scheme is not Basic, the base64 is bad, the result is not
+/// UTF-8 or there is no `:` between the user and the password.
+pub fn parse_authorization(header: &str) -> Option<Credentials> {
+    let token = strip_scheme(header, "Basic")?;
+    let decoded = String::from_utf8(base64::decode(token.trim())?).ok()?;
+    let (user, password) = decoded.split_once(':')?;
+    return Some(Credentials {
+        user: String::from(user),
+        password: String::from(password),
+    });
+}
+
+/// The WWW-Authenticate value that asks for Basic credentials.
+pub fn challenge(realm: &str) -> String {
+    return format!("Basic realm={}, charset=\"UTF-8\"", quote(realm));
+}
+
+/// A 401 response with the Basic challenge and a short explanation.
+pub fn unauthorized(realm: &str, reason: &str) -> Response {
+    let mut response = Response::text(401, reason);
+    response.header("WWW-Authenticate", &challenge(realm));
+    return response;
+}
+
+/// `/basic-auth/{user}/{pass}` accepts exactly the credentials in the path.
+pub fn fixed_route(expected_user: &str, expected_password: &str) -> Response {
+    let realm = "testcgi";
+    let header = match authorization_header() {
+        Some(header) => header,
+        None => return unauthorized(realm, &missing_header_hint()),
+    };
+    let credentials = match parse_authorization(&header) {
+        Some(credentials) => credentials,
+        None => {
+            return unauthorized(realm,
+                "The Authorization header is not valid Basic credentials.");
+        }
+    };
+    let user_ok = constant_time_eq(credentials.user.as_bytes(),
+        expected_user.as_bytes());
+    let password_ok = constant_time_eq(credentials.password.as_bytes(),
+        expected_password.as_bytes());
+    if !(user_ok && password_ok) {
+        return unauthorized(realm, "Wrong user name or password.");
+    }
+    return Response::text(200,
+        &format!("Authenticated as {}.", credentials.user));
+}
+
+/// `/basic-auth` checks the credentials against the htpasswd file named by
+/// TESTCGI_HTPASSWD.
+pub fn htpasswd_route() -> Response {
+    let realm = "testcgi";
+    let path = match config::get("TESTCGI_HTPASSWD") {
+        Some(path) => path,
+        None => {
+            return Response::text(500,
+                "/basic-auth needs TESTCGI_HTPASSWD to name an htpasswd file.");
+        }
+    };
+    let htpasswd = match Htpasswd::load(Path::new(&path)) {
+        Ok(htpasswd) => htpasswd,
+        Err(e) => {
+            return Response::text(500,
+                &format!("Cannot read the htpasswd file: {e}"));
+        }
+    };
+    let header = match authorization_header() {
+        Some(header) => header,
+        None => return unauthorized(realm, &missing_header_hint()),
+    };
+    let credentials = match parse_authorization(&header) {
+        Some(credentials) => credentials,
+        None => {
+            return unauthorized(realm,
+                "The Authorization header is not valid Basic credentials.");
+        }
+    };
+    if !htpasswd.verify(&credentials.user, &credentials.password) {
+        return unauthorized(realm, "Wrong user name or password.");
+    }
+    return Response::text(200,
+        &format!("Authenticated as {}.", credentials.user));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_basic_credentials() {
+        let credentials = parse_authorization("Basic dXNlcjpwYTpzcw==")
+            .unwrap();
+        assert_eq!(credentials.user, "user");
+        assert_eq!(credentials.password, "pa:ss");
+        assert!(parse_authorization("Bearer dXNlcjpwYXNz").is_none());
+        assert!(parse_authorization("Basic dXNlcg==").is_none());
+        assert!(parse_authorization("Basic !!!").is_none());
+        assert!(parse_authorization("Basic /w==").is_none());
+    }
+
+    #[test]
+    fn challenges_with_a_quoted_realm() {
+        assert_eq!(challenge("a \"b\""),
+            "Basic realm=\"a \\\"b\\\"\", charset=\"UTF-8\"");
+        let response = unauthorized("r", "no");
+        assert_eq!(response.status, 401);
+        assert!(response.headers.iter()
+            .any(|(n, _)| n == "WWW-Authenticate"));
+    }
+}
@@ -0,0 +1,309 @@
+// src/body.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Looks at a request body without trusting it to be text: sizes, hashes,
+//! line endings, a binary check and a hex dump.
+
+use std::fmt;
+use std::fmt::Write;
+
+use crate::config;
+use crate::crc32::crc32;
+use crate::hex;
+use crate::html;
+use crate::sha256::sha256;
+
+/// Bytes per page of hex dump, 256 lines of 16.
+pub const DUMP_PAGE_SIZE: usize = 4096;
+/// The dump stops after this many pages so a large upload does not make a
+/// page of hundreds of megabytes.
+pub const MAX_DUMP_PAGES: usize = 16;
+/// The query parameter that picks the first page the dump shows, counted
+/// from 0, for bodies longer than MAX_DUMP_PAGES pages.
+pub const PAGE_PARAMETER: &str = "body_page";
+/// How far into the body to look for a NUL byte, as git does.
+const BINARY_SNIFF_SIZE: usize = 8000;
+/// Where body.view ends up: auto, text or hex.
+pub const VIEW_VARIABLE: &str = "TESTCGI_BODY_VIEW";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LineEndings {
+    pub crlf: usize,
+    pub lf: usize,
+    /// A CR not followed by LF, as old Mac OS wrote.
+    pub cr: usize,
+}
+
+impl LineEndings {
+    pub fn count(data: &[u8]) -> LineEndings {
+        let mut endings = LineEndings::default();
+        let mut i = 0;
+        while i < data.len() {
+            match data[i] {
+                b'\r' if data.get(i + 1) == Some(&b'\n') => {
+                    endings.crlf += 1;
+                    i += 1;
+                }
+                b'\r' => endings.cr += 1,
+                b'\n' => endings.lf += 1,
+                _ => {}
+            }
+            i += 1;
+        }
+        return endings;
+    }
+
+    /// Whether more than one kind of line ending was used.
+    pub fn is_mixed(&self) -> bool {
+        return [self.crlf, self.lf, self.cr].iter().filter(|n| **n > 0)
+            .count() > 1;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Binary {
+    NulByte,
+    ControlCharacters,
+}
+
+impl fmt::Display for Binary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Binary::NulByte => write!(f, "it contains a NUL byte"),
+            Binary::ControlCharacters => {
+                write!(f, "more than one byte in ten is a control character")
+            }
+        };
+    }
+}
+
+/// How the diagnostic page shows the body.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum View {
+    /// A hex dump if binary_reason finds a reason, text otherwise.
+    Auto,
+    Text,
+    Hex,
+}
+
+impl View {
+    /// Every name `parse` takes, in any case, for the body.view setting.
+    pub const NAMES: [&str; 3] = ["auto", "text", "hex"];
+
+    pub fn parse(input: &str) -> Option<View> {
+        let input = input.trim();
+        return [View::Auto, View::Text, View::Hex].into_iter()
+            .zip(View::NAMES)
+            .find(|(_, name)| input.eq_ignore_ascii_case(name))
+            .map(|(view, _)| view);
+    }
+}
+
+/// The configured view, Auto unless body.view says otherwise.
+pub fn view() -> View {
+    return config::get(VIEW_VARIABLE)
+        .and_then(|v| View::parse(&v))
+        .unwrap_or(View::Auto);
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Inspection {
+    /// Bytes actually read.
+    pub size: usize,
+    /// What CONTENT_LENGTH promised, if it was a number.
+    pub content_length: Option<u64>,
+    /// Why the body looks binary, or None if it looks like text.
+    pub binary: Option<Binary>,
+    /// Lower case hex.
+    pub sha256: String,
+    pub crc32: u32,
+    pub line_endings: LineEndings,
+    /// Bytes that are not part of a valid UTF-8 sequence.
+    pub invalid_utf8: usize,
+}
+
+impl Inspection {
+    pub fn new(data: &[u8], content_length: Option<u64>) -> Inspection {
+        return Inspection {
+            size: data.len(),
+            content_length,
+            binary: binary_reason(data),
+            sha256: hex::encode(&sha256(data)),
+            crc32: crc32(data),
+            line_endings: LineEndings::count(data),
+            invalid_utf8: data.utf8_chunks().map(|c| c.invalid().len()).sum(),
+        };
+    }
+
+    /// Whether fewer or more bytes arrived than CONTENT_LENGTH said.
+    pub fn length_mismatch(&self) -> bool {
+        return self.content_length.is_some_and(|n| n != self.size as u64);
+    }
+}
+
+/// Why `data` should be dumped in hex rather than shown as text, if it
+/// should. Text in an unknown 8-bit character set is still text, so
+/// invalid UTF-8 alone does not count.
+pub fn binary_reason(data: &[u8]) -> Option<Binary> {
+    let start = &data[..data.len().min(BINARY_SNIFF_SIZE)];
+    if start.contains(&0) {
+        return Some(Binary::NulByte);
+    }
+    // Tab, LF, FF, CR and ESC all turn up in ordinary text.
+    let controls = data.iter()
+        .filter(|b| **b < 0x20 && !matches!(b, b'\t' | b'\n' | 0x0c | b'\r'
+            | 0x1b))
+        .count();
+    if controls * 10 > data.len() {
+        return Some(Binary::ControlCharacters);
+    }
+    return None;
+}
+
+/// The number of DUMP_PAGE_SIZE pages `size` bytes fill.
+pub fn page_count(size: usize) -> usize {
+    return size.div_ceil(DUMP_PAGE_SIZE);
+}
+
+/// One page of `data` in the layout of `hexdump -C`: offset, sixteen bytes
+/// in two groups of eight, then the printable ASCII with dots for the
+/// rest. Offsets are counted from the start of the body. Plain text, so it
+/// needs escaping to go in a page.
+pub fn hex_dump(data: &[u8], page: usize) -> String {
+    let start = (page * DUMP_PAGE_SIZE).min(data.len());
+    let end = (start + DUMP_PAGE_SIZE).min(data.len());
+    let mut output = String::new();
+    for (i, line) in data[start..end].chunks(16).enumerate() {
+        _ = write!(output, "{:08x}  ", start + i * 16);
+        for column in 0..16 {
+            match line.get(column) {
+                Some(byte) => _ = write!(output, "{byte:02x} "),
+                None => output.push_str("   "),
+            }
+            if column == 7 {
+                output.push(' ');
+            }
+        }
+        output.push_str(" |");
+        for byte in line {
+            output.push(if byte.is_ascii_graphic() || *byte == b' ' {
+                *byte as char
+            } else {
+                '.'
+            });
+        }
+        output.push_str("|\n");
+    }
+    return output;
+}
+
+/// `data` as text, escaped for HTML, with nothing lost: control characters
+/// other than tab and LF become the matching Control Pictures symbol, so a
+/// CR shows as ␍, and bytes that are not UTF-8 are marked as `\xNN`.
+pub fn text_html(data: &[u8]) -> String {
+    let mut output = String::with_capacity(data.len());
+    for chunk in data.utf8_chunks() {
+        let mut chars = chunk.valid().chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\t' | '\n' => output.push(c),
+                '\0'..='\x1f' | '\x7f' => {
+                    let picture = if c == '\x7f' {
+                        '\u{2421}'
+                    } else {
+                        char::from_u32(0x2400 + c as u32).unwrap_or('?')
+                    };
+                    _ = write!(output, "<abbr title=\"U+{:04X}\">{picture}\
+                        </abbr>", c as u32);
+                    // Keep the line break a lone CR stood for.
+                    if c == '\r' && chars.peek() != Some(&'\n') {
+                        output.push('\n');
+                    }
+                }
+                _ => output.push_str(&html::escape(c.encode_utf8(&mut [0; 4]))),
+            }
+        }
+        for byte in chunk.invalid() {
+            _ = write!(output,
+                "<mark title=\"not UTF-8\">\\x{byte:02x}</mark>");
+        }
+    }
+    return output;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_line_endings() {
+        let endings = LineEndings::count(b"a\r\nb\nc\rd\r\n\r");
+        assert_eq!(endings, LineEndings { crlf: 2, lf: 1, cr: 2 });
+        assert!(endings.is_mixed());
+        assert!(!LineEndings::count(b"a\nb\n").is_mixed());
+    }
+
+    #[test]
+    fn tells_binary_from_text() {
+        assert_eq!(binary_reason(b"plain\ttext\r\n\x1b[0m"), None);
+        assert_eq!(binary_reason(b"caf\xe9"), None);
+        assert_eq!(binary_reason(b"a\0b"), Some(Binary::NulByte));
+        assert_eq!(binary_reason(b"\x01\x02abc"),
+            Some(Binary::ControlCharacters));
+        assert_eq!(binary_reason(b""), None);
+    }
+
+    #[test]
+    fn parses_views() {
+        assert_eq!(View::parse(" HEX "), Some(View::Hex));
+        assert_eq!(View::parse("auto"), Some(View::Auto));
+        assert_eq!(View::parse("text"), Some(View::Text));
+        assert_eq!(View::parse("wide"), None);
+    }
+
+    #[test]
+    fn inspects_bodies() {
+        let inspection = Inspection::new(b"abc\xff", Some(5));
+        assert_eq!(inspection.size, 4);
+        assert_eq!(inspection.invalid_utf8, 1);
+        assert!(inspection.length_mismatch());
+        assert!(!Inspection::new(b"", None).length_mismatch());
+        assert_eq!(Inspection::new(b"abc", None).sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+
+    #[test]
+    fn dumps_hex() {
+        assert_eq!(hex_dump(b"0123456789abcdef\x00A", 0),
+            "00000000  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66  \
+            |0123456789abcdef|\n\
+            00000010  00 41                                             \
+            |.A|\n");
+        assert_eq!(page_count(0), 0);
+        assert_eq!(page_count(DUMP_PAGE_SIZE + 1), 2);
+        let data = vec![b'x'; DUMP_PAGE_SIZE + 1];
+        assert!(hex_dump(&data, 1)
+            .starts_with(&format!("{DUMP_PAGE_SIZE:08x}")));
+        assert_eq!(hex_dump(&data, 5), "");
+    }
+
+    #[test]
+    fn shows_text_losslessly() {
+        assert_eq!(text_html(b"<a>\r\nb\rc\xff"),
+            "&lt;a&gt;<abbr title=\"U+000D\">\u{240d}</abbr>\nb\
+            <abbr title=\"U+000D\">\u{240d}</abbr>\nc\
+            <mark title=\"not UTF-8\">\\xff</mark>");
+    }
+}
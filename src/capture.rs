@@ -0,0 +1,605 @@
+// src/capture.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Capturing requests to a file and replaying them later.
+//!
+//! With capture.dir set every request is written out as a JSON file holding
+//! the environment, the command line arguments, the request body and the
+//! response that was sent. `testcgi replay <file>` runs a CGI program again
+//! with exactly that environment and body and shows how the new response
+//! differs from the old one.
+//!
+//! Capture files hold every header the client sent, passwords and cookies
+//! included, so they are only readable by their owner.
+//!
+//! Arguments and variable values that are not UTF-8 are written as
+//! `{"base64": "..."}` objects so a replay passes the very same bytes.
+//! Variable names are JSON member names and are kept as UTF-8, lossily.
+
+use std::env;
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::fs::DirBuilder;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::SystemTime;
+
+use crate::base64;
+use crate::config;
+use crate::csrf;
+use crate::http_date::format_rfc3339;
+use crate::json;
+use crate::json::Value;
+use crate::request_id;
+use crate::response::Response;
+
+pub const DIR_VARIABLE: &str = "TESTCGI_CAPTURE_DIR";
+
+/// Set by `replay` in the program's environment so the replayed request is
+/// not captured again.
+pub const REPLAY_VARIABLE: &str = "TESTCGI_REPLAY";
+
+/// Bumped if the file layout ever changes. Version 2 added base64 objects
+/// for values that are not UTF-8; version 1 files are still read.
+pub const FORMAT_VERSION: i64 = 2;
+
+/// A response as it went out: the Status field turned back into a number
+/// and every other header field in order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recorded {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Recorded {
+    pub fn from_response(response: &Response) -> Recorded {
+        return Recorded {
+            status: response.status,
+            headers: response.headers.clone(),
+            body: response.body.clone(),
+        };
+    }
+
+    /// Splits the output of a CGI program into header fields and body. A
+    /// missing Status field means 200, or 302 with a Location (RFC 3875
+    /// section 6.3.3).
+    pub fn parse(output: &[u8]) -> Result<Recorded, String> {
+        let (end, skip) = match find(output, b"\r\n\r\n") {
+            Some(index) => (index, 4),
+            None => match find(output, b"\n\n") {
+                Some(index) => (index, 2),
+                None => {
+                    return Err(String::from(
+                        "the output has no blank line after the header"));
+                }
+            },
+        };
+        let head = String::from_utf8_lossy(&output[..end]);
+        let mut status: Option<u16> = None;
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for line in head.lines() {
+            let line = line.trim_end_matches('\r');
+            let Some((name, value)) = line.split_once(':') else {
+                return Err(format!("{line:?} is not a header field"));
+            };
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("Status") {
+                let code = value.split_whitespace().next().unwrap_or_default();
+                status = match code.parse() {
+                    Ok(code) => Some(code),
+                    Err(_) => return Err(format!("bad Status {value:?}")),
+                };
+            } else {
+                headers.push((String::from(name), String::from(value)));
+            }
+        }
+        let status = status.unwrap_or_else(|| {
+            if headers.iter().any(|(n, _)| n.eq_ignore_ascii_case("Location")) {
+                302
+            } else {
+                200
+            }
+        });
+        return Ok(Recorded {
+            status,
+            headers,
+            body: output[end + skip..].to_vec(),
+        });
+    }
+
+    fn to_json(&self) -> Value {
+        let headers = self.headers.iter()
+            .map(|(n, v)| Value::Array(vec![string(n), string(v)]))
+            .collect();
+        return Value::Object(vec![
+            (String::from("status"), Value::Number(self.status as f64)),
+            (String::from("headers"), Value::Array(headers)),
+            (String::from("body"), string(&base64::encode(&self.body))),
+        ]);
+    }
+
+    fn from_json(value: &Value) -> Result<Recorded, String> {
+        let status = value.get("status").and_then(|v| v.as_i64())
+            .and_then(|v| u16::try_from(v).ok())
+            .ok_or("the response has no status")?;
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for pair in value.get("headers").and_then(|v| v.as_array())
+            .ok_or("the response has no headers")? {
+            match pair.as_array() {
+                Some([Value::String(n), Value::String(v)]) => {
+                    headers.push((n.clone(), v.clone()));
+                }
+                _ => return Err(String::from("a header is not a name/value \
+                    pair")),
+            }
+        }
+        let body = decode_body(value.get("body"))?;
+        return Ok(Recorded { status, headers, body });
+    }
+
+    /// The response without the header fields that are bound to differ from
+    /// one run to the next: X-Request-Id, new for every request that does
+    /// not bring its own, and the CSRF cookie, whose token is random.
+    pub fn comparable(&self) -> Recorded {
+        let prefix = format!("{}=", csrf::COOKIE_NAME);
+        let headers = self.headers.iter()
+            .filter(|(n, v)| {
+                let per_run = n.eq_ignore_ascii_case("X-Request-Id")
+                    || (n.eq_ignore_ascii_case("Set-Cookie")
+                        && v.starts_with(&prefix));
+                return !per_run;
+            })
+            .cloned()
+            .collect();
+        return Recorded { headers, ..self.clone() };
+    }
+
+    /// The response as lines of text for `diff_lines`. HTML is broken up
+    /// between tags because testcgi writes its pages on a single line.
+    pub fn lines(&self) -> Vec<String> {
+        let mut output: Vec<String> = vec![format!("Status: {}", self.status)];
+        for (name, value) in &self.headers {
+            output.push(format!("{name}: {value}"));
+        }
+        output.push(String::new());
+        let html = self.headers.iter().any(|(n, v)| {
+            n.eq_ignore_ascii_case("Content-Type") && v.contains("html")
+        });
+        let body = String::from_utf8_lossy(&self.body);
+        for line in body.lines() {
+            if html {
+                output.extend(line.replace("><", ">\n<").lines()
+                    .map(String::from));
+            } else {
+                output.push(String::from(line));
+            }
+        }
+        return output;
+    }
+}
+
+/// One captured request.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Capture {
+    pub time: String,
+    pub request_id: String,
+    pub argv: Vec<OsString>,
+    pub environment: Vec<(String, OsString)>,
+    /// False if the body was left for the route to read, as `/slow-body`
+    /// does, in which case `body` is empty.
+    pub body_captured: bool,
+    pub body: Vec<u8>,
+    /// None if the response was streamed out instead of built first.
+    pub response: Option<Recorded>,
+}
+
+impl Capture {
+    pub fn from_env(body: Option<&[u8]>, response: Option<&Response>)
+        -> Capture
+    {
+        return Capture {
+            time: format_rfc3339(SystemTime::now()),
+            request_id: request_id::current().id.clone(),
+            argv: env::args_os().collect(),
+            environment: env::vars_os()
+                .map(|(n, v)| (n.to_string_lossy().into_owned(), v))
+                .collect(),
+            body_captured: body.is_some(),
+            body: body.unwrap_or_default().to_vec(),
+            response: response.map(Recorded::from_response),
+        };
+    }
+
+    pub fn to_json(&self) -> String {
+        let environment = self.environment.iter()
+            .map(|(n, v)| (n.clone(), os_string(v)))
+            .collect();
+        let value = Value::Object(vec![
+            (String::from("version"), Value::Number(FORMAT_VERSION as f64)),
+            (String::from("time"), string(&self.time)),
+            (String::from("request_id"), string(&self.request_id)),
+            (String::from("argv"),
+                Value::Array(self.argv.iter().map(|a| os_string(a)).collect())),
+            (String::from("environment"), Value::Object(environment)),
+            (String::from("body_captured"), Value::Bool(self.body_captured)),
+            (String::from("body"), string(&base64::encode(&self.body))),
+            (String::from("response"), match &self.response {
+                Some(response) => response.to_json(),
+                None => Value::Null,
+            }),
+        ]);
+        let mut output = value.pretty();
+        output.push('\n');
+        return output;
+    }
+
+    pub fn from_json(input: &str) -> Result<Capture, String> {
+        let value = json::parse(input).map_err(|e| e.to_string())?;
+        match value.get("version").and_then(|v| v.as_i64()) {
+            Some(1 | FORMAT_VERSION) => {}
+            Some(other) => return Err(format!("version {other} captures are \
+                not supported")),
+            None => return Err(String::from("this is not a capture file")),
+        }
+        let text = |name: &str| -> Result<String, String> {
+            return value.get(name).and_then(|v| v.as_str()).map(String::from)
+                .ok_or(format!("{name} is missing"));
+        };
+        let mut argv: Vec<OsString> = Vec::new();
+        for argument in value.get("argv").and_then(|v| v.as_array())
+            .ok_or("argv is missing")? {
+            argv.push(from_os_string(argument)
+                .ok_or("an argument is not a string")?);
+        }
+        let mut environment: Vec<(String, OsString)> = Vec::new();
+        for (name, v) in value.get("environment").and_then(|v| v.as_object())
+            .ok_or("environment is missing")? {
+            let v = from_os_string(v)
+                .ok_or(format!("{name} is not a string"))?;
+            environment.push((name.clone(), v));
+        }
+        let response = match value.get("response") {
+            None | Some(Value::Null) => None,
+            Some(response) => Some(Recorded::from_json(response)?),
+        };
+        return Ok(Capture {
+            time: text("time")?,
+            request_id: text("request_id")?,
+            argv,
+            environment,
+            body_captured: value.get("body_captured")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(true),
+            body: decode_body(value.get("body"))?,
+            response,
+        });
+    }
+
+    /// A name that sorts by time and cannot clash with another request's:
+    /// `20250102T030405.678Z-<request id>.json`.
+    pub fn file_name(&self) -> String {
+        let time: String = self.time.chars()
+            .filter(|c| *c != '-' && *c != ':')
+            .collect();
+        let id: String = self.request_id.chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+            .take(64)
+            .collect();
+        return format!("{time}-{id}.json");
+    }
+
+    /// Writes the capture into `directory`, creating it if need be. An
+    /// existing file is never overwritten.
+    pub fn write(&self, directory: &Path) -> io::Result<PathBuf> {
+        DirBuilder::new().recursive(true).mode(0o700).create(directory)?;
+        let path = directory.join(self.file_name());
+        let mut file = OpenOptions::new().write(true).create_new(true)
+            .mode(0o600).open(&path)?;
+        file.write_all(self.to_json().as_bytes())?;
+        return Ok(path);
+    }
+}
+
+/// True if requests should be captured. A request being replayed never is.
+pub fn enabled() -> bool {
+    return config::get(DIR_VARIABLE).is_some()
+        && env::var_os(REPLAY_VARIABLE).is_none();
+}
+
+static BODY: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Keeps the request body for the capture written by `finish`. Only needed
+/// when the body is read before the response is ready.
+pub fn begin(body: &[u8]) {
+    _ = BODY.set(body.to_vec());
+}
+
+/// Writes the capture for this request, if capturing is on.
+pub fn finish(response: Option<&Response>) -> io::Result<Option<PathBuf>> {
+    if !enabled() {
+        return Ok(None);
+    }
+    let Some(directory) = config::get(DIR_VARIABLE) else {
+        return Ok(None);
+    };
+    let body = BODY.get().map(|b| b.as_slice());
+    let capture = Capture::from_env(body, response);
+    return Ok(Some(capture.write(Path::new(&directory))?));
+}
+
+/// Runs `program` with the captured arguments, environment and body and
+/// returns what it wrote. Anything it writes to stderr is passed through.
+pub fn replay(capture: &Capture, program: &Path) -> io::Result<Recorded> {
+    let mut command = Command::new(program);
+    if let Some(name) = capture.argv.first() {
+        // The captured argv[0], so a program that shows it shows the same.
+        command.arg0(name);
+    }
+    let mut child = command
+        .args(capture.argv.iter().skip(1))
+        .env_clear()
+        .envs(capture.environment.iter().map(|(n, v)| (n, v)))
+        .env(REPLAY_VARIABLE, "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+    // Fed from another thread so a program that writes before it has read
+    // everything cannot deadlock us.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let body = capture.body.clone();
+    let feeder = thread::spawn(move || {
+        // A program that does not read its body closes the pipe early, and
+        // that is its business.
+        _ = stdin.write_all(&body);
+    });
+    let output = child.wait_with_output()?;
+    _ = feeder.join();
+    return Recorded::parse(&output.stdout)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Above this many line pairs the longest common subsequence table gets too
+/// big, and the texts are shown as wholly replaced instead.
+const MAX_DIFF_CELLS: usize = 16 * 1024 * 1024;
+
+/// A line by line diff of `old` and `new` built from their longest common
+/// subsequence.
+pub fn diff_lines<'a>(old: &'a [String], new: &'a [String]) -> Vec<Change<'a>> {
+    // Lines that match at either end need no table.
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b).count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    let mut output: Vec<Change> = Vec::with_capacity(old.len() + new.len());
+    output.extend(old[..prefix].iter().map(|l| Change::Same(l)));
+    if (a.len() + 1) * (b.len() + 1) > MAX_DIFF_CELLS {
+        output.extend(a.iter().map(|l| Change::Removed(l)));
+        output.extend(b.iter().map(|l| Change::Added(l)));
+    } else {
+        // lengths[i][j] is the LCS length of a[i..] and b[j..].
+        let width = b.len() + 1;
+        let mut lengths = vec![0u32; (a.len() + 1) * width];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lengths[i * width + j] = if a[i] == b[j] {
+                    lengths[(i + 1) * width + j + 1] + 1
+                } else {
+                    lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() && j < b.len() {
+            if a[i] == b[j] {
+                output.push(Change::Same(&a[i]));
+                i += 1;
+                j += 1;
+            } else if lengths[(i + 1) * width + j]
+                >= lengths[i * width + j + 1] {
+                output.push(Change::Removed(&a[i]));
+                i += 1;
+            } else {
+                output.push(Change::Added(&b[j]));
+                j += 1;
+            }
+        }
+        output.extend(a[i..].iter().map(|l| Change::Removed(l)));
+        output.extend(b[j..].iter().map(|l| Change::Added(l)));
+    }
+    output.extend(old[old.len() - suffix..].iter().map(|l| Change::Same(l)));
+    return output;
+}
+
+/// Unchanged lines kept around each change by `format_diff`.
+const CONTEXT: usize = 3;
+
+/// Renders a diff with `-` and `+` in front of changed lines and long runs
+/// of unchanged lines cut down to a count.
+pub fn format_diff(changes: &[Change]) -> String {
+    let mut output = String::new();
+    let changed: Vec<bool> = changes.iter()
+        .map(|c| !matches!(c, Change::Same(_)))
+        .collect();
+    let near_change = |index: usize| {
+        let start = index.saturating_sub(CONTEXT);
+        let end = (index + CONTEXT + 1).min(changes.len());
+        return changed[start..end].iter().any(|c| *c);
+    };
+    let mut skipped = 0;
+    for (index, change) in changes.iter().enumerate() {
+        if let Change::Same(_) = change && !near_change(index) {
+            skipped += 1;
+            continue;
+        }
+        if skipped > 0 {
+            output.push_str(&format!("@@ {skipped} unchanged lines @@\n"));
+            skipped = 0;
+        }
+        match change {
+            Change::Same(line) => output.push_str(&format!(" {line}\n")),
+            Change::Removed(line) => output.push_str(&format!("-{line}\n")),
+            Change::Added(line) => output.push_str(&format!("+{line}\n")),
+        }
+    }
+    if skipped > 0 {
+        output.push_str(&format!("@@ {skipped} unchanged lines @@\n"));
+    }
+    return output;
+}
+
+fn string(input: &str) -> Value {
+    return Value::String(String::from(input));
+}
+
+/// A string, or a `{"base64": "..."}` object if `input` is not UTF-8.
+fn os_string(input: &OsStr) -> Value {
+    return match input.to_str() {
+        Some(text) => string(text),
+        None => Value::Object(vec![(String::from("base64"),
+            string(&base64::encode(input.as_bytes())))]),
+    };
+}
+
+fn from_os_string(value: &Value) -> Option<OsString> {
+    if let Some(text) = value.as_str() {
+        return Some(OsString::from(text));
+    }
+    let bytes = base64::decode(value.get("base64")?.as_str()?)?;
+    return Some(OsString::from_vec(bytes));
+}
+
+fn decode_body(value: Option<&Value>) -> Result<Vec<u8>, String> {
+    let text = value.and_then(|v| v.as_str()).ok_or("the body is missing")?;
+    return base64::decode(text).ok_or(String::from("the body is not base64"));
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    return haystack.windows(needle.len()).position(|w| w == needle);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        return text.lines().map(String::from).collect();
+    }
+
+    #[test]
+    fn parses_cgi_output() {
+        let recorded = Recorded::parse(b"Status: 404 Not Found\r\n\
+            Content-Type: text/plain\r\n\r\nno\r\n\r\n").unwrap();
+        assert_eq!(recorded.status, 404);
+        assert_eq!(recorded.headers, [(String::from("Content-Type"),
+            String::from("text/plain"))]);
+        assert_eq!(recorded.body, b"no\r\n\r\n");
+        assert_eq!(Recorded::parse(b"Location: /x\n\n").unwrap().status,
+            302);
+        assert_eq!(Recorded::parse(b"A: b\n\nbody").unwrap().status, 200);
+        assert!(Recorded::parse(b"A: b\r\n").is_err());
+        assert!(Recorded::parse(b"nonsense\n\n").is_err());
+        assert!(Recorded::parse(b"Status: soon\n\n").is_err());
+    }
+
+    #[test]
+    fn round_trips_captures() {
+        let capture = Capture {
+            time: String::from("2025-01-02T03:04:05.678Z"),
+            request_id: String::from("id/../x"),
+            argv: vec![OsString::from("testcgi")],
+            environment: vec![
+                (String::from("A"), OsString::from("ü")),
+                (String::from("B"), OsString::from_vec(vec![0xff, 0])),
+            ],
+            body_captured: true,
+            body: vec![0, 1, 2],
+            response: Some(Recorded {
+                status: 201,
+                headers: vec![(String::from("X"), String::from("y"))],
+                body: b"ok".to_vec(),
+            }),
+        };
+        assert_eq!(Capture::from_json(&capture.to_json()).unwrap(), capture);
+        assert_eq!(capture.file_name(), "20250102T030405.678Z-idx.json");
+        assert!(Capture::from_json("{\"version\": 3}").is_err());
+        assert!(Capture::from_json("{}").is_err());
+    }
+
+    #[test]
+    fn ignores_per_run_headers() {
+        let recorded = Recorded {
+            status: 200,
+            headers: vec![
+                (String::from("X-Request-Id"), String::from("1")),
+                (String::from("Set-Cookie"),
+                    format!("{}=abc", csrf::COOKIE_NAME)),
+                (String::from("Set-Cookie"), String::from("a=b")),
+            ],
+            body: Vec::new(),
+        };
+        assert_eq!(recorded.comparable().headers,
+            [(String::from("Set-Cookie"), String::from("a=b"))]);
+    }
+
+    #[test]
+    fn diffs_lines() {
+        let old = lines("a\nb\nc\nd");
+        let new = lines("a\nx\nc\nd\ne");
+        assert_eq!(diff_lines(&old, &new), [
+            Change::Same("a"),
+            Change::Removed("b"),
+            Change::Added("x"),
+            Change::Same("c"),
+            Change::Same("d"),
+            Change::Added("e"),
+        ]);
+        assert!(diff_lines(&old, &old).iter()
+            .all(|c| matches!(c, Change::Same(_))));
+    }
+
+    #[test]
+    fn formats_diffs_with_context() {
+        let old = lines("1\n2\n3\n4\n5\n6\n7\n8\n9");
+        let mut new = old.clone();
+        new[8] = String::from("nine");
+        assert_eq!(format_diff(&diff_lines(&old, &new)),
+            "@@ 5 unchanged lines @@\n 6\n 7\n 8\n-9\n+nine\n");
+        assert_eq!(format_diff(&diff_lines(&old, &old)),
+            "@@ 9 unchanged lines @@\n");
+    }
+}
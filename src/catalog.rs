@@ -0,0 +1,428 @@
+// src/catalog.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Message catalogs for translating the diagnostic page.
+//!
+//! A catalog is a small text file:
+//!
+//! ```text
+//! # Comments start with # or ;
+//! language = de-AT
+//! name = Österreichisches Deutsch
+//! fallback = de
+//!
+//! [messages]
+//! heading.cookies = Kekse
+//! config.read_from = Gelesen aus {0}
+//! ```
+//!
+//! `language` is required. A message missing from a catalog is looked up in
+//! its `fallback`, then in the catalogs for shorter forms of its language
+//! (`de` for `de-AT`), and finally in English. `{0}`, `{1}` and so on are
+//! filled in with arguments.
+//!
+//! English, German and French are built in. Catalogs in the directory named
+//! by TESTCGI_CATALOGS are added, or merged over a built-in catalog for the
+//! same language.
+//!
+//! Library types whose messages can end up on the page, such as form
+//! problems and JSON errors, have a `describe` method taking a Translator.
+//! Their Display output is the same message from the built-in English
+//! catalog, so the English text lives in one place.
+
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::accept_language;
+use crate::config;
+use crate::config::Error;
+
+pub const DIRECTORY_VARIABLE: &str = "TESTCGI_CATALOGS";
+
+/// The language every chain ends in and every message has to exist in.
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+const BUILT_IN: &[(&str, &str)] = &[
+    ("en.catalog", include_str!("catalogs/en.catalog")),
+    ("de.catalog", include_str!("catalogs/de.catalog")),
+    ("fr.catalog", include_str!("catalogs/fr.catalog")),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Catalog {
+    /// A language tag such as `de` or `pt-BR`.
+    pub language: String,
+    /// The name of the language in that language.
+    pub name: String,
+    pub fallback: Option<String>,
+    pub messages: Vec<(String, String)>,
+}
+
+impl Catalog {
+    /// `source` names the file in error messages.
+    pub fn parse(source: &str, input: &str) -> Result<Catalog, Error> {
+        let error = |line: usize, message: String| {
+            return Error { source: String::from(source), line, message };
+        };
+        let mut language: Option<String> = None;
+        let mut name: Option<String> = None;
+        let mut fallback: Option<String> = None;
+        let mut messages: Vec<(String, String)> = Vec::new();
+        let mut in_messages = false;
+        for (index, line) in input.lines().enumerate() {
+            let number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';')
+            {
+                continue;
+            }
+            if line.starts_with('[') {
+                if line != "[messages]" {
+                    return Err(error(number, format!("unknown section \
+                        {line}; the only one is [messages]")));
+                }
+                in_messages = true;
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error(number, String::from("expected key = value")));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            if key.is_empty() {
+                return Err(error(number, String::from("the key is empty")));
+            }
+            if in_messages {
+                if messages.iter().any(|(k, _)| k == key) {
+                    return Err(error(number, format!("{key} is translated \
+                        twice")));
+                }
+                messages.push((String::from(key), unescape(value)));
+                continue;
+            }
+            match key {
+                "language" => {
+                    if value == "*"
+                        || !accept_language::is_valid_range(value) {
+                        return Err(error(number, format!("{value:?} is not a \
+                            language tag")));
+                    }
+                    language = Some(String::from(value));
+                }
+                "name" => name = Some(String::from(value)),
+                "fallback" => fallback = Some(String::from(value)),
+                _ => {
+                    return Err(error(number, format!("unknown key {key}; \
+                        messages go in the [messages] section")));
+                }
+            }
+        }
+        let Some(language) = language else {
+            return Err(error(0, String::from("the catalog has no language")));
+        };
+        return Ok(Catalog {
+            name: name.unwrap_or_else(|| language.clone()),
+            language,
+            fallback,
+            messages,
+        });
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        return self.messages.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str());
+    }
+}
+
+/// `\n`, `\t` and `\\` in a message. Anything else after a backslash is
+/// kept as it is.
+fn unescape(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            output.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => output.push('\n'),
+            Some('t') => output.push('\t'),
+            Some('\\') => output.push('\\'),
+            Some(other) => {
+                output.push('\\');
+                output.push(other);
+            }
+            None => output.push('\\'),
+        }
+    }
+    return output;
+}
+
+/// Every catalog available.
+pub struct Catalogs {
+    list: Vec<Catalog>,
+}
+
+impl Catalogs {
+    pub fn built_in() -> Catalogs {
+        let list = BUILT_IN.iter()
+            .map(|(name, text)| match Catalog::parse(name, text) {
+                Ok(catalog) => catalog,
+                Err(e) => panic!("built-in catalog: {e}"),
+            })
+            .collect();
+        return Catalogs { list };
+    }
+
+    /// Adds a catalog. One for a language that is already here has its
+    /// messages laid over the existing ones.
+    pub fn add(&mut self, catalog: Catalog) {
+        let Some(existing) = self.list.iter_mut()
+            .find(|c| c.language.eq_ignore_ascii_case(&catalog.language)) else {
+            self.list.push(catalog);
+            return;
+        };
+        existing.name = catalog.name;
+        if catalog.fallback.is_some() {
+            existing.fallback = catalog.fallback;
+        }
+        for (key, value) in catalog.messages {
+            existing.messages.retain(|(k, _)| *k != key);
+            existing.messages.push((key, value));
+        }
+    }
+
+    /// Adds every `*.catalog` file in `directory`.
+    pub fn load_directory(&mut self, directory: &Path) -> Result<(), Error> {
+        let error = |message: String| {
+            return Error {
+                source: directory.display().to_string(),
+                line: 0,
+                message,
+            };
+        };
+        let entries = fs::read_dir(directory)
+            .map_err(|e| error(e.to_string()))?;
+        let mut paths: Vec<_> = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|e| error(e.to_string()))?.path();
+            if path.extension().is_some_and(|e| e == "catalog") {
+                paths.push(path);
+            }
+        }
+        // Sorted so that two files for one language merge the same way
+        // every time.
+        paths.sort();
+        for path in paths {
+            let source = path.display().to_string();
+            let text = fs::read_to_string(&path).map_err(|e| Error {
+                source: source.clone(),
+                line: 0,
+                message: e.to_string(),
+            })?;
+            self.add(Catalog::parse(&source, &text)?);
+        }
+        return Ok(());
+    }
+
+    pub fn languages(&self) -> Vec<&str> {
+        return self.list.iter().map(|c| c.language.as_str()).collect();
+    }
+
+    pub fn find(&self, language: &str) -> Option<&Catalog> {
+        return self.list.iter()
+            .find(|c| c.language.eq_ignore_ascii_case(language));
+    }
+
+    /// A translator for `language` and everything it falls back on.
+    pub fn translator(&self, language: &str) -> Translator<'_> {
+        let mut chain: Vec<&Catalog> = Vec::new();
+        let mut next: Option<String> = Some(String::from(language));
+        while let Some(language) = next.take() {
+            let Some(catalog) = self.find(&language) else {
+                break;
+            };
+            // A fallback that loops back on itself ends the chain.
+            if chain.iter().any(|c| std::ptr::eq(*c, catalog)) {
+                break;
+            }
+            chain.push(catalog);
+            next = catalog.fallback.clone();
+        }
+        let mut prefix = language;
+        while let Some(index) = prefix.rfind('-') {
+            prefix = &prefix[..index];
+            if let Some(catalog) = self.find(prefix)
+                && !chain.iter().any(|c| std::ptr::eq(*c, catalog)) {
+                chain.push(catalog);
+            }
+        }
+        if let Some(catalog) = self.find(DEFAULT_LANGUAGE)
+            && !chain.iter().any(|c| std::ptr::eq(*c, catalog)) {
+            chain.push(catalog);
+        }
+        return Translator { chain };
+    }
+
+    /// Picks the catalog the client likes best by RFC 4647 lookup, or
+    /// English if it likes none of them.
+    pub fn negotiate(&self, accept_language: &str) -> Translator<'_> {
+        let ranges = accept_language::parse(accept_language);
+        let languages = self.languages();
+        let language = accept_language::lookup(&ranges, &languages)
+            .unwrap_or(DEFAULT_LANGUAGE);
+        return self.translator(language);
+    }
+}
+
+/// Looks messages up along a fallback chain.
+pub struct Translator<'a> {
+    chain: Vec<&'a Catalog>,
+}
+
+impl<'a> Translator<'a> {
+    /// The language that was picked, for Content-Language and `lang`.
+    pub fn language(&self) -> &'a str {
+        return match self.chain.first() {
+            Some(catalog) => &catalog.language,
+            None => DEFAULT_LANGUAGE,
+        };
+    }
+
+    /// The message for `key`. A key no catalog has is returned as it is, so
+    /// a missing translation shows up on the page instead of hiding.
+    pub fn get(&self, key: &'a str) -> &'a str {
+        for catalog in &self.chain {
+            if let Some(message) = catalog.get(key) {
+                return message;
+            }
+        }
+        return key;
+    }
+
+    /// The message for `key` with `{0}`, `{1}` and so on replaced by
+    /// `arguments`. Arguments are not searched for placeholders themselves,
+    /// so a client's `{1}` stays as it was sent.
+    pub fn format(&self, key: &'a str, arguments: &[&str]) -> String {
+        let message = self.get(key);
+        let mut output = String::with_capacity(message.len());
+        let mut rest = message;
+        while let Some(start) = rest.find('{') {
+            output.push_str(&rest[..start]);
+            rest = &rest[start + 1..];
+            if let Some(end) = rest.find('}')
+                && let Ok(index) = rest[..end].parse::<usize>()
+                && let Some(argument) = arguments.get(index) {
+                output.push_str(argument);
+                rest = &rest[end + 1..];
+            } else {
+                output.push('{');
+            }
+        }
+        output.push_str(rest);
+        return output;
+    }
+}
+
+/// The built-in English messages, for logs, the command line and Display.
+pub fn english() -> Translator<'static> {
+    static BUILT_IN_CATALOGS: OnceLock<Catalogs> = OnceLock::new();
+    return BUILT_IN_CATALOGS.get_or_init(Catalogs::built_in)
+        .translator(DEFAULT_LANGUAGE);
+}
+
+/// The built-in catalogs plus any from TESTCGI_CATALOGS.
+pub fn from_env() -> Result<Catalogs, Error> {
+    let mut catalogs = Catalogs::built_in();
+    if let Some(directory) = config::get(DIRECTORY_VARIABLE) {
+        catalogs.load_directory(Path::new(&directory))?;
+    }
+    return Ok(catalogs);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_catalogs_are_complete() {
+        let catalogs = Catalogs::built_in();
+        let english = catalogs.find(DEFAULT_LANGUAGE).unwrap();
+        for catalog in &catalogs.list {
+            let missing: Vec<&str> = english.messages.iter()
+                .map(|(key, _)| key.as_str())
+                .filter(|key| catalog.get(key).is_none())
+                .collect();
+            assert!(missing.is_empty(), "{} lacks {missing:?}",
+                catalog.language);
+            let extra: Vec<&str> = catalog.messages.iter()
+                .map(|(key, _)| key.as_str())
+                .filter(|key| english.get(key).is_none())
+                .collect();
+            assert!(extra.is_empty(), "{} has unknown {extra:?}",
+                catalog.language);
+        }
+    }
+
+    #[test]
+    fn parses_catalogs() {
+        let catalog = Catalog::parse("t", "# comment\nlanguage = de-AT\n\
+            fallback = de\n\n[messages]\n; also a comment\n\
+            a = x\\ty\\\\\\q\n").unwrap();
+        assert_eq!(catalog.name, "de-AT");
+        assert_eq!(catalog.fallback.as_deref(), Some("de"));
+        assert_eq!(catalog.get("a"), Some("x\ty\\\\q"));
+
+        for bad in ["[messages]\na = 1", "language = *", "language = de\n\
+            [other]", "language = de\nnoise", "language = de\n[messages]\n\
+            a = 1\na = 2", "language = de\ncolour = red"] {
+            assert!(Catalog::parse("t", bad).is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn falls_back_along_the_chain() {
+        let mut catalogs = Catalogs::built_in();
+        catalogs.add(Catalog::parse("t", "language = de-AT\n[messages]\n\
+            heading.environment = Umgebung\n").unwrap());
+        let tr = catalogs.translator("de-AT");
+        assert_eq!(tr.language(), "de-AT");
+        assert_eq!(tr.get("heading.environment"), "Umgebung");
+        assert_eq!(tr.get("heading.arguments"), "Kommandozeilenargumente");
+        assert_eq!(tr.get("no.such.key"), "no.such.key");
+        assert_eq!(catalogs.translator("it").language(), "en");
+
+        let looped = Catalog::parse("t", "language = xx\nfallback = xx\n")
+            .unwrap();
+        catalogs.add(looped);
+        assert_eq!(catalogs.translator("xx").get("heading.environment"),
+            "Environment Variables");
+    }
+
+    #[test]
+    fn negotiates_and_formats() {
+        let catalogs = Catalogs::built_in();
+        assert_eq!(catalogs.negotiate("de-AT, fr;q=0.5").language(), "de");
+        assert_eq!(catalogs.negotiate("it").language(), "en");
+        let tr = catalogs.translator("de");
+        assert_eq!(tr.format("config.read_from", &["/etc/t"]),
+            "Gelesen aus /etc/t");
+        assert_eq!(tr.format("quirk.path_info_repeats.detail",
+            &["{1}", "b"]), "PATH_INFO {1} wiederholt SCRIPT_NAME b.");
+        assert_eq!(tr.format("quirk.path_info_repeats.detail", &["a"]),
+            "PATH_INFO a wiederholt SCRIPT_NAME {1}.");
+    }
+}
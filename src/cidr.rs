@@ -0,0 +1,184 @@
+// src/cidr.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! IPv4 and IPv6 address ranges in CIDR notation, as used for the trusted
+//! proxy and access lists.
+
+use std::fmt;
+use std::net::IpAddr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Parses `192.0.2.0/24`, `2001:db8::/32` or a bare address, which is a
+    /// range of one. Bits past the prefix must be zero so that a typo like
+    /// `10.1.2.3/8` is caught instead of quietly meaning `10.0.0.0/8`.
+    pub fn parse(input: &str) -> Result<Cidr, String> {
+        let input = input.trim();
+        let (address, prefix) = match input.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (input, None),
+        };
+        let network: IpAddr = match address.parse() {
+            Ok(ip) => ip,
+            Err(_) => return Err(format!("{input:?} is not an IP address.")),
+        };
+        let width = bit_width(&network);
+        // parse alone would also take a leading +.
+        let is_number = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+        let prefix: u8 = match prefix {
+            None => width,
+            Some(prefix) => match prefix.parse() {
+                Ok(n) if n <= width && is_number(prefix) => n,
+                _ => {
+                    return Err(format!(
+                        "{input:?} needs a prefix length from 0 to {width}."));
+                }
+            },
+        };
+        let cidr = Cidr { network, prefix };
+        if to_bits(&network) & !cidr.mask() != 0 {
+            return Err(format!("{input:?} has bits set past the /{prefix} \
+                prefix."));
+        }
+        return Ok(cidr);
+    }
+
+    pub fn network(&self) -> IpAddr {
+        return self.network;
+    }
+
+    pub fn prefix(&self) -> u8 {
+        return self.prefix;
+    }
+
+    /// IPv4-mapped IPv6 addresses such as `::ffff:192.0.2.1` match IPv4
+    /// ranges, since that is how a dual-stack socket reports IPv4 clients.
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+                Some(v4) => IpAddr::V4(v4),
+                None => *ip,
+            },
+            IpAddr::V4(_) => *ip,
+        };
+        if ip.is_ipv4() != self.network.is_ipv4() {
+            return false;
+        }
+        return to_bits(&ip) & self.mask() == to_bits(&self.network);
+    }
+
+    fn mask(&self) -> u128 {
+        let width = bit_width(&self.network);
+        if self.prefix == 0 {
+            return 0;
+        }
+        let ones = u128::MAX << (128 - self.prefix as u32);
+        // IPv4 addresses sit in the low 32 bits.
+        return ones >> (128 - width as u32);
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}/{}", self.network, self.prefix);
+    }
+}
+
+/// Parses a list of ranges separated by commas or whitespace.
+pub fn parse_list(input: &str) -> Result<Vec<Cidr>, String> {
+    let mut output: Vec<Cidr> = Vec::new();
+    for item in input.split([',', ' ', '\t', '\n']) {
+        if item.is_empty() {
+            continue;
+        }
+        output.push(Cidr::parse(item)?);
+    }
+    return Ok(output);
+}
+
+/// The first range in `list` that holds `ip`.
+pub fn find<'a>(list: &'a [Cidr], ip: &IpAddr) -> Option<&'a Cidr> {
+    return list.iter().find(|cidr| cidr.contains(ip));
+}
+
+fn bit_width(ip: &IpAddr) -> u8 {
+    return match ip {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    };
+}
+
+fn to_bits(ip: &IpAddr) -> u128 {
+    return match ip {
+        IpAddr::V4(v4) => u32::from(*v4) as u128,
+        IpAddr::V6(v6) => u128::from(*v6),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(input: &str) -> IpAddr {
+        return input.parse().unwrap();
+    }
+
+    #[test]
+    fn parse() {
+        let cidr = Cidr::parse(" 10.0.0.0/8 ").unwrap();
+        assert_eq!(cidr.network(), ip("10.0.0.0"));
+        assert_eq!(cidr.prefix(), 8);
+        assert_eq!(Cidr::parse("192.0.2.1").unwrap().prefix(), 32);
+        assert_eq!(Cidr::parse("2001:db8::/32").unwrap().to_string(),
+            "2001:db8::/32");
+        assert_eq!(Cidr::parse("::/0").unwrap().prefix(), 0);
+        for bad in ["10.0.0.0/+8", "10.0.0.0/33", "10.0.0.0/", "10.0.0.0/8/8",
+            "10.1.2.3/8", "2001:db8::1/32", "example.com", "10.0.0.0/ 8"] {
+            assert!(Cidr::parse(bad).is_err(), "{bad} was accepted");
+        }
+    }
+
+    #[test]
+    fn contains() {
+        let private = Cidr::parse("10.0.0.0/8").unwrap();
+        assert!(private.contains(&ip("10.255.0.1")));
+        assert!(!private.contains(&ip("11.0.0.1")));
+        assert!(private.contains(&ip("::ffff:10.0.0.1")));
+        assert!(!private.contains(&ip("::10.0.0.1")));
+        let documentation = Cidr::parse("2001:db8::/32").unwrap();
+        assert!(documentation.contains(&ip("2001:db8:1::5")));
+        assert!(!documentation.contains(&ip("2001:db9::")));
+        assert!(!documentation.contains(&ip("10.0.0.1")));
+        assert!(Cidr::parse("0.0.0.0/0").unwrap().contains(&ip("1.2.3.4")));
+        let host = Cidr::parse("192.0.2.7").unwrap();
+        assert!(host.contains(&ip("192.0.2.7")));
+        assert!(!host.contains(&ip("192.0.2.6")));
+    }
+
+    #[test]
+    fn lists() {
+        let list = parse_list("127.0.0.1, ::1\n10.0.0.0/8").unwrap();
+        assert_eq!(list.len(), 3);
+        assert_eq!(find(&list, &ip("10.1.1.1")), Some(&list[2]));
+        assert_eq!(find(&list, &ip("192.0.2.1")), None);
+        assert!(parse_list("127.0.0.1, nonsense").is_err());
+        assert_eq!(parse_list(" , ").unwrap(), Vec::new());
+    }
+}
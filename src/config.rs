@@ -0,0 +1,555 @@
+// src/config.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! The optional testcgi configuration file.
+//!
+//! Every setting can also be given as a TESTCGI_* environment variable, for
+//! instance with Apache's SetEnv, and the environment wins over the file.
+//! The file is named by TESTCGI_CONFIG, or else is `testcgi.conf` next to
+//! the executable if there is one there.
+//!
+//! ```text
+//! # Lines starting with # or ; are comments.
+//! secret = "correct horse battery staple"
+//!
+//! [access]
+//! allow = 127.0.0.1, 10.0.0.0/8
+//! allow = fd00::/8          # lists can be split over several lines
+//!
+//! [limits]
+//! max_body = 1M
+//! ```
+//!
+//! A key inside `[section]` is known as `section.key`. Values run to the end
+//! of the line or to a `#` with a space before it. Put them in double quotes
+//! to keep a `#` or leading and trailing spaces. Quoted values understand
+//! `\"`, `\\`, `\n` and `\t`.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::access_log;
+use crate::body;
+use crate::cidr;
+use crate::forwarded::ProxyHeader;
+use crate::log;
+use crate::redact;
+use crate::route::ROUTE_NAMES;
+
+pub const CONFIG_VARIABLE: &str = "TESTCGI_CONFIG";
+pub const DEFAULT_FILE_NAME: &str = "testcgi.conf";
+
+/// The largest request body read when limits.max_body is not set.
+pub const DEFAULT_MAX_BODY: u64 = 16 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Text,
+    Path,
+    Bool,
+    Integer,
+    /// A byte count with an optional K, M or G suffix.
+    Size,
+    /// One of the listed names, in any case.
+    Choice(&'static [&'static str]),
+    Cidrs,
+    Patterns,
+    Routes,
+}
+
+pub struct Setting {
+    /// The name in the file, `section.key` or just `key`.
+    pub key: &'static str,
+    pub variable: &'static str,
+    pub kind: Kind,
+    /// Lists may be given more than once in the file and are joined.
+    pub list: bool,
+}
+
+const fn setting(key: &'static str, variable: &'static str, kind: Kind)
+    -> Setting
+{
+    let list = matches!(kind, Kind::Cidrs | Kind::Patterns | Kind::Routes);
+    return Setting { key, variable, kind, list };
+}
+
+pub const SETTINGS: &[Setting] = &[
+    setting("secret", "TESTCGI_SECRET", Kind::Text),
+    setting("routes.enabled", "TESTCGI_ROUTES", Kind::Routes),
+    setting("limits.max_body", "TESTCGI_MAX_BODY", Kind::Size),
+    setting("body.view", "TESTCGI_BODY_VIEW", Kind::Choice(&body::View::NAMES)),
+    setting("session.dir", "TESTCGI_SESSION_DIR", Kind::Path),
+    setting("basic_auth.htpasswd", "TESTCGI_HTPASSWD", Kind::Path),
+    setting("digest_auth.nonce_dir", "TESTCGI_NONCE_DIR", Kind::Path),
+    setting("jwt.keys", "TESTCGI_JWT_KEYS", Kind::Path),
+    setting("jwt.audience", "TESTCGI_JWT_AUDIENCE", Kind::Text),
+    setting("jwt.issuer", "TESTCGI_JWT_ISSUER", Kind::Text),
+    setting("jwt.leeway", "TESTCGI_JWT_LEEWAY", Kind::Integer),
+    setting("csrf.mode", "TESTCGI_CSRF",
+        Kind::Choice(&["off", "double-submit", "hmac"])),
+    setting("csrf.check_origin", "TESTCGI_CSRF_ORIGIN", Kind::Bool),
+    setting("proxy.trusted", "TESTCGI_TRUSTED_PROXIES", Kind::Cidrs),
+    setting("proxy.header", "TESTCGI_PROXY_HEADER",
+        Kind::Choice(ProxyHeader::NAMES)),
+    setting("access.allow", "TESTCGI_ALLOW", Kind::Cidrs),
+    setting("access.deny", "TESTCGI_DENY", Kind::Cidrs),
+    setting("redact.patterns", "TESTCGI_REDACT", Kind::Patterns),
+    setting("redact.defaults", "TESTCGI_REDACT_DEFAULTS", Kind::Bool),
+    setting("redact.hash", "TESTCGI_REDACT_HASH", Kind::Bool),
+    setting("log.level", "TESTCGI_LOG_LEVEL", Kind::Choice(&log::Level::NAMES)),
+    setting("log.format", "TESTCGI_LOG_FORMAT",
+        Kind::Choice(&log::Format::NAMES)),
+    setting("access_log.file", "TESTCGI_ACCESS_LOG", Kind::Path),
+    setting("access_log.format", "TESTCGI_ACCESS_LOG_FORMAT",
+        Kind::Choice(&access_log::Format::NAMES)),
+    setting("capture.dir", "TESTCGI_CAPTURE_DIR", Kind::Path),
+    setting("baseline.file", "TESTCGI_BASELINE", Kind::Path),
+    setting("language.catalogs", "TESTCGI_CATALOGS", Kind::Path),
+    setting("json.max_depth", "TESTCGI_JSON_MAX_DEPTH", Kind::Integer),
+    setting("json.max_size", "TESTCGI_JSON_MAX_SIZE", Kind::Size),
+];
+
+pub fn find_setting(key: &str) -> Option<&'static Setting> {
+    return SETTINGS.iter().find(|s| s.key == key);
+}
+
+/// A problem with the configuration. `line` is zero for problems with an
+/// environment variable or the file as a whole.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    pub source: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            return write!(f, "{}: {}", self.source, self.message);
+        }
+        return write!(f, "{}:{}: {}", self.source, self.line, self.message);
+    }
+}
+
+pub struct Value {
+    pub setting: &'static Setting,
+    pub value: String,
+    pub line: usize,
+}
+
+pub struct Config {
+    pub path: Option<PathBuf>,
+    pub values: Vec<Value>,
+}
+
+impl Config {
+    pub fn empty() -> Config {
+        return Config { path: None, values: Vec::new() };
+    }
+
+    pub fn load(path: &Path) -> Result<Config, Error> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => {
+                return Err(Error {
+                    source: path.display().to_string(),
+                    line: 0,
+                    message: format!("cannot read the file: {e}"),
+                });
+            }
+        };
+        let mut config = Config::parse(&text, &path.display().to_string())?;
+        config.path = Some(PathBuf::from(path));
+        return Ok(config);
+    }
+
+    /// Parses the file and checks every value. `source` names the file in
+    /// error messages.
+    pub fn parse(text: &str, source: &str) -> Result<Config, Error> {
+        let mut config = Config::empty();
+        let mut section = String::new();
+        for (index, raw) in text.lines().enumerate() {
+            let line = index + 1;
+            let error = |message: String| Error {
+                source: String::from(source),
+                line,
+                message,
+            };
+            let trimmed = raw.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#')
+                || trimmed.starts_with(';') {
+                continue;
+            }
+            if let Some(rest) = trimmed.strip_prefix('[') {
+                let Some(name) = rest.strip_suffix(']') else {
+                    return Err(error(String::from(
+                        "a section header has to end with ]")));
+                };
+                let name = name.trim();
+                if !name.chars().all(is_key_char) {
+                    return Err(error(format!("{name:?} is not a valid \
+                        section name")));
+                }
+                if !name.is_empty()
+                    && !SETTINGS.iter().any(|s| s.key.starts_with(
+                        &format!("{name}."))) {
+                    return Err(error(format!("there is no [{name}] section; \
+                        the sections are {}", section_names().join(", "))));
+                }
+                section = String::from(name);
+                continue;
+            }
+            let Some((name, value)) = trimmed.split_once('=') else {
+                return Err(error(format!("expected key = value, not \
+                    {trimmed:?}")));
+            };
+            let name = name.trim();
+            if name.is_empty() || !name.chars().all(is_key_char) {
+                return Err(error(format!("{name:?} is not a valid key")));
+            }
+            let key = if section.is_empty() {
+                String::from(name)
+            } else {
+                format!("{section}.{name}")
+            };
+            let Some(setting) = find_setting(&key) else {
+                return Err(error(unknown_key_message(&key, &section)));
+            };
+            let value = match parse_value(value.trim()) {
+                Ok(value) => value,
+                Err(message) => return Err(error(message)),
+            };
+            if let Err(message) = validate(setting, &value) {
+                return Err(error(format!("{key}: {message}")));
+            }
+            if let Some(previous) = config.values.iter_mut()
+                .find(|v| v.setting.key == setting.key) {
+                if !setting.list {
+                    return Err(error(format!("{key} was already set on line \
+                        {}", previous.line)));
+                }
+                previous.value.push_str(", ");
+                previous.value.push_str(&value);
+                continue;
+            }
+            config.values.push(Value { setting, value, line });
+        }
+        return Ok(config);
+    }
+
+    pub fn get(&self, variable: &str) -> Option<&Value> {
+        return self.values.iter().find(|v| v.setting.variable == variable);
+    }
+}
+
+fn is_key_char(c: char) -> bool {
+    return c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.';
+}
+
+fn section_names() -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for setting in SETTINGS {
+        if let Some((section, _)) = setting.key.split_once('.') {
+            let name = format!("[{section}]");
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    return names;
+}
+
+fn unknown_key_message(key: &str, section: &str) -> String {
+    let prefix = if section.is_empty() {
+        String::new()
+    } else {
+        format!("{section}.")
+    };
+    let known: Vec<&str> = SETTINGS
+        .iter()
+        .filter_map(|s| s.key.strip_prefix(prefix.as_str()))
+        .filter(|k| !k.contains('.'))
+        .collect();
+    if known.is_empty() {
+        return format!("unknown setting {key}");
+    }
+    return format!("unknown setting {key}; expected one of {}",
+        known.join(", "));
+}
+
+/// The text of a value, with quotes and escapes dealt with.
+fn parse_value(input: &str) -> Result<String, String> {
+    let Some(rest) = input.strip_prefix('"') else {
+        // A # after whitespace starts a comment. Quote the value to keep it.
+        let value = match input.find(" #").or_else(|| input.find("\t#")) {
+            Some(index) => input[..index].trim_end(),
+            None => input,
+        };
+        return Ok(String::from(value));
+    };
+    let mut output = String::with_capacity(rest.len());
+    let mut chars = rest.chars();
+    loop {
+        match chars.next() {
+            None => return Err(String::from("the closing \" is missing")),
+            Some('"') => break,
+            Some('\\') => match chars.next() {
+                Some('"') => output.push('"'),
+                Some('\\') => output.push('\\'),
+                Some('n') => output.push('\n'),
+                Some('t') => output.push('\t'),
+                Some(c) => return Err(format!("unknown escape \\{c}")),
+                None => return Err(String::from("the closing \" is missing")),
+            },
+            Some(c) => output.push(c),
+        }
+    }
+    let tail = chars.as_str().trim();
+    if !tail.is_empty() && !tail.starts_with('#') && !tail.starts_with(';') {
+        return Err(format!("unexpected {tail:?} after the closing quote"));
+    }
+    return Ok(output);
+}
+
+/// on/off, yes/no, true/false or 1/0.
+pub fn parse_bool(input: &str) -> Option<bool> {
+    return match input.trim().to_ascii_lowercase().as_str() {
+        "on" | "yes" | "true" | "1" => Some(true),
+        "off" | "no" | "false" | "0" => Some(false),
+        _ => None,
+    };
+}
+
+/// A byte count such as `4096`, `64K`, `1M` or `2G`.
+pub fn parse_size(input: &str) -> Option<u64> {
+    let input = input.trim();
+    let (digits, multiplier) = match input.char_indices().last() {
+        Some((index, 'k' | 'K')) => (&input[..index], 1024),
+        Some((index, 'm' | 'M')) => (&input[..index], 1024 * 1024),
+        Some((index, 'g' | 'G')) => (&input[..index], 1024 * 1024 * 1024),
+        _ => (input, 1),
+    };
+    let number: u64 = digits.trim().parse().ok()?;
+    return number.checked_mul(multiplier);
+}
+
+/// Checks a value for a setting, wherever it came from.
+pub fn validate(setting: &Setting, value: &str) -> Result<(), String> {
+    return match setting.kind {
+        Kind::Text => Ok(()),
+        Kind::Path => {
+            if value.trim().is_empty() {
+                return Err(String::from("the path is empty"));
+            }
+            Ok(())
+        }
+        Kind::Bool => match parse_bool(value) {
+            Some(_) => Ok(()),
+            None => Err(format!("{value:?} should be on or off")),
+        },
+        Kind::Integer => match value.trim().parse::<i64>() {
+            Ok(_) => Ok(()),
+            Err(_) => Err(format!("{value:?} is not a whole number")),
+        },
+        Kind::Size => match parse_size(value) {
+            Some(_) => Ok(()),
+            None => Err(format!("{value:?} is not a size like 512K or 1M")),
+        },
+        Kind::Choice(choices) => {
+            if choices.iter().any(|c| c.eq_ignore_ascii_case(value.trim())) {
+                return Ok(());
+            }
+            Err(format!("{value:?} should be one of {}", choices.join(", ")))
+        }
+        Kind::Cidrs => cidr::parse_list(value).map(|_| ()),
+        Kind::Patterns => {
+            for item in redact::split_patterns(value)? {
+                redact::Pattern::parse(&item)?;
+            }
+            Ok(())
+        }
+        Kind::Routes => {
+            for name in value.split([',', ' ', '\t']).filter(|n| !n.is_empty())
+            {
+                if !ROUTE_NAMES.contains(&name) {
+                    return Err(format!("there is no {name:?} route; the \
+                        routes are {}", ROUTE_NAMES.join(", ")));
+                }
+            }
+            Ok(())
+        }
+    };
+}
+
+static LOADED: OnceLock<Config> = OnceLock::new();
+
+/// The file named by TESTCGI_CONFIG, or `testcgi.conf` beside the
+/// executable when that exists.
+pub fn find_file() -> Option<PathBuf> {
+    if let Some(path) = env::var_os(CONFIG_VARIABLE)
+        && !path.is_empty() {
+        return Some(PathBuf::from(path));
+    }
+    let beside = env::current_exe().ok()?.with_file_name(DEFAULT_FILE_NAME);
+    if beside.is_file() {
+        return Some(beside);
+    }
+    return None;
+}
+
+/// Loads the file and checks every setting, including the ones that come
+/// from the environment. Call it once, at startup, before anything reads a
+/// setting. Library users that never call it get the environment alone.
+pub fn init() -> Result<&'static Config, Error> {
+    if let Some(config) = LOADED.get() {
+        return Ok(config);
+    }
+    let config = match find_file() {
+        Some(path) => Config::load(&path)?,
+        None => Config::empty(),
+    };
+    for setting in SETTINGS {
+        if let Some(value) = env_value(setting.variable)
+            && let Err(message) = validate(setting, &value) {
+            return Err(Error {
+                source: String::from(setting.variable),
+                line: 0,
+                message,
+            });
+        }
+    }
+    return Ok(LOADED.get_or_init(|| config));
+}
+
+/// The loaded file, if `init` found one.
+pub fn loaded() -> Option<&'static Config> {
+    return LOADED.get();
+}
+
+fn env_value(variable: &str) -> Option<String> {
+    return env::var(variable).ok().filter(|v| !v.is_empty());
+}
+
+/// The value of a setting by its environment variable name: the variable if
+/// it is set and not empty, otherwise the configuration file.
+pub fn get(variable: &str) -> Option<String> {
+    if let Some(value) = env_value(variable) {
+        return Some(value);
+    }
+    return LOADED.get()
+        .and_then(|config| config.get(variable))
+        .map(|v| v.value.clone());
+}
+
+/// Where the value of a setting came from, for the diagnostic page.
+pub fn origin(variable: &str) -> Option<String> {
+    if env_value(variable).is_some() {
+        return Some(String::from("environment"));
+    }
+    let config = LOADED.get()?;
+    let value = config.get(variable)?;
+    let path = config.path.as_deref().unwrap_or(Path::new("?"));
+    return Some(format!("{}:{}", path.display(), value.line));
+}
+
+/// The largest request body to accept.
+pub fn max_body() -> u64 {
+    return get("TESTCGI_MAX_BODY")
+        .and_then(|v| parse_size(&v))
+        .unwrap_or(DEFAULT_MAX_BODY);
+}
+
+/// Whether the named route is switched on. All of them are unless
+/// routes.enabled lists some.
+pub fn route_enabled(name: &str) -> bool {
+    return match get("TESTCGI_ROUTES") {
+        Some(list) => list.split([',', ' ', '\t']).any(|n| n == name),
+        None => true,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value<'a>(config: &'a Config, variable: &str) -> &'a str {
+        return &config.get(variable).unwrap().value;
+    }
+
+    #[test]
+    fn parses_a_file() {
+        let config = Config::parse("secret = \"a \\\"b\\\" #c\"\n[limits]\n\
+            max_body = 64K # comment\n[proxy]\ntrusted = 10.0.0.0/8\n\
+            trusted = ::1\n", "t").unwrap();
+        assert_eq!(value(&config, "TESTCGI_SECRET"), "a \"b\" #c");
+        assert_eq!(value(&config, "TESTCGI_MAX_BODY"), "64K");
+        assert_eq!(value(&config, "TESTCGI_TRUSTED_PROXIES"),
+            "10.0.0.0/8, ::1");
+        assert_eq!(config.get("TESTCGI_SECRET").unwrap().line, 1);
+    }
+
+    #[test]
+    fn reports_bad_files() {
+        let line = |text: &str| Config::parse(text, "t").err().unwrap().line;
+        assert_eq!(line("[nope]"), 1);
+        assert_eq!(line("\n[limits"), 2);
+        assert_eq!(line("secret = 1\nsecret = 2"), 2);
+        assert_eq!(line("[limits]\nmax_body = lots"), 2);
+        assert_eq!(line("[body]\nview = wide"), 2);
+        assert_eq!(line("secret = \"open"), 1);
+        assert_eq!(line("secret = \"a\" b"), 1);
+        assert_eq!(line("secret = \"\\q\""), 1);
+        assert_eq!(line("just words"), 1);
+        let error = Config::parse("[proxy]\n[]\nsecret2 = x", "t")
+            .err().unwrap();
+        assert_eq!(error.line, 3);
+        assert!(error.message.starts_with("unknown setting secret2"));
+    }
+
+    #[test]
+    fn parses_values() {
+        assert_eq!(parse_bool(" Yes "), Some(true));
+        assert_eq!(parse_bool("0"), Some(false));
+        assert_eq!(parse_bool("maybe"), None);
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size("64k"), Some(65536));
+        assert_eq!(parse_size("2G"), Some(2 << 30));
+        assert_eq!(parse_size("K"), None);
+        assert_eq!(parse_size("99999999999G"), None);
+    }
+
+    #[test]
+    fn validates_every_kind() {
+        let check = |key: &str, value: &str| {
+            return validate(find_setting(key).unwrap(), value).is_ok();
+        };
+        assert!(check("session.dir", "/tmp"));
+        assert!(!check("session.dir", " "));
+        assert!(check("jwt.leeway", "-5"));
+        assert!(!check("jwt.leeway", "5s"));
+        assert!(check("log.level", "DEBUG"));
+        assert!(check("body.view", "hex"));
+        assert!(!check("body.view", "wide"));
+        assert!(!check("access.allow", "10.0.0.0/33"));
+        assert!(check("redact.patterns", "*KEY*, /^DB_/"));
+        assert!(!check("redact.patterns", "/(/"));
+        assert!(check("routes.enabled", "cookies, server"));
+        assert!(!check("routes.enabled", "nope"));
+    }
+}
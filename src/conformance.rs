@@ -0,0 +1,629 @@
+// src/conformance.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Checks the meta-variables the web server gave us against RFC 3875.
+//!
+//! Every check gets a verdict and the section of the RFC it comes from:
+//!
+//! - Fail: a MUST was broken or a value does not fit the grammar.
+//! - Warn: a SHOULD was broken, or something legal but likely to trip up
+//!   scripts.
+//! - Pass: all is as the RFC describes.
+
+use std::fmt;
+use std::net::IpAddr;
+
+use crate::catalog;
+use crate::catalog::Translator;
+use crate::html;
+
+pub const RFC_URL: &str = "https://www.rfc-editor.org/rfc/rfc3875";
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Verdict {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl Verdict {
+    pub fn describe(&self, tr: &Translator) -> String {
+        return String::from(match self {
+            Verdict::Pass => tr.get("conformance.pass"),
+            Verdict::Warn => tr.get("conformance.warn"),
+            Verdict::Fail => tr.get("conformance.fail"),
+        });
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}", self.describe(&catalog::english()));
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Check {
+    /// Usually the meta-variable the check is about.
+    pub subject: String,
+    /// The RFC 3875 section, e.g. `4.1.4`.
+    pub section: &'static str,
+    pub verdict: Verdict,
+    /// The catalog key of the message and the values that go in it.
+    pub message: &'static str,
+    pub arguments: Vec<String>,
+}
+
+impl Check {
+    pub fn describe(&self, tr: &Translator) -> String {
+        let arguments: Vec<&str> = self.arguments.iter()
+            .map(String::as_str)
+            .collect();
+        return tr.format(self.message, &arguments);
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}", self.describe(&catalog::english()));
+    }
+}
+
+/// The meta-variables RFC 3875 says the server MUST set.
+pub const REQUIRED: &[(&str, &str)] = &[
+    ("GATEWAY_INTERFACE", "4.1.4"),
+    ("QUERY_STRING", "4.1.7"),
+    ("REMOTE_ADDR", "4.1.8"),
+    ("REQUEST_METHOD", "4.1.12"),
+    ("SCRIPT_NAME", "4.1.13"),
+    ("SERVER_NAME", "4.1.14"),
+    ("SERVER_PORT", "4.1.15"),
+    ("SERVER_PROTOCOL", "4.1.16"),
+    ("SERVER_SOFTWARE", "4.1.17"),
+];
+
+/// Every meta-variable RFC 3875 section 4.1 defines, whether the server has
+/// to set it or not.
+pub const META_VARIABLES: &[(&str, &str)] = &[
+    ("AUTH_TYPE", "4.1.1"),
+    ("CONTENT_LENGTH", "4.1.2"),
+    ("CONTENT_TYPE", "4.1.3"),
+    ("GATEWAY_INTERFACE", "4.1.4"),
+    ("PATH_INFO", "4.1.5"),
+    ("PATH_TRANSLATED", "4.1.6"),
+    ("QUERY_STRING", "4.1.7"),
+    ("REMOTE_ADDR", "4.1.8"),
+    ("REMOTE_HOST", "4.1.9"),
+    ("REMOTE_IDENT", "4.1.10"),
+    ("REMOTE_USER", "4.1.11"),
+    ("REQUEST_METHOD", "4.1.12"),
+    ("SCRIPT_NAME", "4.1.13"),
+    ("SERVER_NAME", "4.1.14"),
+    ("SERVER_PORT", "4.1.15"),
+    ("SERVER_PROTOCOL", "4.1.16"),
+    ("SERVER_SOFTWARE", "4.1.17"),
+];
+
+struct Report {
+    checks: Vec<Check>,
+}
+
+impl Report {
+    fn add(&mut self, subject: &str, section: &'static str, verdict: Verdict,
+        message: &'static str, arguments: &[&str])
+    {
+        self.checks.push(Check {
+            subject: String::from(subject),
+            section,
+            verdict,
+            message,
+            arguments: arguments.iter().map(|a| String::from(*a)).collect(),
+        });
+    }
+}
+
+/// Runs every check over a set of meta-variables, normally `env::vars()`.
+pub fn check(variables: &[(String, String)]) -> Vec<Check> {
+    let get = |name: &str| variables.iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_str());
+    let mut report = Report { checks: Vec::new() };
+
+    for (name, section) in REQUIRED {
+        match get(name) {
+            Some(_) => report.add(name, section, Verdict::Pass,
+                "conformance.set", &[]),
+            None => report.add(name, section, Verdict::Fail,
+                "conformance.required", &[]),
+        }
+    }
+
+    if let Some(value) = get("GATEWAY_INTERFACE") {
+        check_gateway_interface(&mut report, value);
+    }
+    if let Some(value) = get("SERVER_PROTOCOL") {
+        check_server_protocol(&mut report, value);
+    }
+    if let Some(value) = get("REQUEST_METHOD") {
+        let quoted = format!("{value:?}");
+        if !value.is_empty() && value.chars().all(is_tchar) {
+            if value.chars().any(|c| c.is_ascii_lowercase()) {
+                report.add("REQUEST_METHOD", "4.1.12", Verdict::Warn,
+                    "conformance.method_case", &[&quoted]);
+            } else {
+                report.add("REQUEST_METHOD", "4.1.12", Verdict::Pass,
+                    "conformance.method", &[&quoted]);
+            }
+        } else {
+            report.add("REQUEST_METHOD", "4.1.12", Verdict::Fail,
+                "conformance.method_bad", &[&quoted]);
+        }
+    }
+    check_content(&mut report, get("CONTENT_LENGTH"), get("CONTENT_TYPE"),
+        get("REQUEST_METHOD"));
+    check_path(&mut report, get("PATH_INFO"), get("PATH_TRANSLATED"),
+        get("DOCUMENT_ROOT"));
+    if let Some(value) = get("SCRIPT_NAME") {
+        if value.is_empty() || value.starts_with('/') {
+            report.add("SCRIPT_NAME", "4.1.13", Verdict::Pass,
+                "conformance.script_name", &[]);
+        } else {
+            report.add("SCRIPT_NAME", "4.1.13", Verdict::Fail,
+                "conformance.script_name_bad", &[&format!("{value:?}")]);
+        }
+    }
+    if let Some(value) = get("QUERY_STRING") {
+        if value.chars().any(|c| c.is_ascii_whitespace() || c.is_control()) {
+            report.add("QUERY_STRING", "4.1.7", Verdict::Warn,
+                "conformance.query_whitespace", &[]);
+        } else {
+            report.add("QUERY_STRING", "4.1.7", Verdict::Pass,
+                "conformance.query", &[]);
+        }
+    }
+    if let Some(value) = get("REMOTE_ADDR") {
+        match value.parse::<IpAddr>() {
+            Ok(_) => report.add("REMOTE_ADDR", "4.1.8", Verdict::Pass,
+                "conformance.remote_addr", &[]),
+            Err(_) => report.add("REMOTE_ADDR", "4.1.8", Verdict::Fail,
+                "conformance.remote_addr_bad", &[&format!("{value:?}")]),
+        }
+    }
+    match get("REMOTE_HOST") {
+        Some(_) => report.add("REMOTE_HOST", "4.1.9", Verdict::Pass,
+            "conformance.set", &[]),
+        None => report.add("REMOTE_HOST", "4.1.9", Verdict::Warn,
+            "conformance.remote_host", &[]),
+    }
+    if let Some(auth_type) = get("AUTH_TYPE") {
+        let needs_user = auth_type.eq_ignore_ascii_case("basic")
+            || auth_type.eq_ignore_ascii_case("digest");
+        if needs_user && get("REMOTE_USER").is_none_or(|u| u.is_empty()) {
+            report.add("REMOTE_USER", "4.1.11", Verdict::Fail,
+                "conformance.remote_user", &[auth_type]);
+        } else {
+            report.add("AUTH_TYPE", "4.1.1", Verdict::Pass,
+                "conformance.value", &[&format!("{auth_type:?}")]);
+        }
+    }
+    if let Some(value) = get("SERVER_NAME") {
+        if is_server_name(value) {
+            report.add("SERVER_NAME", "4.1.14", Verdict::Pass,
+                "conformance.server_name", &[]);
+        } else {
+            report.add("SERVER_NAME", "4.1.14", Verdict::Fail,
+                "conformance.server_name_bad", &[&format!("{value:?}")]);
+        }
+    }
+    if let Some(value) = get("SERVER_PORT") {
+        if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+            report.add("SERVER_PORT", "4.1.15", Verdict::Pass,
+                "conformance.server_port", &[]);
+        } else {
+            report.add("SERVER_PORT", "4.1.15", Verdict::Fail,
+                "conformance.server_port_bad", &[&format!("{value:?}")]);
+        }
+    }
+    check_http_variables(&mut report, variables);
+    return report.checks;
+}
+
+fn check_gateway_interface(report: &mut Report, value: &str) {
+    let quoted = format!("{value:?}");
+    let version = match value.split_once('/') {
+        Some((name, version)) if name.eq_ignore_ascii_case("CGI") => version,
+        _ => {
+            report.add("GATEWAY_INTERFACE", "4.1.4", Verdict::Fail,
+                "conformance.gateway_bad", &[&quoted]);
+            return;
+        }
+    };
+    if !is_version(version) {
+        report.add("GATEWAY_INTERFACE", "4.1.4", Verdict::Fail,
+            "conformance.gateway_version", &[&quoted]);
+    } else if version != "1.1" {
+        report.add("GATEWAY_INTERFACE", "4.1.4", Verdict::Warn,
+            "conformance.gateway_other", &[&quoted]);
+    } else {
+        report.add("GATEWAY_INTERFACE", "4.1.4", Verdict::Pass,
+            "conformance.value", &[&quoted]);
+    }
+}
+
+fn check_server_protocol(report: &mut Report, value: &str) {
+    let quoted = format!("{value:?}");
+    if value.eq_ignore_ascii_case("INCLUDED") {
+        report.add("SERVER_PROTOCOL", "4.1.16", Verdict::Pass,
+            "conformance.included", &[]);
+        return;
+    }
+    let Some((name, version)) = value.split_once('/') else {
+        report.add("SERVER_PROTOCOL", "4.1.16", Verdict::Fail,
+            "conformance.protocol_bad", &[&quoted]);
+        return;
+    };
+    let good_name = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+    if !good_name {
+        report.add("SERVER_PROTOCOL", "4.1.16", Verdict::Fail,
+            "conformance.protocol_name", &[&format!("{name:?}")]);
+    } else if is_version(version) {
+        report.add("SERVER_PROTOCOL", "4.1.16", Verdict::Pass,
+            "conformance.value", &[&quoted]);
+    } else if !version.is_empty()
+        && version.bytes().all(|b| b.is_ascii_digit()) {
+        report.add("SERVER_PROTOCOL", "4.1.16", Verdict::Warn,
+            "conformance.protocol_minor",
+            &[&quoted, &format!("{name}/{version}.0")]);
+    } else {
+        report.add("SERVER_PROTOCOL", "4.1.16", Verdict::Fail,
+            "conformance.protocol_version", &[&format!("{version:?}")]);
+    }
+}
+
+fn check_content(report: &mut Report, length: Option<&str>,
+    content_type: Option<&str>, method: Option<&str>)
+{
+    let bodyless = matches!(method, Some("GET" | "HEAD" | "DELETE" | "OPTIONS"
+        | "TRACE"));
+    match length {
+        None if matches!(method, Some("POST" | "PUT" | "PATCH")) => {
+            report.add("CONTENT_LENGTH", "4.1.2", Verdict::Warn,
+                "conformance.length_missing", &[method.unwrap_or_default()]);
+        }
+        None => {
+            report.add("CONTENT_LENGTH", "4.1.2", Verdict::Pass,
+                "conformance.length_none", &[]);
+        }
+        Some(value) if value.is_empty()
+            || !value.bytes().all(|b| b.is_ascii_digit()) => {
+            report.add("CONTENT_LENGTH", "4.1.2", Verdict::Fail,
+                "conformance.length_bad", &[&format!("{value:?}")]);
+        }
+        Some(value) if bodyless => {
+            report.add("CONTENT_LENGTH", "4.1.2", Verdict::Warn,
+                "conformance.length_bodyless",
+                &[&format!("{value:?}"), method.unwrap_or_default()]);
+        }
+        Some(value) => {
+            report.add("CONTENT_LENGTH", "4.1.2", Verdict::Pass,
+                "conformance.length", &[value]);
+        }
+    }
+    match content_type {
+        Some(value) => {
+            let essence = value.split(';').next().unwrap_or_default().trim();
+            let valid = match essence.split_once('/') {
+                Some((a, b)) => !a.is_empty() && !b.is_empty()
+                    && a.chars().all(is_tchar) && b.chars().all(is_tchar),
+                None => false,
+            };
+            if valid {
+                report.add("CONTENT_TYPE", "4.1.3", Verdict::Pass,
+                    "conformance.value", &[&format!("{value:?}")]);
+            } else {
+                report.add("CONTENT_TYPE", "4.1.3", Verdict::Fail,
+                    "conformance.type_bad", &[&format!("{value:?}")]);
+            }
+        }
+        None if length.is_some_and(|l| l != "0") => {
+            report.add("CONTENT_TYPE", "4.1.3", Verdict::Warn,
+                "conformance.type_missing", &[]);
+        }
+        None => {}
+    }
+}
+
+fn check_path(report: &mut Report, path_info: Option<&str>,
+    path_translated: Option<&str>, document_root: Option<&str>)
+{
+    let path_info = path_info.unwrap_or_default();
+    let path_translated = path_translated.unwrap_or_default();
+    if !path_info.is_empty() && !path_info.starts_with('/') {
+        report.add("PATH_INFO", "4.1.5", Verdict::Fail,
+            "conformance.path_info_bad", &[&format!("{path_info:?}")]);
+    } else if path_info.contains("/../") || path_info.ends_with("/..") {
+        report.add("PATH_INFO", "4.1.5", Verdict::Warn,
+            "conformance.path_info_dots", &[]);
+    } else if path_info.is_empty() {
+        report.add("PATH_INFO", "4.1.5", Verdict::Pass,
+            "conformance.path_info_empty", &[]);
+    } else {
+        report.add("PATH_INFO", "4.1.5", Verdict::Pass,
+            "conformance.path_info", &[]);
+    }
+
+    if path_info.is_empty() {
+        if path_translated.is_empty() {
+            report.add("PATH_TRANSLATED", "4.1.6", Verdict::Pass,
+                "conformance.translated_none", &[]);
+        } else {
+            report.add("PATH_TRANSLATED", "4.1.6", Verdict::Fail,
+                "conformance.translated_set", &[]);
+        }
+        return;
+    }
+    if path_translated.is_empty() {
+        report.add("PATH_TRANSLATED", "4.1.6", Verdict::Warn,
+            "conformance.translated_missing", &[]);
+        return;
+    }
+    let quoted = format!("{path_translated:?}");
+    // The usual mapping, though the RFC leaves it up to the server, is the
+    // document root followed by PATH_INFO.
+    if let Some(root) = document_root
+        && !root.is_empty() {
+        let expected = format!("{}{path_info}", root.trim_end_matches('/'));
+        if path_translated == expected {
+            report.add("PATH_TRANSLATED", "4.1.6", Verdict::Pass,
+                "conformance.translated_root", &[]);
+        } else {
+            report.add("PATH_TRANSLATED", "4.1.6", Verdict::Warn,
+                "conformance.translated_not_root",
+                &[&quoted, &format!("{expected:?}")]);
+        }
+        return;
+    }
+    if path_translated.ends_with(path_info) {
+        report.add("PATH_TRANSLATED", "4.1.6", Verdict::Pass,
+            "conformance.translated_end", &[]);
+    } else {
+        report.add("PATH_TRANSLATED", "4.1.6", Verdict::Warn,
+            "conformance.translated_not_end", &[&quoted]);
+    }
+}
+
+fn check_http_variables(report: &mut Report, variables: &[(String, String)]) {
+    let mut count = 0;
+    for (name, _) in variables {
+        let Some(field) = name.strip_prefix("HTTP_") else {
+            continue;
+        };
+        count += 1;
+        if field.is_empty() || !field.bytes().all(|b| b.is_ascii_uppercase()
+            || b.is_ascii_digit() || b == b'_') {
+            report.add(name, "4.1.18", Verdict::Fail,
+                "conformance.header_name", &[]);
+            continue;
+        }
+        match field {
+            "AUTHORIZATION" | "PROXY_AUTHORIZATION" => {
+                report.add(name, "4.1.18", Verdict::Warn,
+                    "conformance.header_credentials", &[]);
+            }
+            "CONTENT_LENGTH" | "CONTENT_TYPE" => {
+                report.add(name, "4.1.18", Verdict::Warn,
+                    "conformance.header_duplicate", &[field]);
+            }
+            "PROXY" => {
+                // Not in the RFC, but CVE-2016-5385 and friends came from
+                // scripts treating this as the proxy to use.
+                report.add(name, "4.1.18", Verdict::Warn,
+                    "conformance.httpoxy", &[]);
+            }
+            _ => {}
+        }
+    }
+    if count > 0 {
+        report.add("HTTP_*", "4.1.18", Verdict::Pass,
+            "conformance.header_count", &[&count.to_string()]);
+    }
+}
+
+fn is_version(version: &str) -> bool {
+    return match version.split_once('.') {
+        Some((major, minor)) => !major.is_empty() && !minor.is_empty()
+            && major.bytes().all(|b| b.is_ascii_digit())
+            && minor.bytes().all(|b| b.is_ascii_digit()),
+        None => false,
+    };
+}
+
+fn is_tchar(c: char) -> bool {
+    return c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+}
+
+fn is_server_name(value: &str) -> bool {
+    if let Some(inner) = value.strip_prefix('[')
+        .and_then(|v| v.strip_suffix(']')) {
+        return inner.parse::<IpAddr>().is_ok();
+    }
+    if value.parse::<IpAddr>().is_ok() {
+        return true;
+    }
+    return !value.is_empty() && value.split('.').all(|label| !label.is_empty()
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'));
+}
+
+/// Counts of (pass, warn, fail).
+pub fn summary(checks: &[Check]) -> (usize, usize, usize) {
+    let count = |v: Verdict| checks.iter().filter(|c| c.verdict == v).count();
+    return (count(Verdict::Pass), count(Verdict::Warn), count(Verdict::Fail));
+}
+
+/// The report as an HTML page, worst verdicts first.
+pub fn report_html(checks: &[Check], tr: &Translator) -> String {
+    let mut sorted: Vec<&Check> = checks.iter().collect();
+    sorted.sort_by(|a, b| b.verdict.partial_cmp(&a.verdict).unwrap());
+    let (pass, warn, fail) = summary(checks);
+    let mut out = String::with_capacity(4096);
+    out.push_str(&format!("<!DOCTYPE html><html lang=\"{}\"><body>",
+        html::escape(tr.language())));
+    out.push_str(&format!("<h1>{}</h1>",
+        html::escape(tr.get("conformance.title"))));
+    out.push_str(&format!("<p>{}</p>", html::escape(&tr.format(
+        "conformance.summary",
+        &[&pass.to_string(), &warn.to_string(), &fail.to_string()]))));
+    out.push_str(&format!("<table><tr><th>{}</th><th>{}</th><th>{}</th>\
+        <th>{}</th></tr>", html::escape(tr.get("conformance.verdict")),
+        html::escape(tr.get("conformance.variable")),
+        html::escape(tr.get("conformance.section")),
+        html::escape(tr.get("conformance.detail"))));
+    for check in sorted {
+        out.push_str(&format!("<tr><td>{}</td><td>{}</td><td><a \
+            href=\"{RFC_URL}#section-{}\">{}</a></td><td>{}</td></tr>",
+            html::escape(&check.verdict.describe(tr)),
+            html::escape(&check.subject), check.section, check.section,
+            html::escape(&check.describe(tr))));
+    }
+    out.push_str("</table></body></html>");
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        return pairs.iter()
+            .map(|(n, v)| (String::from(*n), String::from(*v)))
+            .collect();
+    }
+
+    const GOOD: &[(&str, &str)] = &[
+        ("GATEWAY_INTERFACE", "CGI/1.1"),
+        ("QUERY_STRING", "a=1"),
+        ("REMOTE_ADDR", "192.0.2.1"),
+        ("REMOTE_HOST", "192.0.2.1"),
+        ("REQUEST_METHOD", "POST"),
+        ("SCRIPT_NAME", "/cgi-bin/testcgi"),
+        ("SERVER_NAME", "example.com"),
+        ("SERVER_PORT", "443"),
+        ("SERVER_PROTOCOL", "HTTP/1.1"),
+        ("SERVER_SOFTWARE", "Apache/2.4.62"),
+        ("CONTENT_LENGTH", "5"),
+        ("CONTENT_TYPE", "text/plain; charset=utf-8"),
+        ("PATH_INFO", "/a/b"),
+        ("PATH_TRANSLATED", "/var/www/a/b"),
+        ("DOCUMENT_ROOT", "/var/www/"),
+        ("HTTP_HOST", "example.com"),
+    ];
+
+    /// The verdict and message key of the last check about `subject`, which
+    /// comes after the one that says a required variable is set.
+    fn find<'a>(checks: &'a [Check], subject: &str) -> (Verdict, &'a str) {
+        let check = checks.iter().rfind(|c| c.subject == subject).unwrap();
+        return (check.verdict, check.message);
+    }
+
+    #[test]
+    fn good_request_passes() {
+        let checks = check(&variables(GOOD));
+        let (pass, warn, fail) = summary(&checks);
+        assert_eq!((warn, fail), (0, 0), "{checks:?}");
+        assert_eq!(pass, checks.len());
+        let length = checks.iter()
+            .find(|c| c.message == "conformance.length")
+            .unwrap();
+        assert_eq!(length.to_string(), "5 bytes");
+    }
+
+    #[test]
+    fn problems() {
+        let replaced = ["SERVER_SOFTWARE", "REMOTE_HOST", "GATEWAY_INTERFACE",
+            "SERVER_PROTOCOL", "REQUEST_METHOD"];
+        let mut pairs: Vec<(&str, &str)> = GOOD.iter()
+            .filter(|(n, _)| !replaced.contains(n))
+            .copied()
+            .collect();
+        pairs.extend([
+            ("GATEWAY_INTERFACE", "CGI/1.2"),
+            ("SERVER_PROTOCOL", "HTTP/2"),
+            ("REQUEST_METHOD", "get"),
+            ("HTTP_PROXY", "http://evil"),
+            ("HTTP_CONTENT_TYPE", "text/plain"),
+            ("HTTP_X-ODD", "1"),
+        ]);
+        let checks = check(&variables(&pairs));
+        assert_eq!(find(&checks, "SERVER_SOFTWARE"),
+            (Verdict::Fail, "conformance.required"));
+        assert_eq!(find(&checks, "REMOTE_HOST"),
+            (Verdict::Warn, "conformance.remote_host"));
+        assert_eq!(find(&checks, "GATEWAY_INTERFACE"),
+            (Verdict::Warn, "conformance.gateway_other"));
+        let protocol = checks.iter()
+            .rfind(|c| c.subject == "SERVER_PROTOCOL")
+            .unwrap();
+        assert_eq!(protocol.to_string(), "\"HTTP/2\" has no minor version; \
+            the grammar wants HTTP/2.0");
+        assert_eq!(find(&checks, "REQUEST_METHOD"),
+            (Verdict::Warn, "conformance.method_case"));
+        assert_eq!(find(&checks, "HTTP_PROXY"),
+            (Verdict::Warn, "conformance.httpoxy"));
+        assert_eq!(find(&checks, "HTTP_CONTENT_TYPE"),
+            (Verdict::Warn, "conformance.header_duplicate"));
+        assert_eq!(find(&checks, "HTTP_X-ODD"),
+            (Verdict::Fail, "conformance.header_name"));
+    }
+
+    #[test]
+    fn content_and_path() {
+        let checks = check(&variables(&[("REQUEST_METHOD", "GET"),
+            ("CONTENT_LENGTH", "12"), ("PATH_INFO", "x"),
+            ("PATH_TRANSLATED", "/srv/x")]));
+        assert_eq!(find(&checks, "CONTENT_LENGTH"),
+            (Verdict::Warn, "conformance.length_bodyless"));
+        assert_eq!(find(&checks, "CONTENT_TYPE"),
+            (Verdict::Warn, "conformance.type_missing"));
+        assert_eq!(find(&checks, "PATH_INFO"),
+            (Verdict::Fail, "conformance.path_info_bad"));
+        let checks = check(&variables(&[("REQUEST_METHOD", "POST"),
+            ("PATH_TRANSLATED", "/srv/x")]));
+        assert_eq!(find(&checks, "CONTENT_LENGTH"),
+            (Verdict::Warn, "conformance.length_missing"));
+        assert_eq!(find(&checks, "PATH_TRANSLATED"),
+            (Verdict::Fail, "conformance.translated_set"));
+    }
+
+    #[test]
+    fn server_names() {
+        assert!(is_server_name("example.com"));
+        assert!(is_server_name("[2001:db8::1]"));
+        assert!(is_server_name("192.0.2.1"));
+        assert!(!is_server_name("exa mple.com"));
+        assert!(!is_server_name("example..com"));
+        assert!(!is_server_name("[example.com]"));
+    }
+
+    #[test]
+    fn report_is_translated() {
+        let catalogs = catalog::Catalogs::built_in();
+        let checks = check(&variables(&[("REQUEST_METHOD", "GET")]));
+        let page = report_html(&checks, &catalogs.translator("de"));
+        assert!(page.starts_with("<!DOCTYPE html><html lang=\"de\">"));
+        assert!(page.contains("Konformität mit RFC 3875"));
+        assert!(page.contains("ist Pflicht, wurde aber nicht gesetzt"));
+    }
+}
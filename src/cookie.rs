@@ -0,0 +1,358 @@
+// src/cookie.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Cookie parsing and Set-Cookie generation per RFC 6265.
+
+use std::fmt;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use crate::http_date::format_http_date;
+use crate::response::Response;
+
+/// Splits a Cookie header, as found in HTTP_COOKIE, into name/value pairs.
+///
+/// RFC 6265 section 4.2.1 says a user agent sends `name=value; name=value`.
+/// Real user agents are sloppier than that, so this follows the lenient
+/// rules instead:
+///
+/// - empty pairs and extra whitespace are ignored.
+/// - a pair without an `=` is a cookie with an empty name.
+/// - a value wrapped in double quotes has the quotes removed.
+///
+/// The order is kept and duplicates are not merged because the same name can
+/// legitimately appear once per matching Path or Domain.
+pub fn parse_cookie_header(header: &str) -> Vec<(String, String)> {
+    let mut output: Vec<(String, String)> = Vec::with_capacity(8);
+    for pair in header.split(';') {
+        let pair = pair.trim();
+        if pair.is_empty() {
+            continue;
+        }
+        let (name, value) = match pair.split_once('=') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => ("", pair),
+        };
+        let value = if value.len() >= 2 && value.starts_with('"')
+            && value.ends_with('"') {
+            &value[1..value.len() - 1]
+        } else {
+            value
+        };
+        output.push((String::from(name), String::from(value)));
+    }
+    return output;
+}
+
+/// RFC 9110 tchar. Cookie names have to be tokens.
+pub fn is_token_char(c: char) -> bool {
+    return c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+}
+
+/// RFC 6265 cookie-octet: printable US-ASCII without whitespace, DQUOTE,
+/// comma, semicolon or backslash.
+pub fn is_cookie_octet(c: char) -> bool {
+    return matches!(c, '\x21' | '\x23'..='\x2B' | '\x2D'..='\x3A'
+        | '\x3C'..='\x5B' | '\x5D'..='\x7E');
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl SameSite {
+    pub fn parse(input: &str) -> Option<SameSite> {
+        if input.eq_ignore_ascii_case("strict") {
+            return Some(SameSite::Strict);
+        }
+        if input.eq_ignore_ascii_case("lax") {
+            return Some(SameSite::Lax);
+        }
+        if input.eq_ignore_ascii_case("none") {
+            return Some(SameSite::None);
+        }
+        return None;
+    }
+}
+
+impl fmt::Display for SameSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            SameSite::Strict => write!(f, "Strict"),
+            SameSite::Lax => write!(f, "Lax"),
+            SameSite::None => write!(f, "None"),
+        };
+    }
+}
+
+/// Builds the value of a Set-Cookie header field.
+///
+/// ```ignore
+/// let cookie = SetCookie::new("id", "a3fWa")?
+///     .max_age(3600)
+///     .path("/cgi-bin/testcgi")
+///     .http_only(true)
+///     .same_site(SameSite::Lax);
+/// response.header("Set-Cookie", &cookie.to_string());
+/// ```
+#[derive(Clone, Debug)]
+pub struct SetCookie {
+    name: String,
+    value: String,
+    expires: Option<SystemTime>,
+    max_age: Option<i64>,
+    domain: Option<String>,
+    path: Option<String>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+    partitioned: bool,
+}
+
+impl SetCookie {
+    /// Fails if the name is not a token or the value has characters that are
+    /// not allowed in a cookie. Percent encode the value first if it might.
+    pub fn new(name: &str, value: &str) -> Result<SetCookie, &'static str> {
+        if name.is_empty() || !name.chars().all(is_token_char) {
+            return Err("A cookie name must be a non-empty token.");
+        }
+        if !value.chars().all(is_cookie_octet) {
+            return Err("A cookie value may only contain printable ASCII \
+                without spaces, quotes, commas, semicolons or backslashes.");
+        }
+        return Ok(SetCookie {
+            name: String::from(name),
+            value: String::from(value),
+            expires: None,
+            max_age: None,
+            domain: None,
+            path: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+            partitioned: false,
+        });
+    }
+
+    /// A cookie that tells the user agent to throw away `name` right away.
+    /// The Path and Domain have to match the original cookie to work.
+    pub fn delete(name: &str) -> Result<SetCookie, &'static str> {
+        return Ok(SetCookie::new(name, "")?
+            .expires(UNIX_EPOCH)
+            .max_age(0));
+    }
+
+    pub fn name(&self) -> &str {
+        return &self.name;
+    }
+
+    pub fn value(&self) -> &str {
+        return &self.value;
+    }
+
+    pub fn expires(mut self, time: SystemTime) -> SetCookie {
+        self.expires = Some(time);
+        return self;
+    }
+
+    /// Seconds until the cookie expires. Zero or less expires it now.
+    pub fn max_age(mut self, seconds: i64) -> SetCookie {
+        self.max_age = Some(seconds);
+        return self;
+    }
+
+    /// Attribute values cannot contain `;` or control characters, so those
+    /// are dropped rather than letting them start a new attribute.
+    pub fn domain(mut self, domain: &str) -> SetCookie {
+        self.domain = Some(clean_attribute(domain));
+        return self;
+    }
+
+    pub fn path(mut self, path: &str) -> SetCookie {
+        self.path = Some(clean_attribute(path));
+        return self;
+    }
+
+    pub fn secure(mut self, secure: bool) -> SetCookie {
+        self.secure = secure;
+        return self;
+    }
+
+    pub fn http_only(mut self, http_only: bool) -> SetCookie {
+        self.http_only = http_only;
+        return self;
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> SetCookie {
+        self.same_site = Some(same_site);
+        return self;
+    }
+
+    /// CHIPS partitioned cookies. Browsers ignore Partitioned without Secure
+    /// so setting this also turns Secure on.
+    pub fn partitioned(mut self, partitioned: bool) -> SetCookie {
+        self.partitioned = partitioned;
+        return self;
+    }
+}
+
+impl fmt::Display for SetCookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(time) = self.expires {
+            write!(f, "; Expires={}", format_http_date(time))?;
+        }
+        if let Some(seconds) = self.max_age {
+            write!(f, "; Max-Age={}", seconds.max(0))?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={domain}")?;
+        }
+        if let Some(path) = &self.path {
+            write!(f, "; Path={path}")?;
+        }
+        // SameSite=None is rejected by browsers unless the cookie is Secure.
+        if self.secure || self.partitioned
+            || self.same_site == Some(SameSite::None) {
+            write!(f, "; Secure")?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={same_site}")?;
+        }
+        if self.partitioned {
+            write!(f, "; Partitioned")?;
+        }
+        return Ok(());
+    }
+}
+
+fn clean_attribute(input: &str) -> String {
+    return input.chars().filter(|c| *c != ';' && !c.is_control()).collect();
+}
+
+/// The Path every testcgi cookie is set with, so `/cookies/delete` can find
+/// the cookies `/cookies/set` made.
+fn cookie_path(script_name: &str) -> &str {
+    if script_name.is_empty() {
+        return "/";
+    }
+    return script_name;
+}
+
+/// `/cookies/set?name=value&...` sets every query parameter as a cookie.
+///
+/// This answers with a plain page instead of a redirect. A CGI `Location`
+/// header holding only a path is a local redirect that the web server
+/// follows itself, and the Set-Cookie fields would never reach the browser.
+pub fn set_route(query: &[(String, String)], script_name: &str) -> Response {
+    let mut response = Response::new(200);
+    let mut body = String::with_capacity(256);
+    for (name, value) in query {
+        match SetCookie::new(name, value) {
+            Ok(cookie) => {
+                let cookie = cookie.path(cookie_path(script_name));
+                let header = cookie.to_string();
+                body.push_str(&format!("Set-Cookie: {header}\n"));
+                response.header("Set-Cookie", &header);
+            }
+            Err(message) => {
+                return Response::text(400,
+                    &format!("Cannot set cookie {name:?}: {message}"));
+            }
+        }
+    }
+    response.header("Content-Type", "text/plain; charset=utf-8");
+    response.body.extend_from_slice(body.as_bytes());
+    return response;
+}
+
+/// `/cookies/delete?name&...` expires every cookie named in the query.
+pub fn delete_route(names: &[String], script_name: &str) -> Response {
+    let mut response = Response::new(200);
+    let mut body = String::with_capacity(256);
+    for name in names {
+        match SetCookie::delete(name) {
+            Ok(cookie) => {
+                let cookie = cookie.path(cookie_path(script_name));
+                let header = cookie.to_string();
+                body.push_str(&format!("Set-Cookie: {header}\n"));
+                response.header("Set-Cookie", &header);
+            }
+            Err(message) => {
+                return Response::text(400,
+                    &format!("Cannot delete cookie {name:?}: {message}"));
+            }
+        }
+    }
+    response.header("Content-Type", "text/plain; charset=utf-8");
+    response.body.extend_from_slice(body.as_bytes());
+    return response;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        return pairs.iter()
+            .map(|(n, v)| (String::from(*n), String::from(*v)))
+            .collect();
+    }
+
+    #[test]
+    fn parses_cookie_header() {
+        assert_eq!(parse_cookie_header("a=1; b=2"),
+            pairs(&[("a", "1"), ("b", "2")]));
+        assert_eq!(parse_cookie_header(" a = 1 ;; ;b=\"x y\""),
+            pairs(&[("a", "1"), ("b", "x y")]));
+        assert_eq!(parse_cookie_header("flag; a=b=c"),
+            pairs(&[("", "flag"), ("a", "b=c")]));
+        assert_eq!(parse_cookie_header("a=1; a=2"),
+            pairs(&[("a", "1"), ("a", "2")]));
+        assert_eq!(parse_cookie_header("a=\""), pairs(&[("a", "\"")]));
+        assert!(parse_cookie_header("").is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_names_and_values() {
+        assert!(SetCookie::new("", "v").is_err());
+        assert!(SetCookie::new("a b", "v").is_err());
+        assert!(SetCookie::new("a", "x;y").is_err());
+        assert!(SetCookie::new("a", "\"").is_err());
+        assert!(SetCookie::new("a", "x%3By").is_ok());
+    }
+
+    #[test]
+    fn formats_attributes() {
+        let cookie = SetCookie::new("id", "42").unwrap()
+            .max_age(-5)
+            .path("/a;Domain=evil\r\n")
+            .http_only(true)
+            .same_site(SameSite::None);
+        assert_eq!(cookie.to_string(),
+            "id=42; Max-Age=0; Path=/aDomain=evil; Secure; HttpOnly; \
+            SameSite=None");
+        let partitioned = SetCookie::new("p", "").unwrap().partitioned(true);
+        assert_eq!(partitioned.to_string(), "p=; Secure; Partitioned");
+        assert_eq!(SetCookie::delete("id").unwrap().to_string(),
+            "id=; Expires=Thu, 01 Jan 1970 00:00:00 GMT; Max-Age=0");
+    }
+}
@@ -0,0 +1,91 @@
+// src/crc32.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! CRC-32 as used by zlib, gzip and PNG: polynomial 0x04c11db7, reflected,
+//! with the register starting at and finished with all ones.
+
+const TABLE: [u32; 256] = make_table();
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    return table;
+}
+
+/// A running CRC-32 for data that arrives in pieces.
+#[derive(Clone)]
+pub struct Crc32 {
+    register: u32,
+}
+
+impl Crc32 {
+    pub fn new() -> Crc32 {
+        return Crc32 { register: 0xffffffff };
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            let index = ((self.register ^ *byte as u32) & 0xff) as usize;
+            self.register = (self.register >> 8) ^ TABLE[index];
+        }
+    }
+
+    pub fn finish(self) -> u32 {
+        return self.register ^ 0xffffffff;
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Crc32 {
+        return Crc32::new();
+    }
+}
+
+/// The CRC-32 of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    return crc.finish();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The check value from the catalogue of CRC algorithms.
+    #[test]
+    fn check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn fed_in_pieces() {
+        let mut crc = Crc32::new();
+        crc.update(b"12345");
+        crc.update(b"6789");
+        assert_eq!(crc.finish(), 0xcbf43926);
+    }
+}
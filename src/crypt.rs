@@ -0,0 +1,325 @@
+// src/crypt.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! The crypt(3) style password hashes from the `$id$salt$hash` family that
+//! htpasswd files use, apart from bcrypt which has its own module.
+//!
+//! - `$5$` and `$6$` are Ulrich Drepper's SHA-crypt over SHA-256 and SHA-512.
+//! - `$1$` is Poul-Henning Kamp's MD5-crypt and `$apr1$` is Apache's copy of
+//!   it with a different prefix. `htpasswd -m` writes those.
+
+use crate::digest::Digest;
+use crate::hmac::constant_time_eq;
+use crate::md5::Md5;
+use crate::sha256::Sha256;
+use crate::sha512::Sha512;
+
+/// The crypt(3) base64 alphabet.
+const ALPHABET: &[u8; 64] =
+    b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+const SHA_DEFAULT_ROUNDS: u64 = 5000;
+const SHA_MIN_ROUNDS: u64 = 1000;
+const SHA_MAX_ROUNDS: u64 = 999_999_999;
+const SHA_MAX_SALT: usize = 16;
+const MD5_MAX_SALT: usize = 8;
+
+/// True for the prefixes verify knows how to check.
+pub fn is_crypt(hash: &str) -> bool {
+    return hash.starts_with("$5$") || hash.starts_with("$6$")
+        || hash.starts_with("$1$") || hash.starts_with("$apr1$");
+}
+
+/// Rebuilds the hash from `password` and the salt and settings in `hash`,
+/// then compares the two.
+pub fn verify(password: &[u8], hash: &str) -> bool {
+    let computed: Option<String>;
+    if let Some(rest) = hash.strip_prefix("$5$") {
+        computed = sha_crypt::<Sha256>("$5$", password, rest, &SHA256_ORDER);
+    } else if let Some(rest) = hash.strip_prefix("$6$") {
+        computed = sha_crypt::<Sha512>("$6$", password, rest, &SHA512_ORDER);
+    } else if let Some(rest) = hash.strip_prefix("$1$") {
+        computed = Some(md5_crypt("$1$", password, rest));
+    } else if let Some(rest) = hash.strip_prefix("$apr1$") {
+        computed = Some(md5_crypt("$apr1$", password, rest));
+    } else {
+        return false;
+    }
+    return match computed {
+        Some(computed) => {
+            constant_time_eq(computed.as_bytes(), hash.as_bytes())
+        }
+        None => false,
+    };
+}
+
+/// The byte order SHA-crypt uses when it base64 encodes a SHA-256 result,
+/// three bytes at a time. A None stands for a zero byte.
+const SHA256_ORDER: [[Option<usize>; 3]; 11] = sha_order::<11>(10, 32);
+
+/// Same for SHA-512.
+const SHA512_ORDER: [[Option<usize>; 3]; 22] = sha_order::<22>(21, 64);
+
+/// Builds the SHA-crypt output order. Each group takes bytes `i`,
+/// `i + stride` and `i + 2 * stride`, rotated one place every group, and the
+/// last group mops up what is left. The SHA-256 and SHA-512 variants rotate
+/// in opposite directions.
+const fn sha_order<const N: usize>(stride: usize, length: usize)
+    -> [[Option<usize>; 3]; N]
+{
+    let mut order = [[None; 3]; N];
+    let mut i = 0;
+    while i < stride {
+        let a = Some(i);
+        let b = Some(i + stride);
+        let c = Some(i + 2 * stride);
+        order[i] = match (i % 3, length == 32) {
+            (0, _) => [a, b, c],
+            (1, true) | (2, false) => [c, a, b],
+            _ => [b, c, a],
+        };
+        i += 1;
+    }
+    order[stride] = if length == 32 {
+        [None, Some(31), Some(30)]
+    } else {
+        [None, None, Some(63)]
+    };
+    return order;
+}
+
+/// SHA-crypt as specified at https://www.akkadia.org/drepper/SHA-crypt.txt
+///
+/// `settings` is everything after the `$5$` or `$6$`: an optional
+/// `rounds=N$`, the salt and possibly the old hash, which is ignored.
+fn sha_crypt<H: Digest + Clone>(prefix: &str, password: &[u8], settings: &str,
+    order: &[[Option<usize>; 3]]) -> Option<String>
+{
+    let mut rounds = SHA_DEFAULT_ROUNDS;
+    let mut explicit_rounds = false;
+    let mut settings = settings;
+    if let Some(rest) = settings.strip_prefix("rounds=") {
+        let (count, rest) = rest.split_once('$')?;
+        rounds = count.parse::<u64>().ok()?
+            .clamp(SHA_MIN_ROUNDS, SHA_MAX_ROUNDS);
+        explicit_rounds = true;
+        settings = rest;
+    }
+    let salt = settings.split('$').next()?;
+    let salt = &salt.as_bytes()[..salt.len().min(SHA_MAX_SALT)];
+    let size = H::OUTPUT_SIZE;
+
+    let mut b = H::new();
+    b.update(password);
+    b.update(salt);
+    b.update(password);
+    let b = b.finish();
+
+    let mut a = H::new();
+    a.update(password);
+    a.update(salt);
+    let mut remaining = password.len();
+    while remaining > size {
+        a.update(&b);
+        remaining -= size;
+    }
+    a.update(&b[..remaining]);
+    let mut bits = password.len();
+    while bits > 0 {
+        if bits & 1 == 1 {
+            a.update(&b);
+        } else {
+            a.update(password);
+        }
+        bits >>= 1;
+    }
+    let a = a.finish();
+
+    let mut dp = H::new();
+    for _ in 0..password.len() {
+        dp.update(password);
+    }
+    let p_sequence = repeat_to(&dp.finish(), password.len());
+
+    let mut ds = H::new();
+    for _ in 0..(16 + a[0] as usize) {
+        ds.update(salt);
+    }
+    let s_sequence = repeat_to(&ds.finish(), salt.len());
+
+    let mut c = a;
+    for i in 0..rounds {
+        let mut round = H::new();
+        if i % 2 == 1 {
+            round.update(&p_sequence);
+        } else {
+            round.update(&c);
+        }
+        if i % 3 != 0 {
+            round.update(&s_sequence);
+        }
+        if i % 7 != 0 {
+            round.update(&p_sequence);
+        }
+        if i % 2 == 1 {
+            round.update(&c);
+        } else {
+            round.update(&p_sequence);
+        }
+        c = round.finish();
+    }
+
+    let mut output = String::from(prefix);
+    if explicit_rounds {
+        output.push_str(&format!("rounds={rounds}$"));
+    }
+    output.push_str(&String::from_utf8_lossy(salt));
+    output.push('$');
+    for (n, group) in order.iter().enumerate() {
+        let byte = |index: Option<usize>| index.map(|i| c[i]).unwrap_or(0);
+        let characters = if n + 1 < order.len() {
+            4
+        } else if size == 32 {
+            3
+        } else {
+            2
+        };
+        push_base64(&mut output, byte(group[0]), byte(group[1]),
+            byte(group[2]), characters);
+    }
+    return Some(output);
+}
+
+/// MD5-crypt. `settings` is the salt, possibly followed by `$` and the old
+/// hash.
+fn md5_crypt(prefix: &str, password: &[u8], settings: &str) -> String {
+    let salt = settings.split('$').next().unwrap_or("");
+    let salt = &salt.as_bytes()[..salt.len().min(MD5_MAX_SALT)];
+
+    let mut alternate = Md5::new();
+    alternate.update(password);
+    alternate.update(salt);
+    alternate.update(password);
+    let alternate = alternate.finish();
+
+    let mut context = Md5::new();
+    context.update(password);
+    context.update(prefix.as_bytes());
+    context.update(salt);
+    let mut remaining = password.len();
+    while remaining > 0 {
+        let take = remaining.min(16);
+        context.update(&alternate[..take]);
+        remaining -= take;
+    }
+    let mut bits = password.len();
+    while bits > 0 {
+        if bits & 1 == 1 {
+            context.update(&[0]);
+        } else {
+            context.update(&password[..1]);
+        }
+        bits >>= 1;
+    }
+    let mut result = context.finish();
+
+    // A thousand rounds to slow down brute force, which was a lot in 1994.
+    for i in 0..1000 {
+        let mut round = Md5::new();
+        if i % 2 == 1 {
+            round.update(password);
+        } else {
+            round.update(&result);
+        }
+        if i % 3 != 0 {
+            round.update(salt);
+        }
+        if i % 7 != 0 {
+            round.update(password);
+        }
+        if i % 2 == 1 {
+            round.update(&result);
+        } else {
+            round.update(password);
+        }
+        result = round.finish();
+    }
+
+    let mut output = String::from(prefix);
+    output.push_str(&String::from_utf8_lossy(salt));
+    output.push('$');
+    for (a, b, c) in [(0, 6, 12), (1, 7, 13), (2, 8, 14), (3, 9, 15),
+        (4, 10, 5)] {
+        push_base64(&mut output, result[a], result[b], result[c], 4);
+    }
+    push_base64(&mut output, 0, 0, result[11], 2);
+    return output;
+}
+
+/// `data` repeated until it is `length` bytes long.
+fn repeat_to(data: &[u8], length: usize) -> Vec<u8> {
+    return data.iter().copied().cycle().take(length).collect();
+}
+
+/// crypt(3) base64 puts the least significant six bits first.
+fn push_base64(output: &mut String, high: u8, middle: u8, low: u8,
+    characters: usize)
+{
+    let mut value = ((high as u32) << 16) | ((middle as u32) << 8) | low as u32;
+    for _ in 0..characters {
+        output.push(ALPHABET[(value & 0x3f) as usize] as char);
+        value >>= 6;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hashes made with `openssl passwd`. The SHA-crypt ones with rounds
+    /// are the examples from Ulrich Drepper's specification.
+    const HASHES: &[(&str, &str)] = &[
+        ("password", "$1$saltsalt$qjXMvbEw8oaL.CzflDtaK/"),
+        ("password", "$apr1$saltsalt$yAAkm4libquA.ZWLHbSBq/"),
+        ("", "$apr1$r31.....$iQoXxeL5pW.kaXVrniDCU0"),
+        ("password",
+            "$5$saltstring$OH4IDuTlsuTYPdED1gsuiRMyTAwNlRWyA6Xr3I4/dQ5"),
+        ("password", "$6$saltstring$adDbXsJjcDlq2662QPgd.tkSOVmnG9Tt3oXl4HR60S\
+            usC3AGjirnDenVZp3DGwLwqy6iYKCzannhaX9DR72nN1"),
+        ("Hello world!", "$5$rounds=10000$saltstringsaltst$3xv.VbSHBb41AL9AvLe\
+            ujZkZRBAwqFMz2.opqey6IcA"),
+        ("a very much longer text to encrypt.  This one even stretches over \
+            morethan one line.", "$6$rounds=1000$toolongsaltstrin$uUoPCVs4Mbmk\
+            LqPR9Zjq9d2xIEsKhIxxOMolJjDvb/MrASr6BCNhSKjZ1p/Mb2UxYCMwvCIkXErgwL6\
+            1HTZ.R."),
+    ];
+
+    #[test]
+    fn openssl_passwd() {
+        for (password, hash) in HASHES {
+            assert!(is_crypt(hash), "{hash}");
+            assert!(verify(password.as_bytes(), hash), "{hash}");
+            assert!(!verify(b"wrong", hash), "{hash}");
+        }
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        assert!(!is_crypt("$2y$05$CCCCCCCCCCCCCCCCCCCCC.E5YPO9kmyuRGyh0XouQYb4\
+            YMJKvyOeW"));
+        assert!(!verify(b"password", "$7$saltsalt$qjXMvbEw8oaL.CzflDtaK/"));
+        assert!(!verify(b"password", "$5$rounds=x$saltstring$OH4IDuTl"));
+    }
+}
@@ -0,0 +1,390 @@
+// src/csrf.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Cross-site request forgery protection for form POSTs.
+//!
+//! Two kinds of token are supported:
+//!
+//! - Double-submit cookie. A random token is set as a cookie and the form has
+//!   to send the same value back. Another site can make the browser send the
+//!   cookie but cannot read it to put it in the form.
+//! - HMAC synchronizer token. The token is a timestamp and some random bytes
+//!   signed with the server secret and tied to the session cookie, so no
+//!   cookie of its own is needed and a token cannot be moved to another
+//!   session.
+//!
+//! Either way the token comes back in the `_csrf` form field or the
+//! `X-CSRF-Token` header. Checking the Origin or Referer against SERVER_NAME
+//! can be switched on as well.
+
+use std::collections::HashMap;
+use std::env;
+use std::io;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use crate::config;
+use crate::cookie::SameSite;
+use crate::cookie::SetCookie;
+use crate::cookie::parse_cookie_header;
+use crate::hex;
+use crate::hmac::constant_time_eq;
+use crate::hmac::hmac;
+use crate::html;
+use crate::http_date::unix_seconds;
+use crate::random;
+use crate::session::DEFAULT_COOKIE_NAME;
+use crate::sha256::Sha256;
+use crate::url::split_host_port;
+
+/// The hidden form field that carries the token.
+pub const FIELD_NAME: &str = "_csrf";
+
+/// The meta-variable for the `X-CSRF-Token` request header, for scripts that
+/// post JSON instead of forms.
+pub const HEADER_VARIABLE: &str = "HTTP_X_CSRF_TOKEN";
+
+/// The cookie the double-submit token lives in.
+pub const COOKIE_NAME: &str = "testcgi_csrf";
+
+/// How long an HMAC token stays good.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(2 * 60 * 60);
+
+const TOKEN_RANDOM_BYTES: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    DoubleSubmit,
+    Hmac,
+}
+
+impl Mode {
+    pub fn parse(input: &str) -> Option<Mode> {
+        let input = input.trim();
+        if input.eq_ignore_ascii_case("double-submit") {
+            return Some(Mode::DoubleSubmit);
+        }
+        if input.eq_ignore_ascii_case("hmac") {
+            return Some(Mode::Hmac);
+        }
+        return None;
+    }
+}
+
+/// True for methods that can change state and so need a token. RFC 9110
+/// section 9.2.1 calls the rest safe.
+pub fn is_state_changing(method: &str) -> bool {
+    return !["GET", "HEAD", "OPTIONS", "TRACE"].iter()
+        .any(|safe| method.eq_ignore_ascii_case(safe));
+}
+
+/// The token a request sent, from the X-CSRF-Token header or else the form.
+pub fn submitted_token(form: &HashMap<String, String>) -> Option<String> {
+    if let Ok(token) = env::var(HEADER_VARIABLE)
+        && !token.is_empty() {
+        return Some(token);
+    }
+    return form.get(FIELD_NAME).cloned();
+}
+
+/// A hidden input to put inside a `<form method="post">`.
+pub fn hidden_field(token: &str) -> String {
+    return format!("<input type=\"hidden\" name=\"{FIELD_NAME}\" \
+        value=\"{}\" />", html::escape(token));
+}
+
+pub struct CsrfProtection {
+    mode: Mode,
+    secret: Vec<u8>,
+    max_age: Duration,
+    check_origin: bool,
+    cookie_path: String,
+}
+
+impl CsrfProtection {
+    /// `secret` is only used by Mode::Hmac.
+    pub fn new(mode: Mode, secret: &[u8]) -> CsrfProtection {
+        return CsrfProtection {
+            mode,
+            secret: secret.to_vec(),
+            max_age: DEFAULT_MAX_AGE,
+            check_origin: false,
+            cookie_path: String::from("/"),
+        };
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> CsrfProtection {
+        self.max_age = max_age;
+        return self;
+    }
+
+    /// Also require the Origin, or failing that the Referer, to name this
+    /// server.
+    pub fn check_origin(mut self, check_origin: bool) -> CsrfProtection {
+        self.check_origin = check_origin;
+        return self;
+    }
+
+    pub fn cookie_path(mut self, path: &str) -> CsrfProtection {
+        self.cookie_path = String::from(path);
+        return self;
+    }
+
+    pub fn mode(&self) -> Mode {
+        return self.mode;
+    }
+
+    /// A token to put in a form. For double-submit this is the token already
+    /// in the cookie if there is one; otherwise the cookie that has to be
+    /// sent with the page comes back too.
+    pub fn token(&self, cookie_header: &str)
+        -> io::Result<(String, Option<SetCookie>)>
+    {
+        let random = hex::encode(&random::bytes(TOKEN_RANDOM_BYTES)?);
+        match self.mode {
+            Mode::DoubleSubmit => {
+                if let Some(existing) = cookie_value(cookie_header, COOKIE_NAME)
+                    && is_double_submit_token(&existing) {
+                    return Ok((existing, None));
+                }
+                let cookie = SetCookie::new(COOKIE_NAME, &random)
+                    .expect("the cookie name is a token and hex is allowed")
+                    .path(&self.cookie_path)
+                    .same_site(SameSite::Strict);
+                return Ok((random, Some(cookie)));
+            }
+            Mode::Hmac => {
+                let issued = unix_seconds(SystemTime::now());
+                let payload = format!("{issued}.{random}");
+                let mac = self.mac(&payload, &session_binding(cookie_header));
+                return Ok((format!("{payload}.{mac}"), None));
+            }
+        }
+    }
+
+    fn mac(&self, payload: &str, binding: &str) -> String {
+        let message = format!("{payload}|{binding}");
+        return hex::encode(&hmac::<Sha256>(&self.secret, message.as_bytes()));
+    }
+
+    /// Checks a request. Safe methods always pass. The error is the reason
+    /// for the 403.
+    pub fn verify(&self, method: &str, submitted: Option<&str>,
+        cookie_header: &str) -> Result<(), String>
+    {
+        if !is_state_changing(method) {
+            return Ok(());
+        }
+        if self.check_origin {
+            check_origin_matches_server()?;
+        }
+        let submitted = match submitted {
+            Some(token) if !token.is_empty() => token,
+            _ => {
+                return Err(format!("The {FIELD_NAME} form field and the \
+                    X-CSRF-Token header are both missing."));
+            }
+        };
+        match self.mode {
+            Mode::DoubleSubmit => {
+                let expected = match cookie_value(cookie_header, COOKIE_NAME) {
+                    Some(expected) => expected,
+                    None => {
+                        return Err(format!("The {COOKIE_NAME} cookie is \
+                            missing."));
+                    }
+                };
+                if !constant_time_eq(submitted.as_bytes(),
+                    expected.as_bytes()) {
+                    return Err(String::from("The token does not match the \
+                        cookie."));
+                }
+                return Ok(());
+            }
+            Mode::Hmac => {
+                let (payload, mac) = match submitted.rsplit_once('.') {
+                    Some(parts) => parts,
+                    None => return Err(String::from("The token is malformed.")),
+                };
+                let expected = self.mac(payload,
+                    &session_binding(cookie_header));
+                if !constant_time_eq(mac.as_bytes(), expected.as_bytes()) {
+                    return Err(String::from("The token signature is wrong or \
+                        belongs to another session."));
+                }
+                let issued: i64 = payload.split('.').next()
+                    .and_then(|t| t.parse().ok())
+                    .unwrap_or(0);
+                let age = unix_seconds(SystemTime::now()) - issued;
+                if age > self.max_age.as_secs() as i64 {
+                    return Err(format!("The token expired; it is {age} \
+                        seconds old."));
+                }
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Reads TESTCGI_CSRF (`double-submit` or `hmac`) and TESTCGI_CSRF_ORIGIN.
+/// None when protection is switched off. Errors describe a bad setting.
+pub fn from_env(cookie_path: &str) -> Result<Option<CsrfProtection>, String> {
+    let mode = match config::get("TESTCGI_CSRF") {
+        Some(mode) if !mode.trim().eq_ignore_ascii_case("off") => mode,
+        _ => return Ok(None),
+    };
+    let mode = match Mode::parse(&mode) {
+        Some(mode) => mode,
+        None => {
+            return Err(format!("TESTCGI_CSRF is {mode:?}; use off, \
+                double-submit or hmac."));
+        }
+    };
+    let secret = config::get("TESTCGI_SECRET").unwrap_or_default();
+    if mode == Mode::Hmac && secret.is_empty() {
+        return Err(String::from("HMAC CSRF tokens need TESTCGI_SECRET to be \
+            set."));
+    }
+    let check_origin = config::get("TESTCGI_CSRF_ORIGIN")
+        .and_then(|v| config::parse_bool(&v))
+        .unwrap_or(false);
+    return Ok(Some(CsrfProtection::new(mode, secret.as_bytes())
+        .check_origin(check_origin)
+        .cookie_path(cookie_path)));
+}
+
+/// Checks HTTP_ORIGIN, or HTTP_REFERER when there is no Origin, against
+/// SERVER_NAME and SERVER_PORT.
+pub fn check_origin_matches_server() -> Result<(), String> {
+    let server_name = env::var("SERVER_NAME").unwrap_or_default();
+    let server_port = env::var("SERVER_PORT").unwrap_or_default();
+    let source = match env::var("HTTP_ORIGIN") {
+        Ok(origin) if !origin.is_empty() => origin,
+        _ => match env::var("HTTP_REFERER") {
+            Ok(referer) if !referer.is_empty() => referer,
+            _ => {
+                return Err(String::from("Neither Origin nor Referer was \
+                    sent."));
+            }
+        },
+    };
+    return origin_matches(&source, &server_name, &server_port);
+}
+
+/// Compares the host and port of an Origin or Referer URL with the server.
+/// A missing port means the scheme's default.
+pub fn origin_matches(source: &str, server_name: &str, server_port: &str)
+    -> Result<(), String>
+{
+    let (scheme, rest) = match source.split_once("://") {
+        Some(parts) => parts,
+        None => return Err(format!("{source:?} is not an absolute URL.")),
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let authority = authority.rsplit('@').next().unwrap_or(authority);
+    let (host, port) = split_host_port(authority);
+    let port = match port {
+        Some(port) => port,
+        None => if scheme.eq_ignore_ascii_case("https") { "443" } else { "80" },
+    };
+    if !host.eq_ignore_ascii_case(server_name.trim_matches(['[', ']'])) {
+        return Err(format!("The request came from {host:?}, not \
+            {server_name:?}."));
+    }
+    if !server_port.is_empty() && port != server_port {
+        return Err(format!("The request came from port {port}, not \
+            {server_port}."));
+    }
+    return Ok(());
+}
+
+fn cookie_value(cookie_header: &str, name: &str) -> Option<String> {
+    return parse_cookie_header(cookie_header).into_iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v);
+}
+
+fn is_double_submit_token(token: &str) -> bool {
+    return token.len() == TOKEN_RANDOM_BYTES * 2
+        && token.bytes().all(|b| b.is_ascii_hexdigit());
+}
+
+/// HMAC tokens are tied to the session cookie when there is one.
+fn session_binding(cookie_header: &str) -> String {
+    return cookie_value(cookie_header, DEFAULT_COOKIE_NAME).unwrap_or_default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_origin() {
+        assert!(origin_matches("https://example.com", "example.com", "443")
+            .is_ok());
+        assert!(origin_matches("http://EXAMPLE.com:8080/form?x", "example.com",
+            "8080").is_ok());
+        assert!(origin_matches("http://user@[::1]/", "[::1]", "80").is_ok());
+        assert!(origin_matches("https://example.com", "example.com", "")
+            .is_ok());
+        assert!(origin_matches("https://example.com", "example.com", "80")
+            .is_err());
+        assert!(origin_matches("https://example.com.evil", "example.com",
+            "443").is_err());
+        assert!(origin_matches("https://evil@example.org", "example.com",
+            "443").is_err());
+        assert!(origin_matches("null", "example.com", "443").is_err());
+    }
+
+    #[test]
+    fn verifies_double_submit() {
+        let csrf = CsrfProtection::new(Mode::DoubleSubmit, b"");
+        let (token, cookie) = csrf.token("").unwrap();
+        assert!(is_double_submit_token(&token));
+        assert!(cookie.unwrap().to_string().contains("SameSite=Strict"));
+
+        let header = format!("{COOKIE_NAME}={token}");
+        assert_eq!(csrf.token(&header).unwrap().0, token);
+        assert!(csrf.verify("POST", Some(&token), &header).is_ok());
+        assert!(csrf.verify("GET", None, "").is_ok());
+        assert!(csrf.verify("POST", None, &header).is_err());
+        assert!(csrf.verify("POST", Some(""), &header).is_err());
+        assert!(csrf.verify("POST", Some(&token), "").is_err());
+        assert!(csrf.verify("delete", Some("other"), &header).is_err());
+    }
+
+    #[test]
+    fn verifies_hmac() {
+        let csrf = CsrfProtection::new(Mode::Hmac, b"secret");
+        let session = format!("{DEFAULT_COOKIE_NAME}=abc");
+        let (token, cookie) = csrf.token(&session).unwrap();
+        assert!(cookie.is_none());
+        assert!(csrf.verify("POST", Some(&token), &session).is_ok());
+
+        let other_session = format!("{DEFAULT_COOKIE_NAME}=xyz");
+        assert!(csrf.verify("POST", Some(&token), &other_session).is_err());
+        let other_secret = CsrfProtection::new(Mode::Hmac, b"other");
+        assert!(other_secret.verify("POST", Some(&token), &session).is_err());
+        assert!(csrf.verify("POST", Some("malformed"), &session).is_err());
+
+        let payload = format!("{}.00",
+            unix_seconds(SystemTime::now()) - 60);
+        let old = format!("{payload}.{}", csrf.mac(&payload, "abc"));
+        let short = CsrfProtection::new(Mode::Hmac, b"secret")
+            .max_age(Duration::from_secs(30));
+        assert!(csrf.verify("POST", Some(&old), &session).is_ok());
+        assert!(short.verify("POST", Some(&old), &session).is_err());
+    }
+}
@@ -0,0 +1,455 @@
+// src/decode_form.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Decoding application/x-www-form-urlencoded data in whatever character set
+//! it was sent in.
+//!
+//! The character set comes from, in order:
+//!
+//! 1. the caller, if it knows better,
+//! 2. the charset parameter of CONTENT_TYPE,
+//! 3. a `_charset_` field, which browsers fill in with the encoding they
+//!    used (HTML section 4.10.21.3),
+//! 4. UTF-8.
+//!
+//! Nothing is silently replaced: every bad percent escape and every byte the
+//! character set cannot decode is listed with the field it was found in.
+//!
+//! Forms sent with `enctype="text/plain"` are read too. That encoding is one
+//! `name=value` line per field with nothing escaped, so it cannot be decoded
+//! reliably, and the guesses made are listed as problems as well.
+
+use std::fmt;
+
+use crate::catalog;
+use crate::catalog::Translator;
+use crate::charset::Charset;
+use crate::charset::Undecodable;
+use crate::charset::content_type_charset;
+
+/// The name of the field browsers put the form's character set in.
+pub const CHARSET_FIELD: &str = "_charset_";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CharsetSource {
+    Caller,
+    ContentType,
+    CharsetField,
+    Default,
+}
+
+impl CharsetSource {
+    pub fn describe(&self, tr: &Translator) -> String {
+        return String::from(tr.get(match self {
+            CharsetSource::Caller => "form.source.caller",
+            CharsetSource::ContentType => "form.source.content_type",
+            CharsetSource::CharsetField => "form.source.charset_field",
+            CharsetSource::Default => "form.source.default",
+        }));
+    }
+}
+
+impl fmt::Display for CharsetSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}", self.describe(&catalog::english()));
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Part {
+    Name,
+    Value,
+}
+
+/// How a text/plain line could have been read another way.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ambiguity {
+    /// A line without `=` was joined to the value before it.
+    Continuation,
+    /// A first line without `=` was taken as a name.
+    NoEquals,
+    /// A line with more than one `=` was split at the first.
+    ExtraEquals,
+    /// Lines end in LF where browsers send CRLF.
+    BareLineFeed,
+}
+
+impl Ambiguity {
+    fn key(&self) -> &'static str {
+        return match self {
+            Ambiguity::Continuation => "form.ambiguous.continuation",
+            Ambiguity::NoEquals => "form.ambiguous.no_equals",
+            Ambiguity::ExtraEquals => "form.ambiguous.extra_equals",
+            Ambiguity::BareLineFeed => "form.ambiguous.bare_lf",
+        };
+    }
+}
+
+/// Something wrong with the form or with one of its fields.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Problem {
+    /// The form named a character set that is not supported, and was
+    /// decoded as UTF-8 instead.
+    UnsupportedCharset { label: String, source: CharsetSource },
+    /// A `%` not followed by two hex digits. It is kept as it is.
+    BadEscape { field: usize, part: Part, offset: usize },
+    /// Bytes the character set has no character for.
+    Undecodable { field: usize, part: Part, error: Undecodable },
+    /// A text/plain field that could have been split another way.
+    Ambiguous { field: usize, reason: Ambiguity },
+}
+
+impl Problem {
+    pub fn describe(&self, tr: &Translator) -> String {
+        let part = |part: &Part| tr.get(match part {
+            Part::Name => "form.part.name",
+            Part::Value => "form.part.value",
+        });
+        return match self {
+            Problem::UnsupportedCharset { label, source } => {
+                tr.format("form.unsupported", &[&format!("{label:?}"),
+                    &source.describe(tr)])
+            }
+            Problem::BadEscape { field, part: which, offset } => {
+                tr.format("form.problem.bad_escape", &[&(field + 1).to_string(),
+                    part(which), &offset.to_string()])
+            }
+            Problem::Undecodable { field, part: which, error } => {
+                let bytes: Vec<String> = error.bytes.iter()
+                    .map(|b| format!("{b:02X}"))
+                    .collect();
+                tr.format("form.problem.undecodable", &[
+                    &(field + 1).to_string(), part(which),
+                    &error.offset.to_string(), &bytes.join(" ")])
+            }
+            Problem::Ambiguous { field, reason } => {
+                tr.format("form.problem.ambiguous", &[&(field + 1).to_string(),
+                    tr.get(reason.key())])
+            }
+        };
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}", self.describe(&catalog::english()));
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Form {
+    pub charset: Charset,
+    pub source: CharsetSource,
+    /// In the order they were sent, duplicates included.
+    pub fields: Vec<(String, String)>,
+    pub problems: Vec<Problem>,
+}
+
+impl Form {
+    /// The first value of the field called `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        return self.fields.iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str());
+    }
+}
+
+/// True if a CONTENT_TYPE value is text/plain, with or without parameters.
+pub fn is_text_plain(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or("").trim();
+    return essence.eq_ignore_ascii_case("text/plain");
+}
+
+/// True if a body looks like a text/plain form rather than some other
+/// text: it ends in CRLF, as browsers always end it, and starts with a line
+/// that has an `=`. Later lines may not, if a value had a line break.
+pub fn looks_like_text_plain(input: &[u8]) -> bool {
+    if !input.ends_with(b"\r\n") {
+        return false;
+    }
+    return input.split(|b| *b == b'\n').next()
+        .is_some_and(|line| line.contains(&b'='));
+}
+
+/// Decodes a form body or query string. `content_type` is CONTENT_TYPE, or
+/// empty for a query string. `charset` overrides everything else. A
+/// character set that is not supported is reported and UTF-8 used instead.
+pub fn decode_form(input: &[u8], content_type: &str, charset: Option<Charset>)
+    -> Form
+{
+    let (charset, source, unsupported) = match charset {
+        Some(charset) => (charset, CharsetSource::Caller, None),
+        None => pick_charset(input, content_type),
+    };
+    let mut form = Form {
+        charset,
+        source,
+        fields: Vec::new(),
+        problems: unsupported.into_iter().collect(),
+    };
+    for (index, (name, value)) in split_pairs(input).enumerate() {
+        let name = decode_part(name, charset, index, Part::Name,
+            &mut form.problems);
+        let value = decode_part(value, charset, index, Part::Value,
+            &mut form.problems);
+        form.fields.push((name, value));
+    }
+    return form;
+}
+
+/// Decodes a form sent with `enctype="text/plain"`.
+///
+/// HTML writes each field as `name=value` and a CRLF, without escaping
+/// anything. So a line is split at its first `=`, and a line with no `=` is
+/// taken to be a line break inside the value before it. Both guesses are
+/// reported, as are bare LF line ends, which a browser would not send.
+pub fn decode_text_plain(input: &[u8], content_type: &str,
+    charset: Option<Charset>) -> Form
+{
+    let lines: Vec<&[u8]> = split_lines(input);
+    let (charset, source, unsupported) = match charset {
+        Some(charset) => (charset, CharsetSource::Caller, None),
+        None => pick_charset_from(content_type, lines.iter()
+            .filter_map(|line| split_line(line))
+            .find(|(name, _)| *name == CHARSET_FIELD.as_bytes())
+            .map(|(_, value)| value.to_vec())),
+    };
+    let mut form = Form {
+        charset,
+        source,
+        fields: Vec::new(),
+        problems: unsupported.into_iter().collect(),
+    };
+    // The field of the first line that ended in a bare LF.
+    let mut bare_line_feed: Option<usize> = None;
+    for (index, line) in lines.iter().enumerate() {
+        let line = match line.strip_suffix(b"\r") {
+            Some(line) => line,
+            None => {
+                // The last line of a body cut short has no line end at all.
+                if bare_line_feed.is_none()
+                    && (index + 1 < lines.len() || input.ends_with(b"\n")) {
+                    let count = form.fields.len();
+                    bare_line_feed = Some(match split_line(line) {
+                        Some(_) => count,
+                        None => count.saturating_sub(1),
+                    });
+                }
+                line
+            }
+        };
+        let Some((name, value)) = split_line(line) else {
+            let count = form.fields.len();
+            match form.fields.last_mut() {
+                Some((_, previous)) => {
+                    let field = count - 1;
+                    previous.push_str("\r\n");
+                    previous.push_str(&decode_plain(line, charset, field,
+                        Part::Value, &mut form.problems));
+                    form.problems.push(Problem::Ambiguous { field,
+                        reason: Ambiguity::Continuation });
+                }
+                None => {
+                    let field = count;
+                    let name = decode_plain(line, charset, field, Part::Name,
+                        &mut form.problems);
+                    form.fields.push((name, String::new()));
+                    form.problems.push(Problem::Ambiguous { field,
+                        reason: Ambiguity::NoEquals });
+                }
+            }
+            continue;
+        };
+        let field = form.fields.len();
+        if value.contains(&b'=') {
+            form.problems.push(Problem::Ambiguous { field,
+                reason: Ambiguity::ExtraEquals });
+        }
+        let name = decode_plain(name, charset, field, Part::Name,
+            &mut form.problems);
+        let value = decode_plain(value, charset, field, Part::Value,
+            &mut form.problems);
+        form.fields.push((name, value));
+    }
+    if let Some(field) = bare_line_feed {
+        form.problems.push(Problem::Ambiguous { field,
+            reason: Ambiguity::BareLineFeed });
+    }
+    return form;
+}
+
+/// The lines of a text/plain body, without the LF that ends each one.
+fn split_lines(input: &[u8]) -> Vec<&[u8]> {
+    let mut lines: Vec<&[u8]> = input.split(|b| *b == b'\n').collect();
+    if lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    return lines;
+}
+
+fn split_line(line: &[u8]) -> Option<(&[u8], &[u8])> {
+    let index = line.iter().position(|b| *b == b'=')?;
+    return Some((&line[..index], &line[index + 1..]));
+}
+
+fn decode_plain(input: &[u8], charset: Charset, field: usize, part: Part,
+    problems: &mut Vec<Problem>) -> String
+{
+    let decoded = charset.decode(input);
+    for error in decoded.errors {
+        problems.push(Problem::Undecodable { field, part, error });
+    }
+    return decoded.text;
+}
+
+fn pick_charset(input: &[u8], content_type: &str)
+    -> (Charset, CharsetSource, Option<Problem>)
+{
+    // The field's value is a plain ASCII label, so it can be read before
+    // the character set is known.
+    let field = split_pairs(input)
+        .find(|(name, _)| *name == CHARSET_FIELD.as_bytes())
+        .map(|(_, value)| percent_decode(value).0);
+    return pick_charset_from(content_type, field);
+}
+
+/// The Content-Type charset wins over the `_charset_` field. A label that
+/// is not supported comes back as a problem, with UTF-8 to use instead.
+fn pick_charset_from(content_type: &str, field: Option<Vec<u8>>)
+    -> (Charset, CharsetSource, Option<Problem>)
+{
+    let (label, source) = match (content_type_charset(content_type), field) {
+        (Some(label), _) => (String::from(label), CharsetSource::ContentType),
+        (None, Some(value)) => (String::from_utf8_lossy(&value).into_owned(),
+            CharsetSource::CharsetField),
+        (None, None) => return (Charset::Utf8, CharsetSource::Default, None),
+    };
+    return match Charset::from_label(&label) {
+        Some(charset) => (charset, source, None),
+        None => (Charset::Utf8, CharsetSource::Default,
+            Some(Problem::UnsupportedCharset { label, source })),
+    };
+}
+
+/// Splits on `&` and then on the first `=`. Empty pieces are skipped and a
+/// piece without `=` is a name with an empty value, as the URL Standard
+/// says.
+fn split_pairs(input: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    return input.split(|b| *b == b'&')
+        .filter(|piece| !piece.is_empty())
+        .map(|piece| match piece.iter().position(|b| *b == b'=') {
+            Some(index) => (&piece[..index], &piece[index + 1..]),
+            None => (piece, &piece[piece.len()..]),
+        });
+}
+
+/// `+` to space and `%XX` to bytes. Returns the offsets of bad escapes,
+/// which are kept as they are.
+fn percent_decode(input: &[u8]) -> (Vec<u8>, Vec<usize>) {
+    let mut output: Vec<u8> = Vec::with_capacity(input.len());
+    let mut bad: Vec<usize> = Vec::new();
+    let hex = |b: u8| (b as char).to_digit(16);
+    let mut i = 0;
+    while i < input.len() {
+        match input[i] {
+            b'+' => output.push(b' '),
+            b'%' => match (input.get(i + 1).and_then(|b| hex(*b)),
+                input.get(i + 2).and_then(|b| hex(*b))) {
+                (Some(high), Some(low)) => {
+                    output.push((high << 4 | low) as u8);
+                    i += 3;
+                    continue;
+                }
+                _ => {
+                    bad.push(i);
+                    output.push(b'%');
+                }
+            },
+            byte => output.push(byte),
+        }
+        i += 1;
+    }
+    return (output, bad);
+}
+
+fn decode_part(input: &[u8], charset: Charset, field: usize, part: Part,
+    problems: &mut Vec<Problem>) -> String
+{
+    let (bytes, bad) = percent_decode(input);
+    for offset in bad {
+        problems.push(Problem::BadEscape { field, part, offset });
+    }
+    let decoded = charset.decode(&bytes);
+    for error in decoded.errors {
+        problems.push(Problem::Undecodable { field, part, error });
+    }
+    return decoded.text;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn charset_from_content_type_and_field() {
+        let form = decode_form(b"a=caf%E9",
+            "application/x-www-form-urlencoded; charset=iso-8859-1", None);
+        assert_eq!(form.charset, Charset::Windows1252);
+        assert_eq!(form.source, CharsetSource::ContentType);
+        assert_eq!(form.get("a"), Some("caf\u{e9}"));
+        let form = decode_form(b"_charset_=windows-1250&a=%8A", "", None);
+        assert_eq!(form.source, CharsetSource::CharsetField);
+        assert_eq!(form.get("a"), Some("\u{160}"));
+        assert!(form.problems.is_empty());
+    }
+
+    #[test]
+    fn unsupported_charset_falls_back_to_utf8() {
+        let form = decode_form(b"_charset_=foo&a=%C3%A9&b=%E9", "", None);
+        assert_eq!(form.charset, Charset::Utf8);
+        assert_eq!(form.get("a"), Some("\u{e9}"));
+        assert_eq!(form.get("b"), Some("\u{FFFD}"));
+        assert_eq!(form.problems[0], Problem::UnsupportedCharset {
+            label: String::from("foo"),
+            source: CharsetSource::CharsetField,
+        });
+        assert!(matches!(form.problems[1],
+            Problem::Undecodable { field: 2, part: Part::Value, .. }));
+    }
+
+    #[test]
+    fn bad_escapes_are_kept() {
+        let form = decode_form(b"a=100%&b+c=d%2", "", None);
+        assert_eq!(form.fields, vec![
+            (String::from("a"), String::from("100%")),
+            (String::from("b c"), String::from("d%2")),
+        ]);
+        assert_eq!(form.problems.len(), 2);
+    }
+
+    #[test]
+    fn text_plain() {
+        let form = decode_text_plain(b"a=1\r\nb=x=y\r\nmore\r\n",
+            "text/plain", None);
+        assert_eq!(form.fields, vec![
+            (String::from("a"), String::from("1")),
+            (String::from("b"), String::from("x=y\r\nmore")),
+        ]);
+        assert_eq!(form.problems, vec![
+            Problem::Ambiguous { field: 1, reason: Ambiguity::ExtraEquals },
+            Problem::Ambiguous { field: 1, reason: Ambiguity::Continuation },
+        ]);
+    }
+}
@@ -15,6 +15,8 @@
 
 use std::collections::HashMap;
 
+use crate::decode_urlencoded::decode_urlencoded;
+
 /// The buffer needs to have the entire input.
 ///
 /// Per RFC 3986 a query string can contain just about any any ASCII character.
@@ -45,16 +47,14 @@ pub fn decode_query_string(buffer: &[u8]) -> HashMap<String, String> {
             }
             let mut key: String;
             let mut value: String;
-            if first.is_some() {
-                let first = first.unwrap();
+            if let Some(first) = first {
                 key = String::from_utf8_lossy(first).into_owned();
                 key = String::from(key.trim());
             } else {
                 // Skip this item. It is not valid.
                 continue;
             }
-            if second.is_some() {
-                let second = second.unwrap();
+            if let Some(second) = second {
                 value = String::from_utf8_lossy(second).into_owned();
                 value = String::from(value.trim());
             } else {
@@ -69,3 +69,50 @@ pub fn decode_query_string(buffer: &[u8]) -> HashMap<String, String> {
 
     return output;
 }
+
+/// Runs the output of decode_query_string through decode_urlencoded.
+///
+/// Items whose key or value is not valid percent encoding are dropped, so this
+/// is meant for the routes that only care about a few well known parameters.
+pub fn decode_query_parameters(buffer: &[u8]) -> HashMap<String, String> {
+    let mut output: HashMap<String, String> = HashMap::with_capacity(16);
+    for (key, value) in decode_query_string(buffer) {
+        match (decode_urlencoded(key), decode_urlencoded(value)) {
+            (Some(key), Some(value)) => {
+                output.insert(key, value);
+            }
+            _ => {
+                continue;
+            }
+        }
+    }
+    return output;
+}
+
+/// Every key in the query string, in order, including bare flags like
+/// `delete?session&theme` that decode_query_string skips because they have no
+/// `=`. Keys that are not valid percent encoding are dropped.
+pub fn decode_query_keys(buffer: &[u8]) -> Vec<String> {
+    let mut output: Vec<String> = Vec::with_capacity(16);
+    for x in buffer.split(|a| *a == b'&') {
+        let key = match x.split(|a| *a == b'=').next() {
+            Some(key) => String::from_utf8_lossy(key).trim().to_string(),
+            None => continue,
+        };
+        if key.is_empty() {
+            continue;
+        }
+        match decode_urlencoded(key) {
+            Some(key) => output.push(key),
+            None => continue,
+        }
+    }
+    return output;
+}
+
+/// True if a CONTENT_TYPE value is application/x-www-form-urlencoded, with or
+/// without parameters such as charset.
+pub fn is_form_urlencoded(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or("").trim();
+    return essence.eq_ignore_ascii_case("application/x-www-form-urlencoded");
+}
@@ -71,29 +71,26 @@ pub fn decode_urlencoded(input: String) -> Option<String> {
                     None => { return None; }
                     Some(d) => {
                         temp_number = d;
-                        temp_number = temp_number << 4;
+                        temp_number <<= 4;
                     }
                 }
                 match s.to_digit(16) {
                     None => { return None; }
                     Some(d) => {
-                        temp_number = temp_number + d;
+                        temp_number += d;
                     }
                 }
 
-                match char::from_u32(temp_number) {
-                    Some(c) => {
-                        output.push(c);
-                        continue;
-                    }
-                    None => {}
+                if let Some(c) = char::from_u32(temp_number) {
+                    output.push(c);
+                    continue;
                 }
 
                 if (temp_number & FOUR_BYTE_DETECT) == FOUR_BYTE_DETECT {
-                    temp_number = temp_number & FOUR_BYTE_DATA;
+                    temp_number &= FOUR_BYTE_DATA;
                     let mut chars: [Option<char>; 9] = [None; 9];
-                    for i in 0..chars.len() {
-                        chars[i] = characters.next();
+                    for c in chars.iter_mut() {
+                        *c = characters.next();
                     }
                     let mut bytes: [u32; 9] = [0; 9];
                     for i in 0..chars.len() {
@@ -127,8 +124,8 @@ pub fn decode_urlencoded(input: String) -> Option<String> {
                     // Six more characters have been read and converted to
                     // nibbles.
                     for i in [1,4,7] {
-                        bytes[i] = bytes[i] << 4;
-                        bytes[i] = bytes[i] + bytes[i+1];
+                        bytes[i] <<= 4;
+                        bytes[i] += bytes[i+1];
                     }
                     if (bytes[1] & bytes[4] & bytes[7] & FOLLOWING_BYTE_DETECT)
                         != FOLLOWING_BYTE_DETECT {
@@ -138,9 +135,8 @@ pub fn decode_urlencoded(input: String) -> Option<String> {
                         }
                     //Okay we have finally validated everything.
                     for i in [1,4,7] {
-                        temp_number = temp_number << FOLLOWING_BYTE_SHIFT;
-                        temp_number = temp_number + (bytes[i] &
-                            FOLLOWING_BYTE_DATA);
+                        temp_number <<= FOLLOWING_BYTE_SHIFT;
+                        temp_number += bytes[i] & FOLLOWING_BYTE_DATA;
                     }
                     match char::from_u32(temp_number) {
                         // All that work and the character is not valid.
@@ -151,10 +147,10 @@ pub fn decode_urlencoded(input: String) -> Option<String> {
                 }
 
                 if (temp_number & THREE_BYTE_DETECT) == THREE_BYTE_DETECT {
-                    temp_number = temp_number & THREE_BYTE_DATA;
+                    temp_number &= THREE_BYTE_DATA;
                     let mut chars: [Option<char>; 6] = [None; 6];
-                    for i in 0..chars.len() {
-                        chars[i] = characters.next();
+                    for c in chars.iter_mut() {
+                        *c = characters.next();
                     }
                     let mut bytes: [u32; 6] = [0; 6];
                     for i in 0.. chars.len() {
@@ -188,8 +184,8 @@ pub fn decode_urlencoded(input: String) -> Option<String> {
                     // Four more characters have been read and converted to
                     // nibbles.
                     for i in [1,4] {
-                        bytes[i] = bytes[i] << 4;
-                        bytes[i] = bytes[i] + bytes[i+1];
+                        bytes[i] <<= 4;
+                        bytes[i] += bytes[i+1];
                     }
                     if (bytes[1] & bytes[4] & FOLLOWING_BYTE_DETECT)
                         != FOLLOWING_BYTE_DETECT {
@@ -199,9 +195,8 @@ pub fn decode_urlencoded(input: String) -> Option<String> {
                         }
                     //Okay we have finally validated everything.
                     for i in [1,4] {
-                        temp_number = temp_number << FOLLOWING_BYTE_SHIFT;
-                        temp_number = temp_number + (bytes[i] &
-                            FOLLOWING_BYTE_DATA);
+                        temp_number <<= FOLLOWING_BYTE_SHIFT;
+                        temp_number += bytes[i] & FOLLOWING_BYTE_DATA;
                     }
                     match char::from_u32(temp_number) {
                         // All that work and the character is not valid.
@@ -211,10 +206,10 @@ pub fn decode_urlencoded(input: String) -> Option<String> {
                     continue;
                 }
                 if (temp_number & TWO_BYTE_DETECT) == TWO_BYTE_DETECT {
-                    temp_number = temp_number & TWO_BYTE_DATA;
+                    temp_number &= TWO_BYTE_DATA;
                     let mut chars: [Option<char>; 9] = [None; 9];
-                    for i in 0..chars.len() {
-                        chars[i] = characters.next();
+                    for c in chars.iter_mut() {
+                        *c = characters.next();
                     }
                     let mut bytes: [u32; 3] = [0; 3];
                     for i in 0.. chars.len() {
@@ -247,10 +242,8 @@ pub fn decode_urlencoded(input: String) -> Option<String> {
                     }
                     // Three more characters have been read and converted to
                     // nibbles.
-                    for i in [1] {
-                        bytes[i] = bytes[i] << 4;
-                        bytes[i] = bytes[i] + bytes[i+1];
-                    }
+                    bytes[1] <<= 4;
+                    bytes[1] += bytes[2];
                     if (bytes[1] & FOLLOWING_BYTE_DETECT)
                         != FOLLOWING_BYTE_DETECT {
                             // One of the following bytes is not a valid
@@ -258,11 +251,8 @@ pub fn decode_urlencoded(input: String) -> Option<String> {
                             return None;
                         }
                     //Okay we have finally validated everything.
-                    for i in [1] {
-                        temp_number = temp_number << FOLLOWING_BYTE_SHIFT;
-                        temp_number = temp_number + (bytes[i] &
-                            FOLLOWING_BYTE_DATA);
-                    }
+                    temp_number <<= FOLLOWING_BYTE_SHIFT;
+                    temp_number += bytes[1] & FOLLOWING_BYTE_DATA;
                     match char::from_u32(temp_number) {
                         // All that work and the character is not valid.
                         None => { return None; }
@@ -281,3 +271,52 @@ pub fn decode_urlencoded(input: String) -> Option<String> {
     }
     return Some(output);
 }
+
+/// Removes percent encoding without interpreting the result as any character
+/// set. `+` is left alone because it only means space in form data.
+///
+/// None if a `%` is not followed by two hex digits.
+pub fn decode_percent_bytes(input: &str) -> Option<Vec<u8>> {
+    let input = input.as_bytes();
+    let mut output: Vec<u8> = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        if input[i] != b'%' {
+            output.push(input[i]);
+            i += 1;
+            continue;
+        }
+        if i + 2 >= input.len() {
+            return None;
+        }
+        let high = (input[i + 1] as char).to_digit(16)?;
+        let low = (input[i + 2] as char).to_digit(16)?;
+        output.push(((high << 4) | low) as u8);
+        i += 3;
+    }
+    return Some(output);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urlencoded() {
+        let decode = |input: &str| decode_urlencoded(String::from(input));
+        assert_eq!(decode("a+b%21"), Some(String::from("a b!")));
+        // Each escape is one Latin-1 character, as it always has been.
+        assert_eq!(decode("caf%E9"), Some(String::from("café")));
+        assert_eq!(decode("50%"), Some(String::from("50")));
+        assert_eq!(decode("%zz"), None);
+        assert_eq!(decode("%4"), None);
+    }
+
+    #[test]
+    fn percent_bytes() {
+        assert_eq!(decode_percent_bytes("a+%e9%2F"),
+            Some(b"a+\xe9/".to_vec()));
+        assert_eq!(decode_percent_bytes("%"), None);
+        assert_eq!(decode_percent_bytes("%g0"), None);
+    }
+}
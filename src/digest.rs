@@ -0,0 +1,37 @@
+// src/digest.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+/// A cryptographic hash function that can be fed data a piece at a time.
+///
+/// The hashes are implemented in this crate so testcgi keeps building with no
+/// dependencies. They are checked against the published test vectors, not
+/// hardened against side channels beyond what HMAC verification needs.
+pub trait Digest: Sized {
+    /// The block size in bytes, which HMAC needs to pad its key.
+    const BLOCK_SIZE: usize;
+    /// The length of the finished hash in bytes.
+    const OUTPUT_SIZE: usize;
+
+    fn new() -> Self;
+    fn update(&mut self, data: &[u8]);
+    fn finish(self) -> Vec<u8>;
+
+    /// Hashes `data` in one go.
+    fn digest(data: &[u8]) -> Vec<u8> {
+        let mut hasher = Self::new();
+        hasher.update(data);
+        return hasher.finish();
+    }
+}
@@ -0,0 +1,702 @@
+// src/digest_auth.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! HTTP Digest authentication from RFC 7616.
+//!
+//! Nonces are not stored. Each one is the time it was issued, some random
+//! bytes and an HMAC-SHA256 of both under a server secret, so any testcgi
+//! process can check a nonce another one handed out and tell when it has
+//! gone stale. The nonce count a client sends with each request is the one
+//! thing that does need remembering, and that goes in a small file per
+//! nonce.
+
+use std::env;
+use std::fs;
+use std::fs::DirBuilder;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use crate::authorization::authorization_header;
+use crate::authorization::missing_header_hint;
+use crate::authorization::parse_auth_params;
+use crate::authorization::quote;
+use crate::authorization::strip_scheme;
+use crate::base64;
+use crate::config;
+use crate::digest::Digest;
+use crate::hex;
+use crate::hmac::constant_time_eq;
+use crate::hmac::hmac;
+use crate::http_date::unix_seconds;
+use crate::md5::Md5;
+use crate::random;
+use crate::response::Response;
+use crate::sha256::Sha256;
+use crate::url::Url;
+
+/// How long a nonce is good for before the client is told it is stale.
+pub const DEFAULT_NONCE_LIFETIME: Duration = Duration::from_secs(300);
+
+/// Random bytes in a nonce, so two challenges in the same second differ.
+const NONCE_RANDOM_BYTES: usize = 8;
+
+/// Bytes of HMAC kept in a nonce. 128 bits is plenty for a MAC that only has
+/// to last a few minutes.
+const NONCE_MAC_BYTES: usize = 16;
+
+/// Roughly one request in this many sweeps out old nonce count files.
+const GC_ONE_IN: u32 = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Algorithm {
+    Md5,
+    Md5Sess,
+    Sha256,
+    Sha256Sess,
+}
+
+impl Algorithm {
+    /// RFC 7616 says the algorithm name is case insensitive. A missing
+    /// algorithm means MD5.
+    pub fn parse(input: &str) -> Option<Algorithm> {
+        return [Algorithm::Md5, Algorithm::Md5Sess, Algorithm::Sha256,
+            Algorithm::Sha256Sess]
+            .into_iter()
+            .find(|algorithm| input.eq_ignore_ascii_case(algorithm.name()));
+    }
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Algorithm::Md5 => "MD5",
+            Algorithm::Md5Sess => "MD5-sess",
+            Algorithm::Sha256 => "SHA-256",
+            Algorithm::Sha256Sess => "SHA-256-sess",
+        };
+    }
+
+    pub fn is_session(&self) -> bool {
+        return matches!(self, Algorithm::Md5Sess | Algorithm::Sha256Sess);
+    }
+
+    /// The hex encoded hash of `data`, which is what every Digest step uses.
+    pub fn hash(&self, data: &str) -> String {
+        return match self {
+            Algorithm::Md5 | Algorithm::Md5Sess => {
+                hex::encode(&Md5::digest(data.as_bytes()))
+            }
+            Algorithm::Sha256 | Algorithm::Sha256Sess => {
+                hex::encode(&Sha256::digest(data.as_bytes()))
+            }
+        };
+    }
+}
+
+/// The parameters of an `Authorization: Digest` header.
+#[derive(Debug)]
+pub struct Credentials {
+    pub username: String,
+    pub realm: String,
+    pub nonce: String,
+    pub uri: String,
+    pub response: String,
+    pub algorithm: Algorithm,
+    pub cnonce: Option<String>,
+    pub opaque: Option<String>,
+    pub qop: Option<String>,
+    pub nc: Option<String>,
+    pub userhash: bool,
+}
+
+/// Parses an `Authorization: Digest ...` header. The error says which
+/// parameter is missing or wrong.
+pub fn parse_authorization(header: &str) -> Result<Credentials, String> {
+    let params = match strip_scheme(header, "Digest") {
+        Some(params) => params,
+        None => return Err(String::from("The scheme is not Digest.")),
+    };
+    let params = match parse_auth_params(params) {
+        Some(params) => params,
+        None => return Err(String::from("The auth-params do not parse.")),
+    };
+    let find = |name: &str| -> Option<String> {
+        return params.iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.clone());
+    };
+    let require = |name: &str| -> Result<String, String> {
+        return find(name).ok_or(format!("The {name} parameter is missing."));
+    };
+    let algorithm = match find("algorithm") {
+        None => Algorithm::Md5,
+        Some(name) => match Algorithm::parse(&name) {
+            Some(algorithm) => algorithm,
+            None => return Err(format!("Unsupported algorithm {name:?}.")),
+        },
+    };
+    return Ok(Credentials {
+        username: require("username")?,
+        realm: require("realm")?,
+        nonce: require("nonce")?,
+        uri: require("uri")?,
+        response: require("response")?,
+        algorithm,
+        cnonce: find("cnonce"),
+        opaque: find("opaque"),
+        qop: find("qop"),
+        nc: find("nc"),
+        userhash: find("userhash")
+            .map(|v| v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false),
+    });
+}
+
+pub enum NonceCheck {
+    /// Not one of ours, or tampered with.
+    Invalid,
+    /// Ours, but older than the nonce lifetime. The age is in seconds.
+    Stale(i64),
+    /// Ours and still good.
+    Fresh(i64),
+}
+
+/// Makes and checks nonces with an HMAC over the issue time, the realm and
+/// the algorithm the challenge offered.
+pub struct NonceAuthority {
+    secret: Vec<u8>,
+    realm: String,
+    lifetime: Duration,
+}
+
+impl NonceAuthority {
+    pub fn new(secret: &[u8], realm: &str) -> NonceAuthority {
+        return NonceAuthority {
+            secret: secret.to_vec(),
+            realm: String::from(realm),
+            lifetime: DEFAULT_NONCE_LIFETIME,
+        };
+    }
+
+    pub fn lifetime(mut self, lifetime: Duration) -> NonceAuthority {
+        self.lifetime = lifetime;
+        return self;
+    }
+
+    fn mac(&self, payload: &[u8], algorithm: Algorithm) -> Vec<u8> {
+        let mut message = payload.to_vec();
+        message.extend_from_slice(self.realm.as_bytes());
+        message.push(b':');
+        message.extend_from_slice(algorithm.name().as_bytes());
+        let mut mac = hmac::<Sha256>(&self.secret, &message);
+        mac.truncate(NONCE_MAC_BYTES);
+        return mac;
+    }
+
+    /// A new nonce for a challenge offering `algorithm`, issued at `now`.
+    pub fn create(&self, algorithm: Algorithm, now: SystemTime)
+        -> io::Result<String>
+    {
+        let mut payload = unix_seconds(now).to_be_bytes().to_vec();
+        payload.extend_from_slice(&random::bytes(NONCE_RANDOM_BYTES)?);
+        let mac = self.mac(&payload, algorithm);
+        payload.extend_from_slice(&mac);
+        return Ok(base64::encode_url(&payload));
+    }
+
+    /// A nonce made for another algorithm is Invalid, so it cannot be
+    /// answered with a weaker one.
+    pub fn check(&self, nonce: &str, algorithm: Algorithm, now: SystemTime)
+        -> NonceCheck
+    {
+        let payload_length = 8 + NONCE_RANDOM_BYTES;
+        let bytes = match base64::decode_url(nonce) {
+            Some(bytes) if bytes.len() == payload_length + NONCE_MAC_BYTES => {
+                bytes
+            }
+            _ => return NonceCheck::Invalid,
+        };
+        let (payload, mac) = bytes.split_at(payload_length);
+        if !constant_time_eq(mac, &self.mac(payload, algorithm)) {
+            return NonceCheck::Invalid;
+        }
+        let mut issued_bytes = [0u8; 8];
+        issued_bytes.copy_from_slice(&payload[..8]);
+        let age = unix_seconds(now) - i64::from_be_bytes(issued_bytes);
+        if age < 0 || age > self.lifetime.as_secs() as i64 {
+            return NonceCheck::Stale(age);
+        }
+        return NonceCheck::Fresh(age);
+    }
+
+    /// The opaque value is fixed per realm and only has to come back
+    /// unchanged.
+    pub fn opaque(&self) -> String {
+        let mut mac = hmac::<Sha256>(&self.secret,
+            format!("opaque:{}", self.realm).as_bytes());
+        mac.truncate(NONCE_MAC_BYTES);
+        return base64::encode_url(&mac);
+    }
+}
+
+/// The WWW-Authenticate value offering `algorithm`.
+pub fn challenge(realm: &str, nonce: &str, opaque: &str, algorithm: Algorithm,
+    stale: bool) -> String
+{
+    let mut value = format!("Digest realm={}, qop=\"auth\", algorithm={}, \
+        nonce={}, opaque={}", quote(realm), algorithm.name(), quote(nonce),
+        quote(opaque));
+    if stale {
+        value.push_str(", stale=true");
+    }
+    return value;
+}
+
+/// Remembers the highest nonce count used with each nonce so a captured
+/// request cannot be played back.
+pub struct NonceCounts {
+    directory: PathBuf,
+    lifetime: Duration,
+}
+
+impl NonceCounts {
+    /// Files older than `lifetime` belong to stale nonces and get removed.
+    pub fn new(directory: impl Into<PathBuf>, lifetime: Duration)
+        -> NonceCounts
+    {
+        return NonceCounts { directory: directory.into(), lifetime };
+    }
+
+    /// Records `nc` for `nonce`. Returns the count seen before, or an error
+    /// of kind AlreadyExists if `nc` is not higher than it.
+    pub fn advance(&self, nonce: &str, nc: u32) -> io::Result<u32> {
+        DirBuilder::new().recursive(true).mode(0o700)
+            .create(&self.directory)?;
+        let name = hex::encode(&Sha256::digest(nonce.as_bytes()));
+        let path = self.directory.join(format!("nc_{}", &name[..32]));
+        let mut file = OpenOptions::new().read(true).write(true).create(true)
+            .truncate(false).mode(0o600).open(path)?;
+        file.lock()?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        let previous: u32 = contents.trim().parse().unwrap_or(0);
+        if nc <= previous {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                format!("nc {nc:08x} was already used; the last one was \
+                    {previous:08x}.")));
+        }
+        file.seek(SeekFrom::Start(0))?;
+        file.set_len(0)?;
+        write!(file, "{nc}")?;
+        return Ok(previous);
+    }
+
+    /// Removes count files for nonces that are long past stale, on about one
+    /// call in a hundred.
+    pub fn maybe_collect_garbage(&self) -> io::Result<()> {
+        let mut roll = [0u8; 4];
+        random::fill(&mut roll)?;
+        if u32::from_le_bytes(roll) % GC_ONE_IN != 0 {
+            return Ok(());
+        }
+        let entries = match fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let cutoff = SystemTime::now() - self.lifetime * 2;
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_name().to_string_lossy().starts_with("nc_") {
+                continue;
+            }
+            let modified = entry.metadata()?.modified()?;
+            if modified < cutoff {
+                fs::remove_file(entry.path())?;
+            }
+        }
+        return Ok(());
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Authenticated,
+    /// The response was right but the nonce has expired. The client should
+    /// retry with a new one without asking the user again.
+    Stale,
+    Rejected,
+}
+
+/// The result of verify, with a line for every step so the diagnostic
+/// route can show exactly where things went wrong.
+pub struct Verification {
+    pub outcome: Outcome,
+    pub steps: Vec<String>,
+}
+
+/// What the server knows about the request being checked.
+pub struct Expected<'a> {
+    /// The algorithm the challenge offered. Credentials using any other one
+    /// are rejected.
+    pub algorithm: Algorithm,
+    pub user: &'a str,
+    pub password: &'a str,
+    pub realm: &'a str,
+    pub method: &'a str,
+    pub uri: &'a str,
+}
+
+pub fn verify(credentials: &Credentials, expected: &Expected,
+    authority: &NonceAuthority, counts: &NonceCounts, now: SystemTime)
+    -> Verification
+{
+    let mut steps: Vec<String> = Vec::new();
+    let algorithm = credentials.algorithm;
+    let reject = |mut steps: Vec<String>, reason: String| -> Verification {
+        steps.push(format!("REJECTED: {reason}"));
+        return Verification { outcome: Outcome::Rejected, steps };
+    };
+    steps.push(format!("algorithm = {}", algorithm.name()));
+    if algorithm != expected.algorithm {
+        return reject(steps, format!("the challenge asked for {}",
+            expected.algorithm.name()));
+    }
+
+    let expected_username = if credentials.userhash {
+        algorithm.hash(&format!("{}:{}", expected.user, expected.realm))
+    } else {
+        String::from(expected.user)
+    };
+    steps.push(format!("username = {:?}{}", credentials.username,
+        if credentials.userhash { " (userhash)" } else { "" }));
+    if !constant_time_eq(credentials.username.as_bytes(),
+        expected_username.as_bytes()) {
+        return reject(steps, format!("expected username {:?}",
+            expected_username));
+    }
+    steps.push(format!("realm = {:?}", credentials.realm));
+    if credentials.realm != expected.realm {
+        return reject(steps, format!("expected realm {:?}", expected.realm));
+    }
+    steps.push(format!("uri = {:?}", credentials.uri));
+    if credentials.uri != expected.uri {
+        return reject(steps, format!("the request URI is {:?}",
+            expected.uri));
+    }
+    if let Some(opaque) = &credentials.opaque {
+        steps.push(format!("opaque = {opaque:?}"));
+        if *opaque != authority.opaque() {
+            return reject(steps, String::from("opaque was changed"));
+        }
+    }
+
+    let check = authority.check(&credentials.nonce, algorithm, now);
+    let stale: bool = match check {
+        NonceCheck::Invalid => {
+            steps.push(format!("nonce = {:?}", credentials.nonce));
+            return reject(steps, String::from("the nonce was not issued by \
+                this server or was tampered with"));
+        }
+        NonceCheck::Stale(age) => {
+            steps.push(format!("nonce = {:?}, issued {age} seconds ago, stale",
+                credentials.nonce));
+            true
+        }
+        NonceCheck::Fresh(age) => {
+            steps.push(format!("nonce = {:?}, issued {age} seconds ago, fresh",
+                credentials.nonce));
+            false
+        }
+    };
+
+    let qop = match credentials.qop.as_deref() {
+        Some("auth") => "auth",
+        Some(other) => {
+            return reject(steps, format!("qop {other:?} is not supported, \
+                only auth"));
+        }
+        None => {
+            return reject(steps, String::from("qop is missing; RFC 2069 \
+                style Digest without qop is not accepted"));
+        }
+    };
+    let (cnonce, nc) = match (&credentials.cnonce, &credentials.nc) {
+        (Some(cnonce), Some(nc)) => (cnonce.as_str(), nc.as_str()),
+        _ => {
+            return reject(steps, String::from("qop=auth needs both cnonce \
+                and nc"));
+        }
+    };
+    // from_str_radix alone would also take a leading +.
+    let is_hex = nc.len() == 8 && nc.bytes().all(|b| b.is_ascii_hexdigit());
+    let nc_value = match u32::from_str_radix(nc, 16) {
+        Ok(value) if is_hex => value,
+        _ => {
+            return reject(steps, format!("nc {nc:?} is not 8 hex digits"));
+        }
+    };
+    steps.push(format!("qop = {qop}, cnonce = {cnonce:?}, nc = {nc}"));
+
+    let a1 = format!("{}:{}:{}", expected.user, expected.realm,
+        expected.password);
+    let mut ha1 = algorithm.hash(&a1);
+    steps.push(format!("HA1 = H({a1:?}) = {ha1}"));
+    if algorithm.is_session() {
+        let session_a1 = format!("{ha1}:{}:{cnonce}", credentials.nonce);
+        ha1 = algorithm.hash(&session_a1);
+        steps.push(format!("HA1 = H({session_a1:?}) = {ha1}"));
+    }
+    let a2 = format!("{}:{}", expected.method, credentials.uri);
+    let ha2 = algorithm.hash(&a2);
+    steps.push(format!("HA2 = H({a2:?}) = {ha2}"));
+    let kd = format!("{ha1}:{}:{nc}:{cnonce}:{qop}:{ha2}", credentials.nonce);
+    let response = algorithm.hash(&kd);
+    steps.push(format!("expected response = H({kd:?}) = {response}"));
+    steps.push(format!("client response = {}", credentials.response));
+    if !constant_time_eq(response.as_bytes(),
+        credentials.response.to_ascii_lowercase().as_bytes()) {
+        return reject(steps, String::from("the responses do not match"));
+    }
+
+    if stale {
+        steps.push(String::from("STALE: the response is right but the nonce \
+            expired, so the client should retry with the new nonce"));
+        return Verification { outcome: Outcome::Stale, steps };
+    }
+    match counts.advance(&credentials.nonce, nc_value) {
+        Ok(previous) => {
+            steps.push(format!("nc {nc_value:08x} is above the last one seen, \
+                {previous:08x}"));
+        }
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            return reject(steps, format!("replay: {e}"));
+        }
+        Err(e) => {
+            return reject(steps, format!("cannot record the nonce count: \
+                {e}"));
+        }
+    }
+    steps.push(String::from("AUTHENTICATED"));
+    return Verification { outcome: Outcome::Authenticated, steps };
+}
+
+/// `/digest-auth/auth/{user}/{pass}[/{algorithm}]` wants Digest credentials
+/// for the user and password in the path. The body lists every step of the
+/// check, whether it passes or not.
+pub fn route(qop: &str, user: &str, password: &str, algorithm: Option<&str>)
+    -> Response
+{
+    let realm = "testcgi";
+    if qop != "auth" {
+        return Response::text(400, "Only qop=auth is supported.");
+    }
+    let algorithm = match algorithm {
+        None => Algorithm::Md5,
+        Some(name) => match Algorithm::parse(name) {
+            Some(algorithm) => algorithm,
+            None => {
+                return Response::text(400, "The algorithm must be MD5, \
+                    MD5-sess, SHA-256 or SHA-256-sess.");
+            }
+        },
+    };
+    let secret = match config::get("TESTCGI_SECRET") {
+        Some(secret) => secret,
+        None => {
+            return Response::text(500,
+                "Digest authentication needs TESTCGI_SECRET to be set.");
+        }
+    };
+    let directory: PathBuf = match config::get("TESTCGI_NONCE_DIR") {
+        Some(directory) => PathBuf::from(directory),
+        None => env::temp_dir().join("testcgi-nonces"),
+    };
+    let authority = NonceAuthority::new(secret.as_bytes(), realm);
+    let counts = NonceCounts::new(directory, DEFAULT_NONCE_LIFETIME);
+    let now = SystemTime::now();
+    let unauthorized = |stale: bool, body: &str| -> Response {
+        let nonce = match authority.create(algorithm, now) {
+            Ok(nonce) => nonce,
+            Err(e) => {
+                return Response::text(500,
+                    &format!("Cannot make a nonce: {e}"));
+            }
+        };
+        let mut response = Response::text(401, body);
+        response.header("WWW-Authenticate", &challenge(realm, &nonce,
+            &authority.opaque(), algorithm, stale));
+        return response;
+    };
+
+    if let Err(e) = counts.maybe_collect_garbage() {
+        return Response::text(500,
+            &format!("Cannot clean up old nonce counts: {e}"));
+    }
+    let header = match authorization_header() {
+        Some(header) => header,
+        None => return unauthorized(false, &missing_header_hint()),
+    };
+    let credentials = match parse_authorization(&header) {
+        Ok(credentials) => credentials,
+        Err(reason) => return unauthorized(false, &reason),
+    };
+    let method = env::var("REQUEST_METHOD").unwrap_or_default();
+    let uri = Url::from_env().request_target();
+    let expected = Expected { algorithm, user, password, realm,
+        method: &method, uri: &uri };
+    let verification = verify(&credentials, &expected, &authority, &counts,
+        now);
+    let body = verification.steps.join("\n");
+    return match verification.outcome {
+        Outcome::Authenticated => Response::text(200, &body),
+        Outcome::Stale => unauthorized(true, &body),
+        Outcome::Rejected => unauthorized(false, &body),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER: &str = "Mufasa";
+    const PASSWORD: &str = "Circle of Life";
+    const REALM: &str = "http-auth@example.org";
+    const URI: &str = "/dir/index.html";
+    const CNONCE: &str = "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ";
+
+    /// The response a client would send, the same way verify works it out.
+    fn response_for(algorithm: Algorithm, nonce: &str, nc: &str) -> String {
+        let ha1 = algorithm.hash(&format!("{USER}:{REALM}:{PASSWORD}"));
+        let ha2 = algorithm.hash(&format!("GET:{URI}"));
+        return algorithm.hash(&format!("{ha1}:{nonce}:{nc}:{CNONCE}:auth:\
+            {ha2}"));
+    }
+
+    fn credentials(algorithm: Algorithm, nonce: &str, nc: &str)
+        -> Credentials
+    {
+        let header = format!("Digest username=\"{USER}\", realm=\"{REALM}\", \
+            uri=\"{URI}\", algorithm={}, nonce=\"{nonce}\", nc={nc}, \
+            cnonce=\"{CNONCE}\", qop=auth, response=\"{}\"",
+            algorithm.name(), response_for(algorithm, nonce, nc));
+        return parse_authorization(&header).unwrap();
+    }
+
+    fn expected(algorithm: Algorithm) -> Expected<'static> {
+        return Expected { algorithm, user: USER, password: PASSWORD,
+            realm: REALM, method: "GET", uri: URI };
+    }
+
+    fn counts(name: &str) -> NonceCounts {
+        let directory = env::temp_dir().join(format!("testcgi-digest-{}-{name}",
+            std::process::id()));
+        return NonceCounts::new(directory, DEFAULT_NONCE_LIFETIME);
+    }
+
+    /// The MD5 and SHA-256 examples in RFC 7616 section 3.9.1.
+    #[test]
+    fn rfc_7616_example() {
+        let nonce = "7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v";
+        assert_eq!(response_for(Algorithm::Md5, nonce, "00000001"),
+            "8ca523f5e9506fed4657c9700eebdbec");
+        assert_eq!(response_for(Algorithm::Sha256, nonce, "00000001"),
+            "753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1");
+    }
+
+    #[test]
+    fn authenticates_and_stops_replays() {
+        let authority = NonceAuthority::new(b"secret", REALM);
+        let counts = counts("replay");
+        let now = SystemTime::now();
+        let nonce = authority.create(Algorithm::Sha256, now).unwrap();
+        let first = credentials(Algorithm::Sha256, &nonce, "00000001");
+        let verification = verify(&first, &expected(Algorithm::Sha256),
+            &authority, &counts, now);
+        assert_eq!(verification.outcome, Outcome::Authenticated);
+        let verification = verify(&first, &expected(Algorithm::Sha256),
+            &authority, &counts, now);
+        assert_eq!(verification.outcome, Outcome::Rejected);
+        let second = credentials(Algorithm::Sha256, &nonce, "00000002");
+        let verification = verify(&second, &expected(Algorithm::Sha256),
+            &authority, &counts, now);
+        assert_eq!(verification.outcome, Outcome::Authenticated);
+        _ = fs::remove_dir_all(&counts.directory);
+    }
+
+    #[test]
+    fn no_algorithm_downgrade() {
+        let authority = NonceAuthority::new(b"secret", REALM);
+        let counts = counts("downgrade");
+        let now = SystemTime::now();
+        let nonce = authority.create(Algorithm::Sha256, now).unwrap();
+        let md5 = credentials(Algorithm::Md5, &nonce, "00000001");
+        let verification = verify(&md5, &expected(Algorithm::Sha256),
+            &authority, &counts, now);
+        assert_eq!(verification.outcome, Outcome::Rejected);
+        // Even where MD5 is expected, a nonce made for SHA-256 is not good.
+        let verification = verify(&md5, &expected(Algorithm::Md5),
+            &authority, &counts, now);
+        assert_eq!(verification.outcome, Outcome::Rejected);
+        assert!(matches!(authority.check(&nonce, Algorithm::Md5, now),
+            NonceCheck::Invalid));
+        _ = fs::remove_dir_all(&counts.directory);
+    }
+
+    #[test]
+    fn nonces() {
+        let authority = NonceAuthority::new(b"secret", REALM);
+        let now = SystemTime::now();
+        let nonce = authority.create(Algorithm::Md5, now).unwrap();
+        assert!(matches!(authority.check(&nonce, Algorithm::Md5, now),
+            NonceCheck::Fresh(0)));
+        let later = now + DEFAULT_NONCE_LIFETIME + Duration::from_secs(1);
+        assert!(matches!(authority.check(&nonce, Algorithm::Md5, later),
+            NonceCheck::Stale(_)));
+        let other = NonceAuthority::new(b"other secret", REALM);
+        assert!(matches!(other.check(&nonce, Algorithm::Md5, now),
+            NonceCheck::Invalid));
+    }
+
+    #[test]
+    fn nonce_count_must_be_eight_hex_digits() {
+        let authority = NonceAuthority::new(b"secret", REALM);
+        let counts = counts("nc");
+        let now = SystemTime::now();
+        let nonce = authority.create(Algorithm::Md5, now).unwrap();
+        for nc in ["+0000001", "1", "000000001"] {
+            let verification = verify(&credentials(Algorithm::Md5, &nonce, nc),
+                &expected(Algorithm::Md5), &authority, &counts, now);
+            assert_eq!(verification.outcome, Outcome::Rejected, "{nc}");
+        }
+        _ = fs::remove_dir_all(&counts.directory);
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(Algorithm::parse("sha-256-SESS"),
+            Some(Algorithm::Sha256Sess));
+        assert_eq!(Algorithm::parse("SHA-512-256"), None);
+        assert!(parse_authorization("Basic abc").is_err());
+        assert!(parse_authorization("Digest username=\"a\"").is_err());
+    }
+}
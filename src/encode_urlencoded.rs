@@ -0,0 +1,47 @@
+// src/encode_urlencoded.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+/// Percent encodes everything except the RFC 3986 unreserved characters, so
+/// the output is safe in a query string, a path segment or a cookie value.
+///
+/// This is the inverse of decode_urlencoded for any input. Spaces become
+/// `%20` rather than `+` because `+` only means space in a query string.
+pub fn encode_urlencoded(input: &str) -> String {
+    let mut output: String = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            output.push(byte as char);
+        } else {
+            output.push('%');
+            output.push(DIGITS[(byte >> 4) as usize] as char);
+            output.push(DIGITS[(byte & 0x0f) as usize] as char);
+        }
+    }
+    return output;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_all_but_unreserved() {
+        assert_eq!(encode_urlencoded("a-b_c.d~e"), "a-b_c.d~e");
+        assert_eq!(encode_urlencoded("a b&c=d/é"), "a%20b%26c%3Dd%2F%C3%A9");
+        assert_eq!(encode_urlencoded(""), "");
+    }
+}
@@ -0,0 +1,265 @@
+// src/env_diff.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Comparing two CGI environments, for moving a script to another server or
+//! finding out why two requests were handled differently.
+//!
+//! Variables are grouped into the RFC 3875 meta-variables, the `HTTP_*`
+//! variables made from request header fields, and everything else, which is
+//! mostly whatever the server adds on its own.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::capture::Capture;
+use crate::conformance::META_VARIABLES;
+use crate::html;
+use crate::json;
+use crate::json::Value;
+use crate::redact::Redactor;
+
+/// The file `/baseline` compares each request with.
+pub const BASELINE_VARIABLE: &str = "TESTCGI_BASELINE";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Group {
+    Meta,
+    Header,
+    Extra,
+}
+
+impl Group {
+    pub fn of(name: &str) -> Group {
+        if META_VARIABLES.iter().any(|(n, _)| *n == name) {
+            return Group::Meta;
+        }
+        if name.starts_with("HTTP_") {
+            return Group::Header;
+        }
+        return Group::Extra;
+    }
+}
+
+impl fmt::Display for Group {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Group::Meta => write!(f, "RFC 3875 meta-variables"),
+            Group::Header => write!(f, "HTTP_* request headers"),
+            Group::Extra => write!(f, "Server-specific variables"),
+        };
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    Added(String),
+    Removed(String),
+    Changed(String, String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Difference {
+    pub name: String,
+    pub group: Group,
+    pub change: Change,
+}
+
+/// What changed going from `old` to `new`, by group and then by name. If a
+/// name appears more than once the last one counts, as it would for getenv.
+pub fn compare(old: &[(String, String)], new: &[(String, String)])
+    -> Vec<Difference>
+{
+    let last = |variables: &[(String, String)], name: &str| {
+        return variables.iter().rev()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.clone());
+    };
+    let mut names: Vec<&str> = old.iter().chain(new)
+        .map(|(n, _)| n.as_str())
+        .collect();
+    names.sort();
+    names.dedup();
+
+    let mut output: Vec<Difference> = Vec::new();
+    for name in names {
+        let change = match (last(old, name), last(new, name)) {
+            (Some(a), Some(b)) if a == b => continue,
+            (Some(a), Some(b)) => Change::Changed(a, b),
+            (Some(a), None) => Change::Removed(a),
+            (None, Some(b)) => Change::Added(b),
+            (None, None) => continue,
+        };
+        output.push(Difference {
+            name: String::from(name),
+            group: Group::of(name),
+            change,
+        });
+    }
+    output.sort_by(|a, b| match a.group.cmp(&b.group) {
+        Ordering::Equal => a.name.cmp(&b.name),
+        other => other,
+    });
+    return output;
+}
+
+/// Reads an environment from either a capture file or a JSON object of
+/// names and string values.
+pub fn load(input: &str) -> Result<Vec<(String, String)>, String> {
+    let value = json::parse(input).map_err(|e| e.to_string())?;
+    if value.get("version").is_some() && value.get("environment").is_some() {
+        return Ok(Capture::from_json(input)?.environment.into_iter()
+            .map(|(n, v)| (n, v.to_string_lossy().into_owned()))
+            .collect());
+    }
+    let Some(members) = value.as_object() else {
+        return Err(String::from("expected a capture file or a JSON object"));
+    };
+    let mut output: Vec<(String, String)> = Vec::with_capacity(members.len());
+    for (name, v) in members {
+        match v {
+            Value::String(s) => output.push((name.clone(), s.clone())),
+            _ => return Err(format!("the value of {name} is not a string")),
+        }
+    }
+    return Ok(output);
+}
+
+/// Plain text for the terminal: a heading per group and one line per
+/// variable, `+` for added, `-` for removed and `~` for changed.
+pub fn format_text(differences: &[Difference], redactor: &Redactor)
+    -> String
+{
+    if differences.is_empty() {
+        return String::from("The environments are the same.\n");
+    }
+    let mut output = String::new();
+    let mut group: Option<Group> = None;
+    for difference in differences {
+        if group != Some(difference.group) {
+            if group.is_some() {
+                output.push('\n');
+            }
+            output.push_str(&format!("{}:\n", difference.group));
+            group = Some(difference.group);
+        }
+        let show = |value: &str| redactor.display(&difference.name, value);
+        let name = &difference.name;
+        output.push_str(&match &difference.change {
+            Change::Added(b) => format!("+ {name}={:?}\n", show(b)),
+            Change::Removed(a) => format!("- {name}={:?}\n", show(a)),
+            Change::Changed(a, b) => {
+                format!("~ {name}={:?} -> {:?}\n", show(a), show(b))
+            }
+        });
+    }
+    return output;
+}
+
+/// The `/baseline` page.
+pub fn report_html(differences: &[Difference], baseline: &str,
+    redactor: &Redactor) -> String
+{
+    let mut out = String::with_capacity(4096);
+    out.push_str("<!DOCTYPE html><html lang=\"en\"><body>");
+    out.push_str("<h1>Environment Compared With the Baseline</h1>");
+    out.push_str(&format!("<p>Baseline: {}</p>", html::escape(baseline)));
+    if differences.is_empty() {
+        out.push_str("<p>No differences.</p>");
+    }
+    for group in [Group::Meta, Group::Header, Group::Extra] {
+        let rows: Vec<&Difference> = differences.iter()
+            .filter(|d| d.group == group)
+            .collect();
+        if rows.is_empty() {
+            continue;
+        }
+        out.push_str(&format!("<h2>{group}</h2>"));
+        out.push_str("<table><tr><th>Variable</th><th>Change</th>\
+            <th>Baseline</th><th>This request</th></tr>");
+        for difference in rows {
+            let show = |value: &str| {
+                html::escape(&redactor.display(&difference.name, value))
+            };
+            let (change, a, b) = match &difference.change {
+                Change::Added(b) => ("added", String::new(), show(b)),
+                Change::Removed(a) => ("removed", show(a), String::new()),
+                Change::Changed(a, b) => ("changed", show(a), show(b)),
+            };
+            out.push_str(&format!("<tr><td>{}</td><td>{change}</td>\
+                <td>{a}</td><td>{b}</td></tr>",
+                html::escape(&difference.name)));
+        }
+        out.push_str("</table>");
+    }
+    out.push_str("</body></html>");
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        return pairs.iter()
+            .map(|(n, v)| (String::from(*n), String::from(*v)))
+            .collect();
+    }
+
+    #[test]
+    fn compares_by_group_and_name() {
+        let old = variables(&[("PATH_INFO", "/a"), ("HTTP_COOKIE", "a=1"),
+            ("Z", "1"), ("REMOTE_ADDR", "x"), ("REMOTE_ADDR", "y")]);
+        let new = variables(&[("PATH_INFO", "/b"), ("HTTP_ACCEPT", "*/*"),
+            ("Z", "1"), ("REMOTE_ADDR", "y"), ("UNIQUE_ID", "u")]);
+        let differences = compare(&old, &new);
+        let summary: Vec<(&str, Group)> = differences.iter()
+            .map(|d| (d.name.as_str(), d.group))
+            .collect();
+        assert_eq!(summary, [
+            ("PATH_INFO", Group::Meta),
+            ("HTTP_ACCEPT", Group::Header),
+            ("HTTP_COOKIE", Group::Header),
+            ("UNIQUE_ID", Group::Extra),
+        ]);
+        assert_eq!(differences[0].change,
+            Change::Changed(String::from("/a"), String::from("/b")));
+        assert!(compare(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn loads_objects_and_captures() {
+        assert_eq!(load("{\"A\": \"1\"}").unwrap(), variables(&[("A", "1")]));
+        assert!(load("{\"A\": 1}").is_err());
+        assert!(load("[]").is_err());
+        assert!(load("{").is_err());
+        let capture = "{\"version\": 2, \"time\": \"t\", \
+            \"request_id\": \"r\", \"argv\": [], \
+            \"environment\": {\"B\": \"2\"}, \"body\": \"\"}";
+        assert_eq!(load(capture).unwrap(), variables(&[("B", "2")]));
+    }
+
+    #[test]
+    fn formats_redacted_text() {
+        let differences = compare(
+            &variables(&[("HTTP_COOKIE", "a=1"), ("PATH_INFO", "/a")]),
+            &variables(&[("PATH_INFO", "/b")]));
+        assert_eq!(format_text(&differences, &Redactor::with_defaults()),
+            "RFC 3875 meta-variables:\n~ PATH_INFO=\"/a\" -> \"/b\"\n\n\
+            HTTP_* request headers:\n\
+            - HTTP_COOKIE=\"[REDACTED len=3]\"\n");
+        assert_eq!(format_text(&[], &Redactor::with_defaults()),
+            "The environments are the same.\n");
+    }
+}
@@ -0,0 +1,466 @@
+// src/forwarded.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Finding the real client behind reverse proxies.
+//!
+//! A proxy puts the address it accepted the connection from in the RFC 7239
+//! Forwarded header or the older X-Forwarded-For, and the web server only
+//! sees the last proxy in REMOTE_ADDR. Anyone can send those headers, so they
+//! are only believed for hops that come from a trusted proxy, starting at
+//! REMOTE_ADDR and walking the chain from the right.
+
+use std::env;
+use std::net::IpAddr;
+
+use crate::catalog::Translator;
+use crate::cidr;
+use crate::cidr::Cidr;
+use crate::config;
+use crate::url::split_host_port;
+
+/// The environment variable listing trusted proxy ranges.
+pub const TRUSTED_PROXIES_VARIABLE: &str = "TESTCGI_TRUSTED_PROXIES";
+/// The environment variable naming the header the trusted proxies write.
+pub const HEADER_VARIABLE: &str = "TESTCGI_PROXY_HEADER";
+
+/// The one forwarding header that is believed. A proxy that only appends
+/// to one of them passes the other through from the client untouched, so
+/// reading both would let a client choose its own address.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ProxyHeader {
+    Forwarded,
+    #[default]
+    XForwardedFor,
+}
+
+impl ProxyHeader {
+    /// The names used for proxy.header, as they are written in the file.
+    pub const NAMES: &[&str] = &["forwarded", "x-forwarded-for"];
+
+    pub fn parse(name: &str) -> Option<ProxyHeader> {
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("forwarded") {
+            return Some(ProxyHeader::Forwarded);
+        }
+        if name.eq_ignore_ascii_case("x-forwarded-for") {
+            return Some(ProxyHeader::XForwardedFor);
+        }
+        return None;
+    }
+
+    /// The header name as it is sent.
+    pub fn name(&self) -> &'static str {
+        return match self {
+            ProxyHeader::Forwarded => "Forwarded",
+            ProxyHeader::XForwardedFor => "X-Forwarded-For",
+        };
+    }
+}
+
+/// One forwarded-element of a Forwarded header. Parameters that were not
+/// sent are None; unknown parameters are ignored.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Element {
+    pub by: Option<String>,
+    pub for_: Option<String>,
+    pub host: Option<String>,
+    pub proto: Option<String>,
+}
+
+/// Splits a Forwarded header into its comma separated elements.
+///
+/// ```text
+/// Forwarded: for=192.0.2.60;proto=http;by=203.0.113.43, for="[2001:db8::1]"
+/// ```
+pub fn parse_forwarded(header: &str) -> Result<Vec<Element>, String> {
+    let mut output: Vec<Element> = Vec::new();
+    let mut element = Element::default();
+    let mut chars = header.chars().peekable();
+    loop {
+        while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+        let mut name = String::new();
+        while let Some(c) = chars.next_if(|c| !"=;,".contains(*c)) {
+            name.push(c);
+        }
+        let name = name.trim().to_ascii_lowercase();
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            if chars.next_if_eq(&'"').is_some() {
+                loop {
+                    match chars.next() {
+                        None => {
+                            return Err(String::from(
+                                "A quoted string in Forwarded is not closed."));
+                        }
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) => value.push(c),
+                            None => {
+                                return Err(String::from("A quoted string in \
+                                    Forwarded ends with a backslash."));
+                            }
+                        },
+                        Some(c) => value.push(c),
+                    }
+                }
+            } else {
+                while let Some(c) = chars.next_if(|c| !";,".contains(*c)) {
+                    value.push(c);
+                }
+                value = String::from(value.trim());
+            }
+        } else if !name.is_empty() {
+            return Err(format!("The Forwarded parameter {name:?} has no \
+                value."));
+        }
+        let slot = match name.as_str() {
+            "by" => Some(&mut element.by),
+            "for" => Some(&mut element.for_),
+            "host" => Some(&mut element.host),
+            "proto" => Some(&mut element.proto),
+            _ => None,
+        };
+        if let Some(slot) = slot {
+            if slot.is_some() {
+                return Err(format!("The Forwarded parameter {name:?} is \
+                    repeated in one element."));
+            }
+            *slot = Some(value);
+        }
+        while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+        match chars.next() {
+            Some(';') => {}
+            Some(',') => {
+                output.push(element);
+                element = Element::default();
+            }
+            None => {
+                output.push(element);
+                break;
+            }
+            Some(c) => {
+                return Err(format!("Unexpected {c:?} in Forwarded."));
+            }
+        }
+    }
+    output.retain(|e| *e != Element::default());
+    return Ok(output);
+}
+
+/// Splits X-Forwarded-For, or X-Forwarded-Proto and X-Forwarded-Host when
+/// several proxies appended to them, into a list.
+pub fn parse_list(header: &str) -> Vec<String> {
+    return header
+        .split(',')
+        .map(|s| String::from(s.trim()))
+        .filter(|s| !s.is_empty())
+        .collect();
+}
+
+/// The address in a node such as `192.0.2.43:47011`, `[2001:db8::1]:80` or
+/// a bare IPv6 address from X-Forwarded-For. `unknown` and obfuscated
+/// identifiers like `_hidden` are not addresses.
+pub fn node_address(node: &str) -> Option<IpAddr> {
+    let node = node.trim();
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    return split_host_port(node).0.parse().ok();
+}
+
+/// One step in the chain, with what the proxy that added it said about the
+/// connection it received.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hop {
+    pub node: String,
+    pub proto: Option<String>,
+    pub host: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+    RemoteAddr,
+    Forwarded,
+    XForwardedFor,
+}
+
+impl Source {
+    pub fn describe(&self, tr: &Translator) -> String {
+        return String::from(tr.get(match self {
+            Source::RemoteAddr => "client.from_remote_addr",
+            Source::Forwarded => "client.from_forwarded",
+            Source::XForwardedFor => "client.from_x_forwarded_for",
+        }));
+    }
+}
+
+/// The client as far as the trusted proxies can vouch for it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Resolved {
+    /// The address, or `unknown` or an obfuscated name if that is what the
+    /// proxy reported.
+    pub client: String,
+    pub client_ip: Option<IpAddr>,
+    /// Set when a trusted proxy reported the scheme the client used.
+    pub scheme: Option<String>,
+    /// Set when a trusted proxy reported the Host the client asked for.
+    pub host: Option<String>,
+    pub source: Source,
+    /// The trusted proxies the request passed through, nearest last.
+    pub proxies: Vec<String>,
+}
+
+/// Walks `hops` from the right. REMOTE_ADDR is the first node; while the
+/// current node is a trusted proxy the hop before it is believed. The first
+/// untrusted node, or the leftmost hop, is the client.
+pub fn resolve(remote_addr: &str, hops: &[Hop], source: Source,
+    trusted: &[Cidr]) -> Resolved
+{
+    let mut resolved = Resolved {
+        client: String::from(remote_addr),
+        client_ip: node_address(remote_addr),
+        scheme: None,
+        host: None,
+        source: Source::RemoteAddr,
+        proxies: Vec::new(),
+    };
+    for hop in hops.iter().rev() {
+        let is_trusted = match resolved.client_ip {
+            Some(ip) => cidr::find(trusted, &ip).is_some(),
+            None => false,
+        };
+        if !is_trusted {
+            break;
+        }
+        resolved.proxies.insert(0, resolved.client.clone());
+        resolved.client = hop.node.clone();
+        resolved.client_ip = node_address(&hop.node);
+        resolved.source = source;
+        if hop.proto.is_some() {
+            resolved.scheme = hop.proto.clone();
+        }
+        if hop.host.is_some() {
+            resolved.host = hop.host.clone();
+        }
+    }
+    return resolved;
+}
+
+/// Turns Forwarded elements into hops. Elements without a `for` are kept as
+/// `unknown` so the chain does not get shorter than it really is.
+pub fn forwarded_hops(elements: &[Element]) -> Vec<Hop> {
+    return elements
+        .iter()
+        .map(|e| Hop {
+            node: e.for_.clone().unwrap_or_else(|| String::from("unknown")),
+            proto: e.proto.clone(),
+            host: e.host.clone(),
+        })
+        .collect();
+}
+
+/// Turns X-Forwarded-For into hops. X-Forwarded-Proto and X-Forwarded-Host
+/// are matched up from the right when they have one value per hop, and
+/// otherwise their last value is taken as what the nearest proxy saw.
+pub fn x_forwarded_hops(for_: &[String], proto: &[String], host: &[String])
+    -> Vec<Hop>
+{
+    let pick = |values: &[String], index: usize| -> Option<String> {
+        if values.len() == for_.len() {
+            return Some(values[index].clone());
+        }
+        if index + 1 == for_.len() {
+            return values.last().cloned();
+        }
+        return None;
+    };
+    let mut output: Vec<Hop> = Vec::with_capacity(for_.len());
+    for (index, node) in for_.iter().enumerate() {
+        output.push(Hop {
+            node: node.clone(),
+            proto: pick(proto, index),
+            host: pick(host, index),
+        });
+    }
+    return output;
+}
+
+/// Everything about the forwarding headers of this request, raw and
+/// resolved, for the diagnostic page and for anything that needs the client
+/// address.
+pub struct ClientInfo {
+    pub remote_addr: String,
+    pub forwarded: Option<String>,
+    pub x_forwarded_for: Option<String>,
+    pub x_forwarded_proto: Option<String>,
+    pub x_forwarded_host: Option<String>,
+    pub trusted: Vec<Cidr>,
+    /// The header that was read. The other one is shown but never used.
+    pub header: ProxyHeader,
+    pub resolved: Resolved,
+    /// Why the Forwarded header was ignored, if it was.
+    pub error: Option<String>,
+}
+
+/// Reads REMOTE_ADDR, the forwarding headers, TESTCGI_TRUSTED_PROXIES and
+/// TESTCGI_PROXY_HEADER. Only the header named by TESTCGI_PROXY_HEADER,
+/// X-Forwarded-For unless it says otherwise, is parsed. Only bad settings
+/// are an error; a header that cannot be parsed is reported and ignored.
+pub fn from_env() -> Result<ClientInfo, String> {
+    let get = |name: &str| env::var(name).ok().filter(|v| !v.is_empty());
+    let trusted = match config::get(TRUSTED_PROXIES_VARIABLE) {
+        Some(list) => match cidr::parse_list(&list) {
+            Ok(list) => list,
+            Err(message) => {
+                return Err(format!("{TRUSTED_PROXIES_VARIABLE}: {message}"));
+            }
+        },
+        None => Vec::new(),
+    };
+    let header = match config::get(HEADER_VARIABLE) {
+        Some(name) => match ProxyHeader::parse(&name) {
+            Some(header) => header,
+            None => {
+                return Err(format!("{HEADER_VARIABLE}: {name} is not one of \
+                    {}", ProxyHeader::NAMES.join(", ")));
+            }
+        },
+        None => ProxyHeader::default(),
+    };
+    let remote_addr = get("REMOTE_ADDR").unwrap_or_default();
+    let forwarded = get("HTTP_FORWARDED");
+    let x_forwarded_for = get("HTTP_X_FORWARDED_FOR");
+    let x_forwarded_proto = get("HTTP_X_FORWARDED_PROTO");
+    let x_forwarded_host = get("HTTP_X_FORWARDED_HOST");
+
+    let mut error: Option<String> = None;
+    let mut hops: Vec<Hop> = Vec::new();
+    let mut source = Source::RemoteAddr;
+    if header == ProxyHeader::Forwarded
+        && let Some(value) = &forwarded {
+        match parse_forwarded(value) {
+            Ok(elements) => {
+                hops = forwarded_hops(&elements);
+                source = Source::Forwarded;
+            }
+            Err(message) => error = Some(message),
+        }
+    }
+    if header == ProxyHeader::XForwardedFor
+        && let Some(value) = &x_forwarded_for {
+        let list = |v: &Option<String>| match v {
+            Some(v) => parse_list(v),
+            None => Vec::new(),
+        };
+        hops = x_forwarded_hops(&parse_list(value),
+            &list(&x_forwarded_proto), &list(&x_forwarded_host));
+        source = Source::XForwardedFor;
+    }
+    let resolved = resolve(&remote_addr, &hops, source, &trusted);
+    return Ok(ClientInfo {
+        remote_addr,
+        forwarded,
+        x_forwarded_for,
+        x_forwarded_proto,
+        x_forwarded_host,
+        trusted,
+        header,
+        resolved,
+        error,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        return values.iter().map(|v| String::from(*v)).collect();
+    }
+
+    #[test]
+    fn parses_forwarded() {
+        let elements = parse_forwarded("for=192.0.2.60;proto=http;\
+            by=203.0.113.43, For=\"[2001:db8::1]:80\";Host=\"a\\\"b\", \
+            secret=x").unwrap();
+        assert_eq!(elements, [
+            Element {
+                by: Some(String::from("203.0.113.43")),
+                for_: Some(String::from("192.0.2.60")),
+                host: None,
+                proto: Some(String::from("http")),
+            },
+            Element {
+                for_: Some(String::from("[2001:db8::1]:80")),
+                host: Some(String::from("a\"b")),
+                ..Element::default()
+            },
+        ]);
+        assert_eq!(parse_forwarded("").unwrap(), []);
+    }
+
+    #[test]
+    fn rejects_bad_forwarded() {
+        assert!(parse_forwarded("for=\"192.0.2.1").is_err());
+        assert!(parse_forwarded("for=\"a\\").is_err());
+        assert!(parse_forwarded("for").is_err());
+        assert!(parse_forwarded("for=a;for=b").is_err());
+        assert!(parse_forwarded("for=\"a\"b").is_err());
+    }
+
+    #[test]
+    fn finds_node_addresses() {
+        assert_eq!(parse_list(" a, ,b "), strings(&["a", "b"]));
+        assert_eq!(node_address("192.0.2.43:47011"),
+            "192.0.2.43".parse().ok());
+        assert_eq!(node_address("[2001:db8::1]:80"),
+            "2001:db8::1".parse().ok());
+        assert_eq!(node_address("2001:db8::1"), "2001:db8::1".parse().ok());
+        assert_eq!(node_address("unknown"), None);
+        assert_eq!(node_address("_hidden"), None);
+    }
+
+    #[test]
+    fn resolves_through_trusted_proxies() {
+        let trusted = [Cidr::parse("10.0.0.0/8").unwrap()];
+        let hops = x_forwarded_hops(
+            &strings(&["198.51.100.7", "203.0.113.1", "10.0.0.2"]),
+            &strings(&["https"]), &[]);
+        assert_eq!(hops[2].proto.as_deref(), Some("https"));
+        assert_eq!(hops[1].proto, None);
+
+        let resolved = resolve("10.0.0.1", &hops, Source::XForwardedFor,
+            &trusted);
+        // 203.0.113.1 is not trusted, so the spoofable 198.51.100.7 to its
+        // left is ignored.
+        assert_eq!(resolved.client, "203.0.113.1");
+        assert_eq!(resolved.proxies, strings(&["10.0.0.2", "10.0.0.1"]));
+        assert_eq!(resolved.scheme.as_deref(), Some("https"));
+        assert_eq!(resolved.source, Source::XForwardedFor);
+
+        let untrusted = resolve("192.0.2.1", &hops, Source::XForwardedFor,
+            &trusted);
+        assert_eq!(untrusted.client, "192.0.2.1");
+        assert_eq!(untrusted.source, Source::RemoteAddr);
+    }
+
+    #[test]
+    fn keeps_elements_without_for() {
+        let elements = parse_forwarded("proto=https").unwrap();
+        let hops = forwarded_hops(&elements);
+        assert_eq!(hops[0].node, "unknown");
+        assert_eq!(hops[0].proto.as_deref(), Some("https"));
+    }
+}
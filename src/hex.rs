@@ -0,0 +1,57 @@
+// src/hex.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// Lower case hexadecimal, two digits per byte.
+pub fn encode(data: &[u8]) -> String {
+    let mut output = String::with_capacity(data.len() * 2);
+    for byte in data {
+        output.push(DIGITS[(byte >> 4) as usize] as char);
+        output.push(DIGITS[(byte & 0x0f) as usize] as char);
+    }
+    return output;
+}
+
+/// Accepts upper or lower case digits. None if the length is odd or there is
+/// anything that is not a hex digit.
+pub fn decode(input: &str) -> Option<Vec<u8>> {
+    let input = input.as_bytes();
+    if !input.len().is_multiple_of(2) {
+        return None;
+    }
+    let mut output = Vec::with_capacity(input.len() / 2);
+    for pair in input.chunks(2) {
+        let high = (pair[0] as char).to_digit(16)?;
+        let low = (pair[1] as char).to_digit(16)?;
+        output.push(((high << 4) | low) as u8);
+    }
+    return Some(output);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        assert_eq!(encode(&[0x00, 0x7f, 0xab]), "007fab");
+        assert_eq!(decode("007FaB").unwrap(), [0x00, 0x7f, 0xab]);
+        assert_eq!(decode("").unwrap(), []);
+        assert_eq!(decode("abc"), None);
+        assert_eq!(decode("zz"), None);
+        assert_eq!(decode("+1"), None);
+    }
+}
@@ -0,0 +1,122 @@
+// src/hmac.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! HMAC from RFC 2104 over any of the in-crate hashes.
+
+use crate::digest::Digest;
+
+/// Computes HMAC-H(key, message).
+pub fn hmac<H: Digest>(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut block_key: Vec<u8> = if key.len() > H::BLOCK_SIZE {
+        H::digest(key)
+    } else {
+        key.to_vec()
+    };
+    block_key.resize(H::BLOCK_SIZE, 0);
+
+    let mut inner = H::new();
+    let inner_pad: Vec<u8> = block_key.iter().map(|b| b ^ 0x36).collect();
+    inner.update(&inner_pad);
+    inner.update(message);
+    let inner_hash = inner.finish();
+
+    let mut outer = H::new();
+    let outer_pad: Vec<u8> = block_key.iter().map(|b| b ^ 0x5c).collect();
+    outer.update(&outer_pad);
+    outer.update(&inner_hash);
+    return outer.finish();
+}
+
+/// Compares two byte strings without stopping at the first difference, so
+/// the time taken does not tell an attacker how much of a MAC was right.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut difference: u8 = 0;
+    for (x, y) in a.iter().zip(b.iter()) {
+        difference |= x ^ y;
+    }
+    return difference == 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+    use crate::md5::Md5;
+    use crate::sha256::Sha256;
+    use crate::sha512::Sha384;
+    use crate::sha512::Sha512;
+
+    fn check<H: Digest>(key: &[u8], message: &[u8], expected: &str) {
+        assert_eq!(hex::encode(&hmac::<H>(key, message)), expected);
+    }
+
+    /// RFC 4231 test case 1.
+    #[test]
+    fn rfc_4231_short_key() {
+        let key = [0x0b; 20];
+        check::<Sha256>(&key, b"Hi There", "b0344c61d8db38535ca8afceaf0bf12b\
+            881dc200c9833da726e9376c2e32cff7");
+        check::<Sha384>(&key, b"Hi There", "afd03944d84895626b0825f4ab46907f\
+            15f9dadbe4101ec682aa034c7cebc59cfaea9ea9076ede7f4af152e8b2fa9cb6");
+        check::<Sha512>(&key, b"Hi There", "87aa7cdea5ef619d4ff0b4241a1d6cb0\
+            2379f4e2ce4ec2787ad0b30545e17cdedaa833b7d6b8a702038b274eaea3f4e4\
+            be9d914eeb61f1702e696c203a126854");
+    }
+
+    /// RFC 4231 test case 2, a key shorter than the output.
+    #[test]
+    fn rfc_4231_jefe() {
+        let message = b"what do ya want for nothing?";
+        check::<Sha256>(b"Jefe", message, "5bdcc146bf60754e6a042426089575c7\
+            5a003f089d2739839dec58b964ec3843");
+        check::<Sha384>(b"Jefe", message, "af45d2e376484031617f78d2b58a6b1b\
+            9c7ef464f5a01b47e42ec3736322445e8e2240ca5e69e2c78b3239ecfab21649");
+        check::<Sha512>(b"Jefe", message, "164b7a7bfcf819e2e395fbe73b56e0a3\
+            87bd64222e831fd610270cd7ea2505549758bf75c05a994a6d034f65f8f0e6fd\
+            caeab1a34d4a6b4b636e070a38bce737");
+    }
+
+    /// RFC 4231 test case 6, a key longer than the block that has to be
+    /// hashed first.
+    #[test]
+    fn rfc_4231_long_key() {
+        let key = [0xaa; 131];
+        let message = b"Test Using Larger Than Block-Size Key - Hash Key First";
+        check::<Sha256>(&key, message, "60e431591ee0b67f0d8a26aacbf5b77f\
+            8e0bc6213728c5140546040f0ee37f54");
+        check::<Sha384>(&key, message, "4ece084485813e9088d2c63a041bc5b4\
+            4f9ef1012a2b588f3cd11f05033ac4c60c2ef6ab4030fe8296248df163f44952");
+        check::<Sha512>(&key, message, "80b24263c7c1a3ebb71493c1dd7be8b4\
+            9b46d1f41b4aeec1121b013783f8f3526b56d037e05f2598bd0fd2215d6a1e52\
+            95e64f73f63f0aec8b915a985d786598");
+    }
+
+    /// RFC 2202 test case 2 for HMAC-MD5.
+    #[test]
+    fn rfc_2202_md5() {
+        check::<Md5>(b"Jefe", b"what do ya want for nothing?",
+            "750c783e6ab0b503eaa86e310a5db738");
+    }
+
+    #[test]
+    fn constant_time_eq_compares() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"ab"));
+    }
+}
@@ -0,0 +1,147 @@
+// src/htpasswd.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! htpasswd files: one `user:hash` line per user.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::bcrypt;
+use crate::crypt;
+
+/// The hash formats htpasswd can write. Only the ones worth trusting today
+/// are checked; the rest are recognized so the error can say what they are.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HashFormat {
+    /// `$2y$`, from `htpasswd -B`.
+    Bcrypt,
+    /// `$5$`, from `htpasswd -2` or `mkpasswd -m sha-256`.
+    Sha256Crypt,
+    /// `$6$`, from `htpasswd -5` or `mkpasswd -m sha-512`.
+    Sha512Crypt,
+    /// `$apr1$`, from `htpasswd -m`, and its `$1$` original.
+    Md5Crypt,
+    /// `{SHA}`, unsalted SHA-1 from `htpasswd -s`. Not accepted.
+    Sha1,
+    /// Anything else, including plain text and traditional DES crypt.
+    Unsupported,
+}
+
+pub fn hash_format(hash: &str) -> HashFormat {
+    if bcrypt::is_bcrypt(hash) {
+        return HashFormat::Bcrypt;
+    }
+    if hash.starts_with("$5$") {
+        return HashFormat::Sha256Crypt;
+    }
+    if hash.starts_with("$6$") {
+        return HashFormat::Sha512Crypt;
+    }
+    if hash.starts_with("$apr1$") || hash.starts_with("$1$") {
+        return HashFormat::Md5Crypt;
+    }
+    if hash.starts_with("{SHA}") {
+        return HashFormat::Sha1;
+    }
+    return HashFormat::Unsupported;
+}
+
+/// Checks `password` against one stored hash.
+pub fn verify_hash(password: &str, hash: &str) -> bool {
+    return match hash_format(hash) {
+        HashFormat::Bcrypt => bcrypt::verify(password.as_bytes(), hash),
+        HashFormat::Sha256Crypt | HashFormat::Sha512Crypt
+        | HashFormat::Md5Crypt => {
+            crypt::is_crypt(hash) && crypt::verify(password.as_bytes(), hash)
+        }
+        HashFormat::Sha1 | HashFormat::Unsupported => false,
+    };
+}
+
+pub struct Htpasswd {
+    entries: Vec<(String, String)>,
+}
+
+impl Htpasswd {
+    /// Blank lines and lines starting with `#` are skipped, as are lines
+    /// without a `:`.
+    pub fn parse(contents: &str) -> Htpasswd {
+        let mut entries: Vec<(String, String)> = Vec::new();
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once(':') {
+                Some((user, hash)) => {
+                    entries.push((String::from(user), String::from(hash)));
+                }
+                None => continue,
+            }
+        }
+        return Htpasswd { entries };
+    }
+
+    pub fn load(path: &Path) -> io::Result<Htpasswd> {
+        return Ok(Htpasswd::parse(&fs::read_to_string(path)?));
+    }
+
+    /// The stored hash for `user`. The first matching line wins, like Apache.
+    pub fn hash_for(&self, user: &str) -> Option<&str> {
+        return self.entries.iter()
+            .find(|(name, _)| name == user)
+            .map(|(_, hash)| hash.as_str());
+    }
+
+    pub fn verify(&self, user: &str, password: &str) -> bool {
+        return match self.hash_for(user) {
+            Some(hash) => verify_hash(password, hash),
+            None => false,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_hash_formats() {
+        assert_eq!(hash_format("$apr1$saltsalt$yAAkm4libquA.ZWLHbSBq/"),
+            HashFormat::Md5Crypt);
+        assert_eq!(hash_format("$5$salt$x"), HashFormat::Sha256Crypt);
+        assert_eq!(hash_format("$6$salt$x"), HashFormat::Sha512Crypt);
+        assert_eq!(hash_format("{SHA}W6ph5Mm5Pz8GgiULbPgzG37mj9g="),
+            HashFormat::Sha1);
+        assert_eq!(hash_format("password"), HashFormat::Unsupported);
+        assert!(!verify_hash("password", "{SHA}W6ph5Mm5Pz8GgiULbPgzG37mj9g="));
+        assert!(!verify_hash("password", "password"));
+    }
+
+    #[test]
+    fn verifies_users() {
+        let file = Htpasswd::parse("# users\n\nalice:$apr1$saltsalt$\
+            yAAkm4libquA.ZWLHbSBq/\nno colon\n\
+            alice:$1$saltsalt$qjXMvbEw8oaL.CzflDtaK/\n\
+            bob:$1$saltsalt$qjXMvbEw8oaL.CzflDtaK/\n");
+        assert!(file.hash_for("alice").unwrap().starts_with("$apr1$"));
+        assert!(file.verify("alice", "password"));
+        assert!(file.verify("bob", "password"));
+        assert!(!file.verify("bob", "Password"));
+        assert!(!file.verify("carol", "password"));
+        assert!(file.hash_for("no colon").is_none());
+    }
+}
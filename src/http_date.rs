@@ -0,0 +1,123 @@
+// src/http_date.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+const DAY_NAMES: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+
+pub const MONTH_NAMES: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun",
+    "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// A UTC calendar date and time broken out of a Unix timestamp.
+pub struct DateTime {
+    pub year: i64,
+    /// 1 through 12.
+    pub month: u32,
+    /// 1 through 31.
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    /// 0 is Thursday, the day of the week of 1970-01-01.
+    pub weekday: u32,
+}
+
+impl DateTime {
+    pub fn from_unix(seconds: i64) -> DateTime {
+        let days = seconds.div_euclid(86400);
+        let remainder = seconds.rem_euclid(86400) as u32;
+
+        // Howard Hinnant's civil_from_days.
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        return DateTime {
+            year,
+            month,
+            day,
+            hour: remainder / 3600,
+            minute: (remainder / 60) % 60,
+            second: remainder % 60,
+            weekday: days.rem_euclid(7) as u32,
+        };
+    }
+
+    pub fn from_system_time(time: SystemTime) -> DateTime {
+        return DateTime::from_unix(unix_seconds(time));
+    }
+}
+
+/// Seconds since the Unix epoch. Times before 1970 come out negative.
+pub fn unix_seconds(time: SystemTime) -> i64 {
+    return match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+}
+
+/// Formats a time as an RFC 9110 IMF-fixdate, e.g.
+/// `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub fn format_http_date(time: SystemTime) -> String {
+    let t = DateTime::from_system_time(time);
+    return format!("{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+        DAY_NAMES[t.weekday as usize], t.day, MONTH_NAMES[t.month as usize - 1],
+        t.year, t.hour, t.minute, t.second);
+}
+
+/// Formats a time as an RFC 3339 UTC timestamp with milliseconds, e.g.
+/// `1994-11-06T08:49:37.000Z`, for logs.
+pub fn format_rfc3339(time: SystemTime) -> String {
+    let t = DateTime::from_system_time(time);
+    let millis = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.subsec_millis(),
+        Err(_) => 0,
+    };
+    return format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{millis:03}Z", t.year,
+        t.month, t.day, t.hour, t.minute, t.second);
+}
+
+/// Formats a time the way the Common Log Format wants it, e.g.
+/// `06/Nov/1994:08:49:37 +0000`.
+pub fn format_clf_date(time: SystemTime) -> String {
+    let t = DateTime::from_system_time(time);
+    return format!("{:02}/{}/{:04}:{:02}:{:02}:{:02} +0000", t.day,
+        MONTH_NAMES[t.month as usize - 1], t.year, t.hour, t.minute, t.second);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn formats_dates() {
+        // 2024-02-29 13:05:09 UTC, a Thursday in a leap year.
+        let time = UNIX_EPOCH + Duration::from_millis(1_709_211_909_042);
+        assert_eq!(format_http_date(time), "Thu, 29 Feb 2024 13:05:09 GMT");
+        assert_eq!(format_rfc3339(time), "2024-02-29T13:05:09.042Z");
+        assert_eq!(format_clf_date(time), "29/Feb/2024:13:05:09 +0000");
+        assert_eq!(unix_seconds(time), 1_709_211_909);
+    }
+}
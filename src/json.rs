@@ -0,0 +1,937 @@
+// src/json.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! A JSON parser and printer following RFC 8259.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::catalog;
+use crate::catalog::Translator;
+use crate::config;
+use crate::html;
+
+/// How deeply arrays and objects may nest before parsing gives up.
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+/// The highest json.max_depth is allowed to go. Parsing and `to_html` both
+/// recurse once per level, and this is well within a thread's stack.
+pub const DEPTH_CEILING: usize = 512;
+/// The largest request body parsed when json.max_size is not set.
+pub const DEFAULT_MAX_SIZE: u64 = 1024 * 1024;
+
+/// Bounds on what the parser will take. The parser recurses once per level
+/// of nesting, so without a depth limit a body of a few thousand `[`s would
+/// overflow the stack.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    pub max_depth: usize,
+    /// In bytes.
+    pub max_size: u64,
+}
+
+impl Limits {
+    /// TESTCGI_JSON_MAX_DEPTH, held to DEPTH_CEILING, and
+    /// TESTCGI_JSON_MAX_SIZE.
+    pub fn from_env() -> Limits {
+        return Limits {
+            max_depth: config::get("TESTCGI_JSON_MAX_DEPTH")
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(DEFAULT_MAX_DEPTH)
+                .min(DEPTH_CEILING),
+            max_size: config::get("TESTCGI_JSON_MAX_SIZE")
+                .and_then(|v| config::parse_size(&v))
+                .unwrap_or(DEFAULT_MAX_SIZE),
+        };
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        return Limits {
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_size: DEFAULT_MAX_SIZE,
+        };
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// Members are kept in document order, duplicates included.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Looks up a member of an object. If a name appears more than once the
+    /// last one wins, like JavaScript's JSON.parse.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        return match self {
+            Value::Object(members) => members.iter()
+                .rev()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v),
+            _ => None,
+        };
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        return match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        };
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        return match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        };
+    }
+
+    /// The number as an i64 if it is a whole number in range.
+    pub fn as_i64(&self) -> Option<i64> {
+        let n = self.as_f64()?;
+        if n.fract() != 0.0 || n < i64::MIN as f64 || n > i64::MAX as f64 {
+            return None;
+        }
+        return Some(n as i64);
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        return match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        };
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        return match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        };
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, Value)]> {
+        return match self {
+            Value::Object(members) => Some(members),
+            _ => None,
+        };
+    }
+
+    /// Indented output, two spaces per level.
+    pub fn pretty(&self) -> String {
+        let mut output = String::new();
+        self.write_pretty(&mut output, 0);
+        return output;
+    }
+
+    fn write_pretty(&self, output: &mut String, depth: usize) {
+        let indent = |output: &mut String, depth: usize| {
+            for _ in 0..depth {
+                output.push_str("  ");
+            }
+        };
+        match self {
+            Value::Array(items) if !items.is_empty() => {
+                output.push_str("[\n");
+                for (i, item) in items.iter().enumerate() {
+                    indent(output, depth + 1);
+                    item.write_pretty(output, depth + 1);
+                    if i + 1 < items.len() {
+                        output.push(',');
+                    }
+                    output.push('\n');
+                }
+                indent(output, depth);
+                output.push(']');
+            }
+            Value::Object(members) if !members.is_empty() => {
+                output.push_str("{\n");
+                for (i, (name, value)) in members.iter().enumerate() {
+                    indent(output, depth + 1);
+                    output.push_str(&quote(name));
+                    output.push_str(": ");
+                    value.write_pretty(output, depth + 1);
+                    if i + 1 < members.len() {
+                        output.push(',');
+                    }
+                    output.push('\n');
+                }
+                indent(output, depth);
+                output.push('}');
+            }
+            _ => output.push_str(&self.to_string()),
+        }
+    }
+}
+
+/// Colours for the classes `to_html` uses. Everything is scoped to the
+/// `json` class so a page can include it more than once.
+const HTML_STYLE: &str = "<style>\
+.json{font-family:monospace;white-space:pre-wrap}\
+.json details>div{margin-left:2em}\
+.json summary{cursor:pointer}\
+.json-key{color:#881391}\
+.json-string{color:#c41a16}\
+.json-number{color:#1c00cf}\
+.json-literal{color:#0d22aa;font-weight:bold}\
+.json-note{color:#808080;font-style:italic}\
+</style>";
+
+/// Levels below this start out collapsed.
+const HTML_OPEN_DEPTH: usize = 3;
+
+impl Value {
+    /// The value as a syntax-highlighted tree, escaped and ready to put in
+    /// a page. Arrays and objects are `<details>` elements so they can be
+    /// folded away; deeply nested ones start out folded. The counts and
+    /// notes are in the language of `tr`.
+    pub fn to_html(&self, tr: &Translator) -> String {
+        let mut output = String::from(HTML_STYLE);
+        output.push_str("<div class=\"json\">");
+        self.write_html(&mut output, "", 0, tr);
+        output.push_str("</div>");
+        return output;
+    }
+
+    /// Writes one line, or one `<details>` for a non-empty container.
+    /// `label` is the already escaped member name and colon, if any.
+    fn write_html(&self, output: &mut String, label: &str, depth: usize,
+        tr: &Translator)
+    {
+        let (open, close, count, one, many) = match self {
+            Value::Array(items) if !items.is_empty() => {
+                ('[', ']', items.len(), "json.item", "json.items")
+            }
+            Value::Object(members) if !members.is_empty() => {
+                ('{', '}', members.len(), "json.member", "json.members")
+            }
+            _ => {
+                output.push_str("<div>");
+                output.push_str(label);
+                output.push_str(&self.scalar_html());
+                output.push_str("</div>");
+                return;
+            }
+        };
+        let note = tr.format(if count == 1 { one } else { many },
+            &[&count.to_string()]);
+        output.push_str(if depth < HTML_OPEN_DEPTH {
+            "<details open>"
+        } else {
+            "<details>"
+        });
+        output.push_str(&format!("<summary>{label}{open} \
+            <span class=\"json-note\">{}</span></summary><div>",
+            html::escape(&note)));
+        match self {
+            Value::Array(items) => {
+                for item in items {
+                    item.write_html(output, "", depth + 1, tr);
+                }
+            }
+            Value::Object(members) => {
+                let mut last: HashMap<&str, usize> = HashMap::new();
+                for (i, (name, _)) in members.iter().enumerate() {
+                    last.insert(name, i);
+                }
+                for (i, (name, value)) in members.iter().enumerate() {
+                    let mut label = format!(
+                        "<span class=\"json-key\">{}</span>: ",
+                        html::escape(&quote(name)));
+                    if last.get(name.as_str()) != Some(&i) {
+                        label.push_str(&format!(
+                            "<span class=\"json-note\">{}</span> ",
+                            html::escape(tr.get("json.duplicate"))));
+                    }
+                    value.write_html(output, &label, depth + 1, tr);
+                }
+            }
+            _ => {}
+        }
+        output.push_str(&format!("</div>{close}</details>"));
+    }
+
+    fn scalar_html(&self) -> String {
+        let class = match self {
+            Value::String(_) => "json-string",
+            Value::Number(_) => "json-number",
+            Value::Null | Value::Bool(_) => "json-literal",
+            // Only empty containers get here.
+            Value::Array(_) | Value::Object(_) => return self.to_string(),
+        };
+        return format!("<span class=\"{class}\">{}</span>",
+            html::escape(&self.to_string()));
+    }
+}
+
+/// Compact output with no whitespace.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Number(n) => {
+                if n.is_finite() {
+                    write!(f, "{n}")
+                } else {
+                    // JSON has no NaN or infinity.
+                    write!(f, "null")
+                }
+            }
+            Value::String(s) => write!(f, "{}", quote(s)),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                write!(f, "]")
+            }
+            Value::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{value}", quote(name))?;
+                }
+                write!(f, "}}")
+            }
+        };
+    }
+}
+
+/// A JSON string literal for `input`, quotes included.
+pub fn quote(input: &str) -> String {
+    let mut output = String::with_capacity(input.len() + 2);
+    output.push('"');
+    for c in input.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            '\u{08}' => output.push_str("\\b"),
+            '\u{0c}' => output.push_str("\\f"),
+            c if (c as u32) < 0x20 => {
+                output.push_str(&format!("\\u{:04x}", c as u32));
+            }
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+    return output;
+}
+
+/// What went wrong, without where.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ErrorKind {
+    TooLarge { size: usize, limit: u64 },
+    InvalidUtf8,
+    TooDeep { limit: usize },
+    UnexpectedEnd,
+    UnexpectedCharacter,
+    TrailingData,
+    UnknownLiteral,
+    ExpectedArraySeparator,
+    ExpectedName,
+    ExpectedColon,
+    ExpectedObjectSeparator,
+    ExpectedDigit,
+    ExpectedFractionDigit,
+    ExpectedExponentDigit,
+    NumberOutOfRange,
+    ShortEscape,
+    BadHexDigit,
+    UnterminatedString,
+    UnknownEscape,
+    ControlCharacter,
+    UnpairedLowSurrogate,
+    UnpairedHighSurrogate,
+    ExpectedLowSurrogate,
+    InvalidCodePoint,
+}
+
+impl ErrorKind {
+    pub fn describe(&self, tr: &Translator) -> String {
+        let key = match self {
+            ErrorKind::TooLarge { size, limit } => {
+                return tr.format("json.error.too_large",
+                    &[&size.to_string(), &limit.to_string()]);
+            }
+            ErrorKind::TooDeep { limit } => {
+                return tr.format("json.error.too_deep", &[&limit.to_string()]);
+            }
+            ErrorKind::InvalidUtf8 => "json.error.invalid_utf8",
+            ErrorKind::UnexpectedEnd => "json.error.unexpected_end",
+            ErrorKind::UnexpectedCharacter => "json.error.unexpected_character",
+            ErrorKind::TrailingData => "json.error.trailing_data",
+            ErrorKind::UnknownLiteral => "json.error.unknown_literal",
+            ErrorKind::ExpectedArraySeparator => "json.error.array_separator",
+            ErrorKind::ExpectedName => "json.error.name",
+            ErrorKind::ExpectedColon => "json.error.colon",
+            ErrorKind::ExpectedObjectSeparator => "json.error.object_separator",
+            ErrorKind::ExpectedDigit => "json.error.digit",
+            ErrorKind::ExpectedFractionDigit => "json.error.fraction_digit",
+            ErrorKind::ExpectedExponentDigit => "json.error.exponent_digit",
+            ErrorKind::NumberOutOfRange => "json.error.number_range",
+            ErrorKind::ShortEscape => "json.error.short_escape",
+            ErrorKind::BadHexDigit => "json.error.hex_digit",
+            ErrorKind::UnterminatedString => "json.error.unterminated_string",
+            ErrorKind::UnknownEscape => "json.error.unknown_escape",
+            ErrorKind::ControlCharacter => "json.error.control_character",
+            ErrorKind::UnpairedLowSurrogate => "json.error.low_surrogate",
+            ErrorKind::UnpairedHighSurrogate => "json.error.high_surrogate",
+            ErrorKind::ExpectedLowSurrogate => "json.error.expected_low",
+            ErrorKind::InvalidCodePoint => "json.error.code_point",
+        };
+        return String::from(tr.get(key));
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Error {
+    pub kind: ErrorKind,
+    /// Byte offset into the input where the problem was found.
+    pub offset: usize,
+    /// One-based, counting CR, LF and CRLF as line breaks.
+    pub line: usize,
+    /// One-based, in characters rather than bytes.
+    pub column: usize,
+}
+
+impl Error {
+    fn new(input: &[u8], offset: usize, kind: ErrorKind) -> Error {
+        let offset = offset.min(input.len());
+        let mut line = 1;
+        let mut line_start = 0;
+        for i in 0..offset {
+            match input[i] {
+                // The LF that follows ends the line.
+                b'\r' if i + 1 < offset && input[i + 1] == b'\n' => {}
+                b'\r' | b'\n' => {
+                    line += 1;
+                    line_start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        // Continuation bytes do not start a character.
+        let column = 1 + input[line_start..offset].iter()
+            .filter(|b| (*b & 0xc0) != 0x80)
+            .count();
+        return Error {
+            kind,
+            offset,
+            line,
+            column,
+        };
+    }
+
+    /// The line the error is on, cut down to about 60 characters around
+    /// the error, and a second line with a caret under the spot.
+    pub fn excerpt(&self, input: &[u8]) -> String {
+        const CONTEXT: usize = 30;
+        let offset = self.offset.min(input.len());
+        let start = input[..offset].iter()
+            .rposition(|b| *b == b'\n' || *b == b'\r')
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let end = input[offset..].iter()
+            .position(|b| *b == b'\n' || *b == b'\r')
+            .map(|i| offset + i)
+            .unwrap_or(input.len());
+        let before: Vec<char> = String::from_utf8_lossy(&input[start..offset])
+            .chars()
+            .collect();
+        let after: Vec<char> = String::from_utf8_lossy(&input[offset..end])
+            .chars()
+            .collect();
+        let skipped = before.len().saturating_sub(CONTEXT);
+        let mut line = String::new();
+        if skipped > 0 {
+            line.push('…');
+        }
+        line.extend(&before[skipped..]);
+        let caret = line.chars().count();
+        line.extend(after.iter().take(CONTEXT));
+        if after.len() > CONTEXT {
+            line.push('…');
+        }
+        // Tabs and other controls would throw the caret off.
+        let line: String = line.chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect();
+        return format!("{line}\n{}^", " ".repeat(caret));
+    }
+}
+
+impl Error {
+    /// The message with its line and column.
+    pub fn describe(&self, tr: &Translator) -> String {
+        return tr.format("json.error.at", &[&self.kind.describe(tr),
+            &self.line.to_string(), &self.column.to_string()]);
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}", self.describe(&catalog::english()));
+    }
+}
+
+/// Parses a complete JSON text. Anything but whitespace after the value is
+/// an error. Nesting is limited to DEFAULT_MAX_DEPTH but size is not, as
+/// this is also used for capture files.
+pub fn parse(input: &str) -> Result<Value, Error> {
+    let limits = Limits {
+        max_size: u64::MAX,
+        ..Limits::default()
+    };
+    return parse_with(input.as_bytes(), &limits);
+}
+
+/// Parses a complete JSON text from bytes, within `limits`. The bytes have
+/// to be UTF-8 (RFC 8259 section 8.1); a leading byte order mark is
+/// skipped as the RFC allows.
+pub fn parse_with(input: &[u8], limits: &Limits) -> Result<Value, Error> {
+    if input.len() as u64 > limits.max_size {
+        return Err(Error::new(input, 0, ErrorKind::TooLarge {
+            size: input.len(),
+            limit: limits.max_size,
+        }));
+    }
+    if let Err(e) = std::str::from_utf8(input) {
+        return Err(Error::new(input, e.valid_up_to(),
+            ErrorKind::InvalidUtf8));
+    }
+    let mut parser = Parser {
+        input,
+        position: 0,
+        depth: 0,
+        max_depth: limits.max_depth,
+    };
+    if input.starts_with(b"\xef\xbb\xbf") {
+        parser.position = 3;
+    }
+    parser.skip_whitespace();
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.position < parser.input.len() {
+        return Err(parser.error(ErrorKind::TrailingData));
+    }
+    return Ok(value);
+}
+
+/// Whether a Content-Type names JSON: application/json or any type with
+/// the +json structured syntax suffix (RFC 6839).
+pub fn is_json(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or("").trim()
+        .to_ascii_lowercase();
+    return essence == "application/json"
+        || essence.split_once('/')
+            .is_some_and(|(_, subtype)| subtype.ends_with("+json"));
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    position: usize,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, kind: ErrorKind) -> Error {
+        return Error::new(self.input, self.position, kind);
+    }
+
+    fn enter(&mut self) -> Result<(), Error> {
+        if self.depth >= self.max_depth {
+            return Err(self.error(ErrorKind::TooDeep {
+                limit: self.max_depth,
+            }));
+        }
+        self.depth += 1;
+        return Ok(());
+    }
+
+    fn peek(&self) -> Option<u8> {
+        return self.input.get(self.position).copied();
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn expect_literal(&mut self, literal: &str, value: Value)
+        -> Result<Value, Error>
+    {
+        if self.input[self.position..].starts_with(literal.as_bytes()) {
+            self.position += literal.len();
+            return Ok(value);
+        }
+        return Err(self.error(ErrorKind::UnknownLiteral));
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        return match self.peek() {
+            None => Err(self.error(ErrorKind::UnexpectedEnd)),
+            Some(b'n') => self.expect_literal("null", Value::Null),
+            Some(b't') => self.expect_literal("true", Value::Bool(true)),
+            Some(b'f') => self.expect_literal("false", Value::Bool(false)),
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b'[') => self.array(),
+            Some(b'{') => self.object(),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error(ErrorKind::UnexpectedCharacter)),
+        };
+    }
+
+    fn array(&mut self) -> Result<Value, Error> {
+        self.enter()?;
+        self.position += 1;
+        let mut items: Vec<Value> = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            self.depth -= 1;
+            return Ok(Value::Array(items));
+        }
+        loop {
+            self.skip_whitespace();
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    self.depth -= 1;
+                    return Ok(Value::Array(items));
+                }
+                _ => return Err(self.error(ErrorKind::ExpectedArraySeparator)),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, Error> {
+        self.enter()?;
+        self.position += 1;
+        let mut members: Vec<(String, Value)> = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+            self.depth -= 1;
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error(ErrorKind::ExpectedName));
+            }
+            let name = self.string()?;
+            self.skip_whitespace();
+            if self.peek() != Some(b':') {
+                return Err(self.error(ErrorKind::ExpectedColon));
+            }
+            self.position += 1;
+            self.skip_whitespace();
+            let value = self.value()?;
+            members.push((name, value));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    self.depth -= 1;
+                    return Ok(Value::Object(members));
+                }
+                _ => return Err(self.error(ErrorKind::ExpectedObjectSeparator)),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, Error> {
+        let start = self.position;
+        if self.peek() == Some(b'-') {
+            self.position += 1;
+        }
+        match self.peek() {
+            Some(b'0') => self.position += 1,
+            Some(b'1'..=b'9') => self.digits(),
+            _ => return Err(self.error(ErrorKind::ExpectedDigit)),
+        }
+        if self.peek() == Some(b'.') {
+            self.position += 1;
+            if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                return Err(self.error(ErrorKind::ExpectedFractionDigit));
+            }
+            self.digits();
+        }
+        if let Some(b'e' | b'E') = self.peek() {
+            self.position += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.position += 1;
+            }
+            if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                return Err(self.error(ErrorKind::ExpectedExponentDigit));
+            }
+            self.digits();
+        }
+        // The grammar above only lets ASCII through.
+        let text = std::str::from_utf8(&self.input[start..self.position])
+            .unwrap_or("0");
+        // Rust rounds overflow to infinity rather than failing.
+        return match text.parse::<f64>() {
+            Ok(n) if n.is_finite() => Ok(Value::Number(n)),
+            _ => Err(Error::new(self.input, start,
+                ErrorKind::NumberOutOfRange)),
+        };
+    }
+
+    fn digits(&mut self) {
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, Error> {
+        let digits = match self.input.get(self.position..self.position + 4) {
+            Some(digits) => digits,
+            None => return Err(self.error(ErrorKind::ShortEscape)),
+        };
+        let mut value: u32 = 0;
+        for d in digits {
+            match (*d as char).to_digit(16) {
+                Some(n) => value = (value << 4) | n,
+                None => return Err(self.error(ErrorKind::BadHexDigit)),
+            }
+        }
+        self.position += 4;
+        return Ok(value);
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        self.position += 1;
+        let mut output: Vec<u8> = Vec::new();
+        loop {
+            let byte = match self.peek() {
+                Some(byte) => byte,
+                None => return Err(self.error(ErrorKind::UnterminatedString)),
+            };
+            match byte {
+                b'"' => {
+                    self.position += 1;
+                    break;
+                }
+                b'\\' => {
+                    self.position += 1;
+                    let escape = match self.peek() {
+                        Some(escape) => escape,
+                        None => {
+                            return Err(self.error(
+                                ErrorKind::UnterminatedString));
+                        }
+                    };
+                    self.position += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{08}',
+                        b'f' => '\u{0c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => {
+                            self.position -= 1;
+                            return Err(self.error(ErrorKind::UnknownEscape));
+                        }
+                    };
+                    let mut buffer = [0u8; 4];
+                    output.extend_from_slice(c.encode_utf8(&mut buffer)
+                        .as_bytes());
+                }
+                0x00..=0x1f => {
+                    return Err(self.error(ErrorKind::ControlCharacter));
+                }
+                _ => {
+                    output.push(byte);
+                    self.position += 1;
+                }
+            }
+        }
+        // The input was checked so everything between the escapes is UTF-8.
+        return Ok(String::from_utf8(output).unwrap_or_default());
+    }
+
+    /// The part after `\u`, including a following low surrogate escape if
+    /// the first one is a high surrogate.
+    fn unicode_escape(&mut self) -> Result<char, Error> {
+        let first = self.hex4()?;
+        if (0xdc00..0xe000).contains(&first) {
+            return Err(self.error(ErrorKind::UnpairedLowSurrogate));
+        }
+        if !(0xd800..0xdc00).contains(&first) {
+            return char::from_u32(first)
+                .ok_or_else(|| self.error(ErrorKind::InvalidCodePoint));
+        }
+        if !self.input[self.position..].starts_with(b"\\u") {
+            return Err(self.error(ErrorKind::UnpairedHighSurrogate));
+        }
+        self.position += 2;
+        let second = self.hex4()?;
+        if !(0xdc00..0xe000).contains(&second) {
+            return Err(self.error(ErrorKind::ExpectedLowSurrogate));
+        }
+        let code = 0x10000 + ((first - 0xd800) << 10) + (second - 0xdc00);
+        return char::from_u32(code)
+            .ok_or_else(|| self.error(ErrorKind::InvalidCodePoint));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Texts JSONTestSuite expects every parser to accept, named after its
+    /// y_ files.
+    const ACCEPTED: &[(&str, &str)] = &[
+        ("y_array_empty", "[]"),
+        ("y_array_heterogeneous", "[null, 1, \"1\", {}]"),
+        ("y_number_negative_zero", "[-0]"),
+        ("y_number_real_capital_e_neg_exp", "[1E-2]"),
+        ("y_number_real_fraction_exponent", "[123.456e78]"),
+        ("y_object_duplicated_key", "{\"a\":\"b\",\"a\":\"c\"}"),
+        ("y_object_empty_key", "{\"\":0}"),
+        ("y_string_accepted_surrogate_pair", "[\"\\ud801\\udc37\"]"),
+        ("y_string_allowed_escapes", "[\"\\\"\\\\\\/\\b\\f\\n\\r\\t\"]"),
+        ("y_string_unicode_escaped_double_quote", "[\"\\u0022\"]"),
+        ("y_string_utf8", "[\"\u{20ac}\u{1d11e}\"]"),
+        ("y_structure_lonely_false", "false"),
+        ("y_structure_lonely_string", "\"asd\""),
+        ("y_structure_whitespace_array", " [] "),
+        ("y_structure_trailing_newline", "[\"a\"]\n"),
+    ];
+
+    /// Texts JSONTestSuite expects every parser to reject, named after its
+    /// n_ files.
+    const REJECTED: &[(&str, &str)] = &[
+        ("n_array_extra_comma", "[\"\",]"),
+        ("n_array_missing_value", "[   , \"\"]"),
+        ("n_array_unclosed", "[\"\""),
+        ("n_number_leading_zero", "[012]"),
+        ("n_number_plus_1", "[+1]"),
+        ("n_number_real_without_fractional_part", "[1.]"),
+        ("n_number_starting_with_dot", "[.123]"),
+        ("n_number_infinity", "[Infinity]"),
+        ("n_number_NaN", "[NaN]"),
+        ("n_object_trailing_comma", "{\"id\":0,}"),
+        ("n_object_unquoted_key", "{a: \"b\"}"),
+        ("n_object_single_quote", "{'a':0}"),
+        ("n_string_escape_x", "[\"\\x00\"]"),
+        ("n_string_unescaped_tab", "[\"\t\"]"),
+        ("n_string_incomplete_surrogate", "[\"\\uD834\\uDd\"]"),
+        ("n_structure_double_array", "[][]"),
+        ("n_structure_no_data", ""),
+        ("n_structure_capitalized_True", "[True]"),
+        ("n_structure_comma_instead_of_closing_brace", "{\"x\": true,"),
+        ("n_incomplete_true", "[tru]"),
+    ];
+
+    #[test]
+    fn json_test_suite() {
+        for (name, input) in ACCEPTED {
+            assert!(parse(input).is_ok(), "{name} was rejected");
+        }
+        for (name, input) in REJECTED {
+            assert!(parse(input).is_err(), "{name} was accepted");
+        }
+    }
+
+    /// RFC 8259 section 8.2 leaves lone surrogates to the implementation;
+    /// this one rejects them.
+    #[test]
+    fn lone_surrogates() {
+        assert_eq!(parse("[\"\\uDFAA\"]").unwrap_err().kind,
+            ErrorKind::UnpairedLowSurrogate);
+        assert_eq!(parse("[\"\\uD800\"]").unwrap_err().kind,
+            ErrorKind::UnpairedHighSurrogate);
+    }
+
+    #[test]
+    fn values() {
+        let value = parse("{\"a\": [1.5, -2e2, true, null], \"b\": \
+            \"\\ud83d\\ude00\"}").unwrap();
+        assert_eq!(value.get("a"), Some(&Value::Array(vec![
+            Value::Number(1.5),
+            Value::Number(-200.0),
+            Value::Bool(true),
+            Value::Null,
+        ])));
+        assert_eq!(value.get("b").and_then(|v| v.as_str()), Some("\u{1f600}"));
+        let duplicates = parse("{\"a\":1,\"a\":2}").unwrap();
+        assert_eq!(duplicates.as_object().map(|m| m.len()), Some(2));
+        assert_eq!(duplicates.get("a"), Some(&Value::Number(2.0)));
+    }
+
+    #[test]
+    fn error_positions() {
+        let error = parse("{\n  \"a\": tru\n}").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::UnknownLiteral);
+        assert_eq!((error.line, error.column), (2, 8));
+        let error = parse("[1] x").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::TrailingData);
+        assert_eq!(error.offset, 4);
+    }
+
+    #[test]
+    fn limits() {
+        let limits = Limits { max_depth: 3, max_size: 16 };
+        assert!(parse_with(b"[[[]]]", &limits).is_ok());
+        assert_eq!(parse_with(b"[[[[]]]]", &limits).unwrap_err().kind,
+            ErrorKind::TooDeep { limit: 3 });
+        assert_eq!(parse_with(b"\"0123456789abcdef\"", &limits).unwrap_err()
+            .kind, ErrorKind::TooLarge { size: 18, limit: 16 });
+        assert_eq!(parse_with(b"[\"\xff\"]", &limits).unwrap_err().kind,
+            ErrorKind::InvalidUtf8);
+        assert!(parse_with(b"\xef\xbb\xbf[]", &limits).is_ok());
+    }
+
+    #[test]
+    fn quote_round_trips() {
+        let text = "tab\t \"quote\" back\\slash \u{1} \u{20ac}";
+        assert_eq!(parse(&quote(text)).unwrap(),
+            Value::String(String::from(text)));
+    }
+
+    #[test]
+    fn content_types() {
+        assert!(is_json("application/json; charset=utf-8"));
+        assert!(is_json("Application/Problem+JSON"));
+        assert!(!is_json("text/json-ish"));
+        assert!(!is_json("application/jsonp"));
+    }
+}
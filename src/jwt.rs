@@ -0,0 +1,598 @@
+// src/jwt.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Bearer tokens (RFC 6750) carrying HMAC signed JWTs (RFC 7519).
+//!
+//! Only the HS256, HS384 and HS512 algorithms are accepted. The keys come
+//! from a file with one `kid = secret` line per key:
+//!
+//! ```text
+//! # The key used when a token has no kid.
+//! default = base64:c2VjcmV0IGtleSBvbmU=
+//! api-2025 = hex:736563726574206b65792074776f
+//! legacy = a plain text secret
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::authorization::authorization_header;
+use crate::authorization::quote;
+use crate::authorization::strip_scheme;
+use crate::base64;
+use crate::catalog;
+use crate::catalog::Translator;
+use crate::config;
+use crate::hex;
+use crate::hmac::constant_time_eq;
+use crate::hmac::hmac;
+use crate::html;
+use crate::http_date::unix_seconds;
+use crate::json;
+use crate::json::Value;
+use crate::response::Response;
+use crate::sha256::Sha256;
+use crate::sha512::Sha384;
+use crate::sha512::Sha512;
+
+/// Seconds of clock difference allowed when checking exp, nbf and iat.
+pub const DEFAULT_LEEWAY: i64 = 60;
+
+/// The kid used for tokens that do not name one.
+pub const DEFAULT_KEY_ID: &str = "default";
+
+/// The three parts of a compact JWS.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TokenPart {
+    Header,
+    Claims,
+    Signature,
+}
+
+/// Why a token was turned down.
+#[derive(Debug, PartialEq)]
+pub enum Rejection {
+    /// A JWT has three dot separated parts; this is how many there were.
+    PartCount(usize),
+    NotBase64(TokenPart),
+    NotUtf8(TokenPart),
+    NotJson(TokenPart, json::Error),
+    NotObject(TokenPart),
+    NoAlgorithm,
+    Algorithm(String),
+    UnknownKey(String),
+    NoDefaultKey,
+    Signature { algorithm: String, key: String },
+    /// The named claim is not a number.
+    NotNumber(String),
+    /// Seconds since it expired.
+    Expired(i64),
+    /// Seconds until it becomes valid.
+    NotYetValid(i64),
+    /// Seconds into the future it was issued.
+    IssuedInFuture(i64),
+    Issuer { found: Option<String>, expected: String },
+    Audience(String),
+    /// TESTCGI_JWT_KEYS is not set.
+    NoKeyFile,
+    /// The key file could not be used; the message says why.
+    Keys(String),
+}
+
+impl Rejection {
+    pub fn describe(&self, tr: &Translator) -> String {
+        let part = |part: &TokenPart| tr.get(match part {
+            TokenPart::Header => "jwt.part.header",
+            TokenPart::Claims => "jwt.part.claims",
+            TokenPart::Signature => "jwt.part.signature",
+        });
+        return match self {
+            Rejection::PartCount(count) => {
+                tr.format("jwt.rejection.parts", &[&count.to_string()])
+            }
+            Rejection::NotBase64(which) => {
+                tr.format("jwt.rejection.base64", &[part(which)])
+            }
+            Rejection::NotUtf8(which) => {
+                tr.format("jwt.rejection.utf8", &[part(which)])
+            }
+            Rejection::NotJson(which, error) => {
+                tr.format("jwt.rejection.json", &[part(which),
+                    &error.describe(tr)])
+            }
+            Rejection::NotObject(which) => {
+                tr.format("jwt.rejection.object", &[part(which)])
+            }
+            Rejection::NoAlgorithm => String::from(tr.get("jwt.rejection.alg")),
+            Rejection::Algorithm(name) => {
+                tr.format("jwt.rejection.algorithm", &[&format!("{name:?}")])
+            }
+            Rejection::UnknownKey(kid) => {
+                tr.format("jwt.rejection.kid", &[&format!("{kid:?}")])
+            }
+            Rejection::NoDefaultKey => {
+                String::from(tr.get("jwt.rejection.default_key"))
+            }
+            Rejection::Signature { algorithm, key } => {
+                tr.format("jwt.rejection.signature", &[algorithm,
+                    &format!("{key:?}")])
+            }
+            Rejection::NotNumber(claim) => {
+                tr.format("jwt.rejection.number", &[claim])
+            }
+            Rejection::Expired(seconds) => {
+                tr.format("jwt.rejection.expired", &[&seconds.to_string()])
+            }
+            Rejection::NotYetValid(seconds) => {
+                tr.format("jwt.rejection.not_before", &[&seconds.to_string()])
+            }
+            Rejection::IssuedInFuture(seconds) => {
+                tr.format("jwt.rejection.issued", &[&seconds.to_string()])
+            }
+            Rejection::Issuer { found: Some(found), expected } => {
+                tr.format("jwt.rejection.issuer", &[&format!("{found:?}"),
+                    &format!("{expected:?}")])
+            }
+            Rejection::Issuer { found: None, expected } => {
+                tr.format("jwt.rejection.no_issuer",
+                    &[&format!("{expected:?}")])
+            }
+            Rejection::Audience(audience) => {
+                tr.format("jwt.rejection.audience", &[&format!("{audience:?}")])
+            }
+            Rejection::NoKeyFile => {
+                String::from(tr.get("jwt.rejection.no_keys"))
+            }
+            Rejection::Keys(message) => {
+                tr.format("jwt.rejection.keys", &[message])
+            }
+        };
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}", self.describe(&catalog::english()));
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Algorithm {
+    HS256,
+    HS384,
+    HS512,
+}
+
+impl Algorithm {
+    pub fn parse(name: &str) -> Option<Algorithm> {
+        return match name {
+            "HS256" => Some(Algorithm::HS256),
+            "HS384" => Some(Algorithm::HS384),
+            "HS512" => Some(Algorithm::HS512),
+            _ => None,
+        };
+    }
+
+    pub fn sign(&self, key: &[u8], message: &[u8]) -> Vec<u8> {
+        return match self {
+            Algorithm::HS256 => hmac::<Sha256>(key, message),
+            Algorithm::HS384 => hmac::<Sha384>(key, message),
+            Algorithm::HS512 => hmac::<Sha512>(key, message),
+        };
+    }
+}
+
+pub struct Key {
+    pub id: String,
+    pub secret: Vec<u8>,
+}
+
+pub struct KeySet {
+    keys: Vec<Key>,
+}
+
+impl KeySet {
+    /// Errors name the line they were found on.
+    pub fn parse(contents: &str) -> Result<KeySet, String> {
+        let mut keys: Vec<Key> = Vec::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (id, secret) = match line.split_once('=') {
+                Some((id, secret)) => (id.trim(), secret.trim()),
+                None => {
+                    return Err(format!("line {}: expected kid = secret",
+                        number + 1));
+                }
+            };
+            let secret = match parse_secret(secret) {
+                Some(secret) if !secret.is_empty() => secret,
+                _ => {
+                    return Err(format!("line {}: the secret for {id:?} is \
+                        empty or badly encoded", number + 1));
+                }
+            };
+            keys.push(Key { id: String::from(id), secret });
+        }
+        return Ok(KeySet { keys });
+    }
+
+    pub fn load(path: &Path) -> Result<KeySet, String> {
+        return match fs::read_to_string(path) {
+            Ok(contents) => KeySet::parse(&contents)
+                .map_err(|e| format!("{}: {e}", path.display())),
+            Err(e) => Err(format!("{}: {e}", path.display())),
+        };
+    }
+
+    /// The key named by `kid`. Without a kid the `default` key is used, or
+    /// the only key if there is just one.
+    pub fn find(&self, kid: Option<&str>) -> Option<&Key> {
+        return match kid {
+            Some(kid) => self.keys.iter().find(|k| k.id == kid),
+            None => self.keys.iter().find(|k| k.id == DEFAULT_KEY_ID)
+                .or(if self.keys.len() == 1 { self.keys.first() } else {
+                    None
+                }),
+        };
+    }
+}
+
+/// A secret is plain text unless it starts with `base64:`, `base64url:` or
+/// `hex:`.
+pub fn parse_secret(input: &str) -> Option<Vec<u8>> {
+    if let Some(encoded) = input.strip_prefix("base64:") {
+        return base64::decode(encoded);
+    }
+    if let Some(encoded) = input.strip_prefix("base64url:") {
+        return base64::decode_url(encoded);
+    }
+    if let Some(encoded) = input.strip_prefix("hex:") {
+        return hex::decode(encoded);
+    }
+    return Some(input.as_bytes().to_vec());
+}
+
+/// The claims a token has to satisfy besides a good signature.
+pub struct Validation {
+    /// If set, `aud` has to be this or an array containing it.
+    pub audience: Option<String>,
+    /// If set, `iss` has to be exactly this.
+    pub issuer: Option<String>,
+    pub leeway: i64,
+}
+
+impl Validation {
+    /// TESTCGI_JWT_AUDIENCE, TESTCGI_JWT_ISSUER and TESTCGI_JWT_LEEWAY.
+    pub fn from_env() -> Validation {
+        return Validation {
+            audience: config::get("TESTCGI_JWT_AUDIENCE"),
+            issuer: config::get("TESTCGI_JWT_ISSUER"),
+            leeway: config::get("TESTCGI_JWT_LEEWAY")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_LEEWAY),
+        };
+    }
+}
+
+/// A JWT that has been split up and decoded but not yet verified.
+pub struct Token {
+    pub header: Value,
+    pub claims: Value,
+    signing_input: String,
+    signature: Vec<u8>,
+}
+
+/// The token68 from an `Authorization: Bearer` header.
+pub fn bearer_token(header: &str) -> Option<&str> {
+    let token = strip_scheme(header, "Bearer")?.trim();
+    if token.is_empty() {
+        return None;
+    }
+    return Some(token);
+}
+
+/// Splits a compact JWS and decodes the header and claims.
+pub fn decode(token: &str) -> Result<Token, Rejection> {
+    let parts: Vec<&str> = token.split('.').collect();
+    if parts.len() != 3 {
+        return Err(Rejection::PartCount(parts.len()));
+    }
+    let decode_json = |part: &str,
+                       what: TokenPart|
+     -> Result<Value, Rejection> {
+        let bytes = base64::decode_url(part)
+            .ok_or(Rejection::NotBase64(what))?;
+        let text = String::from_utf8(bytes)
+            .map_err(|_| Rejection::NotUtf8(what))?;
+        let value = json::parse(&text)
+            .map_err(|e| Rejection::NotJson(what, e))?;
+        if value.as_object().is_none() {
+            return Err(Rejection::NotObject(what));
+        }
+        return Ok(value);
+    };
+    let header = decode_json(parts[0], TokenPart::Header)?;
+    let claims = decode_json(parts[1], TokenPart::Claims)?;
+    let signature = base64::decode_url(parts[2])
+        .ok_or(Rejection::NotBase64(TokenPart::Signature))?;
+    return Ok(Token {
+        header,
+        claims,
+        signing_input: format!("{}.{}", parts[0], parts[1]),
+        signature,
+    });
+}
+
+/// Checks the signature and the registered claims. `now` is Unix seconds.
+pub fn verify(token: &Token, keys: &KeySet, validation: &Validation, now: i64)
+    -> Result<(), Rejection>
+{
+    let algorithm_name = token.header.get("alg").and_then(|v| v.as_str())
+        .ok_or(Rejection::NoAlgorithm)?;
+    let algorithm = match Algorithm::parse(algorithm_name) {
+        Some(algorithm) => algorithm,
+        None => {
+            return Err(Rejection::Algorithm(String::from(algorithm_name)));
+        }
+    };
+    let kid = token.header.get("kid").and_then(|v| v.as_str());
+    let key = match keys.find(kid) {
+        Some(key) => key,
+        None => {
+            return Err(match kid {
+                Some(kid) => Rejection::UnknownKey(String::from(kid)),
+                None => Rejection::NoDefaultKey,
+            });
+        }
+    };
+    let expected = algorithm.sign(&key.secret, token.signing_input.as_bytes());
+    if !constant_time_eq(&expected, &token.signature) {
+        return Err(Rejection::Signature {
+            algorithm: String::from(algorithm_name),
+            key: key.id.clone(),
+        });
+    }
+
+    let number_claim = |name: &str| -> Result<Option<i64>, Rejection> {
+        return match token.claims.get(name) {
+            None => Ok(None),
+            Some(value) => value.as_f64()
+                .map(|n| Some(n.floor() as i64))
+                .ok_or(Rejection::NotNumber(String::from(name))),
+        };
+    };
+    if let Some(exp) = number_claim("exp")?
+        && now - validation.leeway >= exp {
+        return Err(Rejection::Expired(now - exp));
+    }
+    if let Some(nbf) = number_claim("nbf")?
+        && now + validation.leeway < nbf {
+        return Err(Rejection::NotYetValid(nbf - now));
+    }
+    if let Some(iat) = number_claim("iat")?
+        && now + validation.leeway < iat {
+        return Err(Rejection::IssuedInFuture(iat - now));
+    }
+    if let Some(issuer) = &validation.issuer {
+        let iss = token.claims.get("iss").and_then(|v| v.as_str());
+        if iss != Some(issuer.as_str()) {
+            return Err(Rejection::Issuer {
+                found: iss.map(String::from),
+                expected: issuer.clone(),
+            });
+        }
+    }
+    if let Some(audience) = &validation.audience {
+        let matches = match token.claims.get("aud") {
+            Some(Value::String(aud)) => aud == audience,
+            Some(Value::Array(auds)) => auds.iter()
+                .any(|aud| aud.as_str() == Some(audience.as_str())),
+            _ => false,
+        };
+        if !matches {
+            return Err(Rejection::Audience(audience.clone()));
+        }
+    }
+    return Ok(());
+}
+
+/// Loads the keys from TESTCGI_JWT_KEYS.
+pub fn keys_from_env() -> Result<KeySet, Rejection> {
+    return match config::get("TESTCGI_JWT_KEYS") {
+        Some(path) => KeySet::load(Path::new(&path)).map_err(Rejection::Keys),
+        None => Err(Rejection::NoKeyFile),
+    };
+}
+
+/// Decodes and verifies a bearer token against the keys and rules from the
+/// environment. The token comes back even when verification fails, so the
+/// claims can still be shown.
+pub fn check(bearer: &str) -> (Option<Token>, Result<(), Rejection>) {
+    let token = match decode(bearer) {
+        Ok(token) => token,
+        Err(reason) => return (None, Err(reason)),
+    };
+    let keys = match keys_from_env() {
+        Ok(keys) => keys,
+        Err(reason) => return (Some(token), Err(reason)),
+    };
+    let now = unix_seconds(SystemTime::now());
+    let verdict = verify(&token, &keys, &Validation::from_env(), now);
+    return (Some(token), verdict);
+}
+
+/// `/bearer` requires a valid bearer token and answers with its claims.
+pub fn route() -> Response {
+    let realm = "testcgi";
+    let bearer = authorization_header()
+        .and_then(|header| bearer_token(&header).map(String::from));
+    let bearer = match bearer {
+        Some(bearer) => bearer,
+        None => {
+            let mut response = Response::text(401,
+                "A bearer token is required.");
+            response.header("WWW-Authenticate",
+                &format!("Bearer realm={}", quote(realm)));
+            return response;
+        }
+    };
+    return match check(&bearer) {
+        (Some(token), Ok(())) => {
+            let mut response = Response::new(200);
+            response.header("Content-Type", "application/json");
+            response.body.extend_from_slice(token.claims.pretty().as_bytes());
+            response.body.push(b'\n');
+            response
+        }
+        (_, Err(reason)) => {
+            let reason = reason.to_string();
+            let mut response = Response::text(401, &reason);
+            response.header("WWW-Authenticate", &format!(
+                "Bearer realm={}, error=\"invalid_token\", \
+                error_description={}", quote(realm), quote(&reason)));
+            response
+        }
+        (None, Ok(())) => Response::text(500, "Verified a token that did \
+            not decode."),
+    };
+}
+
+/// The bearer token section of the diagnostic page in the language of `tr`,
+/// or None when the request has no bearer token.
+pub fn diagnostic_html(tr: &Translator) -> Option<String> {
+    let header = authorization_header()?;
+    let bearer = bearer_token(&header)?;
+    let (token, verdict) = check(bearer);
+    let mut output = format!("<h1>{}</h1>",
+        html::escape(tr.get("heading.bearer")));
+    let verdict = match verdict {
+        Ok(()) => String::from(tr.get("jwt.accepted")),
+        Err(reason) => tr.format("jwt.rejected", &[&reason.describe(tr)]),
+    };
+    output.push_str(&format!("<p>{}</p>", html::escape(&verdict)));
+    if let Some(token) = token {
+        output.push_str(&format!("<h2>{}</h2><pre>{}</pre>",
+            html::escape(tr.get("jwt.header")),
+            html::escape(&token.header.pretty())));
+        output.push_str(&format!("<h2>{}</h2><pre>{}</pre>",
+            html::escape(tr.get("jwt.claims")),
+            html::escape(&token.claims.pretty())));
+    }
+    output.push_str("<hr />");
+    return Some(output);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The HS256 example from RFC 7515 appendix A.1.
+    const TOKEN: &str = "eyJ0eXAiOiJKV1QiLA0KICJhbGciOiJIUzI1NiJ9.eyJpc3MiOiJq\
+        b2UiLA0KICJleHAiOjEzMDA4MTkzODAsDQogImh0dHA6Ly9leGFtcGxlLmNvbS9pc19yb2\
+        90Ijp0cnVlfQ.dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    const KEYS: &str = "default = base64url:AyM1SysPpbyDfgZld3umj1qzKObwVMkoq\
+        Q-EstJQLr_T-1qS0gZH75aKtMN3Yj0iPS4hcgUuTwjAzZr1Z9CAow";
+    const EXP: i64 = 1300819380;
+
+    fn validation() -> Validation {
+        return Validation { audience: None, issuer: None, leeway: 0 };
+    }
+
+    #[test]
+    fn rfc_7515_example() {
+        let token = decode(TOKEN).unwrap();
+        assert_eq!(token.claims.get("iss").and_then(|v| v.as_str()),
+            Some("joe"));
+        let keys = KeySet::parse(KEYS).unwrap();
+        assert!(verify(&token, &keys, &validation(), EXP - 1).is_ok());
+        assert!(matches!(verify(&token, &keys, &validation(), EXP),
+            Err(Rejection::Expired(0))));
+    }
+
+    #[test]
+    fn claim_checks() {
+        let token = decode(TOKEN).unwrap();
+        let keys = KeySet::parse(KEYS).unwrap();
+        let issuer = Validation {
+            issuer: Some(String::from("jane")),
+            ..validation()
+        };
+        assert!(matches!(verify(&token, &keys, &issuer, EXP - 1),
+            Err(Rejection::Issuer { .. })));
+        let audience = Validation {
+            audience: Some(String::from("api")),
+            ..validation()
+        };
+        assert!(matches!(verify(&token, &keys, &audience, EXP - 1),
+            Err(Rejection::Audience(_))));
+        let leeway = Validation { leeway: 60, ..validation() };
+        assert!(verify(&token, &keys, &leeway, EXP + 59).is_ok());
+    }
+
+    #[test]
+    fn bad_signature() {
+        let token = decode(TOKEN).unwrap();
+        let keys = KeySet::parse("default = another secret").unwrap();
+        assert!(matches!(verify(&token, &keys, &validation(), EXP - 1),
+            Err(Rejection::Signature { .. })));
+        let tampered = TOKEN.replace("dBjf", "dBjg");
+        let token = decode(&tampered).unwrap();
+        let keys = KeySet::parse(KEYS).unwrap();
+        assert!(matches!(verify(&token, &keys, &validation(), EXP - 1),
+            Err(Rejection::Signature { .. })));
+    }
+
+    #[test]
+    fn malformed_tokens() {
+        assert!(matches!(decode("a.b"), Err(Rejection::PartCount(2))));
+        assert!(matches!(decode("!.e30.AA"),
+            Err(Rejection::NotBase64(TokenPart::Header))));
+        // "[]" is JSON but not an object.
+        assert!(matches!(decode("W10.e30.AA"),
+            Err(Rejection::NotObject(TokenPart::Header))));
+    }
+
+    /// The alg name comes from the client and ends up in a response header,
+    /// so a CR or LF in it must not survive into the quoted reason.
+    #[test]
+    fn algorithm_name_is_escaped() {
+        // {"alg":"x\r\nSet-Cookie: pwned=1"}
+        let token = decode("eyJhbGciOiJ4XHJcblNldC1Db29raWU6IHB3bmVkPTEifQ\
+            .e30.AA").unwrap();
+        let keys = KeySet::parse(KEYS).unwrap();
+        let rejection = verify(&token, &keys, &validation(), EXP - 1)
+            .unwrap_err();
+        assert!(matches!(rejection, Rejection::Algorithm(_)));
+        let reason = rejection.describe(&catalog::english());
+        assert!(!reason.contains(['\r', '\n']));
+        assert!(reason.contains("\\r\\nSet-Cookie"));
+        assert_eq!(quote("a\r\nb\t\"c\""), "\"ab\t\\\"c\\\"\"");
+    }
+
+    /// RFC 4231 test case 2 through the JWT algorithms.
+    #[test]
+    fn algorithms() {
+        let message = b"what do ya want for nothing?";
+        assert_eq!(hex::encode(&Algorithm::HS256.sign(b"Jefe", message)),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+        assert_eq!(Algorithm::parse("none"), None);
+        assert_eq!(Algorithm::parse("hs256"), None);
+    }
+}
@@ -0,0 +1,67 @@
+// src/lib.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! The pieces of testcgi that other CGI programs can reuse.
+
+#![allow(clippy::needless_return)]
+
+pub mod accept_language;
+pub mod access;
+pub mod access_log;
+pub mod authorization;
+pub mod base64;
+pub mod basic_auth;
+pub mod bcrypt;
+pub mod body;
+pub mod capture;
+pub mod catalog;
+pub mod charset;
+pub mod cidr;
+pub mod config;
+pub mod conformance;
+pub mod cookie;
+pub mod crc32;
+pub mod crypt;
+pub mod csrf;
+pub mod decode_form;
+pub mod decode_query_string;
+pub mod decode_urlencoded;
+pub mod digest;
+pub mod digest_auth;
+pub mod encode_urlencoded;
+pub mod env_diff;
+pub mod forwarded;
+pub mod hex;
+pub mod hmac;
+pub mod htpasswd;
+pub mod html;
+pub mod http_date;
+pub mod json;
+pub mod jwt;
+pub mod log;
+pub mod md5;
+pub mod random;
+pub mod redact;
+pub mod regex;
+pub mod request;
+pub mod request_id;
+pub mod response;
+pub mod route;
+pub mod server;
+pub mod session;
+pub mod sha256;
+pub mod sha512;
+pub mod slow;
+pub mod url;
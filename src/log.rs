@@ -0,0 +1,234 @@
+// src/log.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Diagnostic logging to stderr, which CGI servers copy into their error log.
+//!
+//! Each message is one line with a level and key/value fields, either as
+//! text:
+//!
+//! ```text
+//! testcgi: error: cannot read the body expected=512 request_id=4bf9
+//! ```
+//!
+//! or as a JSON object when TESTCGI_LOG_FORMAT is `json`. TESTCGI_LOG_LEVEL
+//! sets the quietest level that is written and defaults to `warn`.
+
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::process;
+use std::time::SystemTime;
+
+use crate::config;
+use crate::http_date::format_rfc3339;
+use crate::json::quote;
+use crate::request_id;
+
+pub const LEVEL_VARIABLE: &str = "TESTCGI_LOG_LEVEL";
+pub const FORMAT_VARIABLE: &str = "TESTCGI_LOG_FORMAT";
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    /// Every name `parse` takes, in any case, for the log.level setting.
+    /// `warning` is another name for warn.
+    pub const NAMES: [&str; 5] = ["error", "warn", "warning", "info", "debug"];
+
+    /// The level for each of NAMES.
+    const BY_NAME: [Level; 5] =
+        [Level::Error, Level::Warn, Level::Warn, Level::Info, Level::Debug];
+
+    pub fn parse(input: &str) -> Option<Level> {
+        let input = input.trim();
+        let index = Level::NAMES.iter()
+            .position(|name| name.eq_ignore_ascii_case(input))?;
+        return Some(Level::BY_NAME[index]);
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Level::Error => write!(f, "error"),
+            Level::Warn => write!(f, "warn"),
+            Level::Info => write!(f, "info"),
+            Level::Debug => write!(f, "debug"),
+        };
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl Format {
+    /// Every name `parse` takes, in any case, for the log.format setting.
+    pub const NAMES: [&str; 2] = ["text", "json"];
+
+    pub fn parse(input: &str) -> Option<Format> {
+        let input = input.trim();
+        if input.eq_ignore_ascii_case("text") {
+            return Some(Format::Text);
+        }
+        if input.eq_ignore_ascii_case("json") {
+            return Some(Format::Json);
+        }
+        return None;
+    }
+}
+
+/// A key and anything that can be displayed.
+pub type Field<'a> = (&'a str, &'a dyn fmt::Display);
+
+/// The quietest level that will be written.
+pub fn max_level() -> Level {
+    return config::get(LEVEL_VARIABLE)
+        .and_then(|v| Level::parse(&v))
+        .unwrap_or(Level::Warn);
+}
+
+pub fn enabled(level: Level) -> bool {
+    return level <= max_level();
+}
+
+pub fn format() -> Format {
+    return config::get(FORMAT_VARIABLE)
+        .and_then(|v| Format::parse(&v))
+        .unwrap_or(Format::Text);
+}
+
+pub fn error(message: &str, fields: &[Field]) {
+    log(Level::Error, message, fields);
+}
+
+pub fn warn(message: &str, fields: &[Field]) {
+    log(Level::Warn, message, fields);
+}
+
+pub fn info(message: &str, fields: &[Field]) {
+    log(Level::Info, message, fields);
+}
+
+pub fn debug(message: &str, fields: &[Field]) {
+    log(Level::Debug, message, fields);
+}
+
+/// Writes the message if its level is enabled. The line goes out in one
+/// write so messages from concurrent requests stay on their own lines. The
+/// request ID is added to every message.
+pub fn log(level: Level, message: &str, fields: &[Field]) {
+    if !enabled(level) {
+        return;
+    }
+    let request_id = &request_id::current().id;
+    let mut all: Vec<Field> = Vec::with_capacity(fields.len() + 1);
+    all.extend_from_slice(fields);
+    all.push(("request_id", request_id));
+    let line = render(format(), level, message, &all, SystemTime::now());
+    // There is nowhere left to report a failure to write to stderr.
+    _ = io::stderr().lock().write_all(line.as_bytes());
+}
+
+/// The line for a message, ending in a newline.
+pub fn render(format: Format, level: Level, message: &str, fields: &[Field],
+    time: SystemTime) -> String
+{
+    let mut line = String::with_capacity(128);
+    match format {
+        Format::Text => {
+            line.push_str(&format!("testcgi: {level}: {}",
+                single_line(message)));
+            for (key, value) in fields {
+                line.push(' ');
+                line.push_str(key);
+                line.push('=');
+                line.push_str(&text_value(&value.to_string()));
+            }
+        }
+        Format::Json => {
+            line.push_str(&format!("{{\"time\":{},\"level\":\"{level}\",\
+                \"pid\":{},\"msg\":{}", quote(&format_rfc3339(time)),
+                process::id(), quote(message)));
+            for (key, value) in fields {
+                line.push_str(&format!(",{}:{}", quote(key),
+                    quote(&value.to_string())));
+            }
+            line.push('}');
+        }
+    }
+    line.push('\n');
+    return line;
+}
+
+/// Quotes a text value when it would otherwise be hard to pick out.
+fn text_value(value: &str) -> String {
+    let plain = !value.is_empty() && value.chars().all(|c| c.is_ascii_graphic()
+        && c != '"' && c != '=' && c != '\\');
+    if plain {
+        return String::from(value);
+    }
+    return quote(value);
+}
+
+/// Keeps a message on one line in the text format.
+fn single_line(message: &str) -> String {
+    return message.chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn parses_levels_and_formats() {
+        assert_eq!(Level::parse("WARNING"), Some(Level::Warn));
+        assert_eq!(Level::parse(" debug "), Some(Level::Debug));
+        assert_eq!(Level::parse("trace"), None);
+        assert!(Level::Error < Level::Debug);
+        assert_eq!(Format::parse("Json"), Some(Format::Json));
+        assert_eq!(Format::parse("xml"), None);
+    }
+
+    #[test]
+    fn renders_text() {
+        let fields: [Field; 3] = [("route", &"/x y"), ("status", &404),
+            ("empty", &"")];
+        assert_eq!(render(Format::Text, Level::Info, "not\nfound", &fields,
+            UNIX_EPOCH), "testcgi: info: not found route=\"/x y\" \
+            status=404 empty=\"\"\n");
+    }
+
+    #[test]
+    fn renders_json() {
+        let fields: [Field; 1] = [("error", &"a \"b\"")];
+        let line = render(Format::Json, Level::Error, "failed", &fields,
+            UNIX_EPOCH);
+        assert!(line.starts_with("{\"time\":\"1970-01-01T00:00:00.000Z\",\
+            \"level\":\"error\",\"pid\":"));
+        assert!(line.ends_with(",\"msg\":\"failed\",\
+            \"error\":\"a \\\"b\\\"\"}\n"));
+    }
+}
@@ -13,8 +13,9 @@
 // You should have received a copy of the GNU General Public License along with
 // this program. If not, see <https://www.gnu.org/licenses/>.
 
-mod decode_query_string;
-mod decode_urlencoded;
+#![allow(clippy::len_zero)]
+#![allow(clippy::needless_return)]
+#![allow(clippy::upper_case_acronyms)]
 
 use std::collections::HashMap;
 use std::env;
@@ -23,9 +24,61 @@ use std::io::BufRead;
 use std::io::Read;
 use std::io::Stdout;
 use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::time::SystemTime;
 
-use decode_query_string::decode_query_string;
-use decode_urlencoded::decode_urlencoded;
+use testcgi::access;
+use testcgi::access_log;
+use testcgi::basic_auth;
+use testcgi::body;
+use testcgi::body::Binary;
+use testcgi::body::Inspection;
+use testcgi::body::View;
+use testcgi::capture;
+use testcgi::capture::Capture;
+use testcgi::catalog;
+use testcgi::catalog::Catalogs;
+use testcgi::catalog::Translator;
+use testcgi::config;
+use testcgi::conformance;
+use testcgi::cookie;
+use testcgi::cookie::parse_cookie_header;
+use testcgi::csrf;
+use testcgi::csrf::CsrfProtection;
+use testcgi::decode_form::Form;
+use testcgi::decode_form::decode_form;
+use testcgi::decode_form::decode_text_plain;
+use testcgi::decode_form::is_text_plain;
+use testcgi::decode_form::looks_like_text_plain;
+use testcgi::decode_query_string::decode_query_keys;
+use testcgi::decode_query_string::decode_query_parameters;
+use testcgi::decode_query_string::is_form_urlencoded;
+use testcgi::digest_auth;
+use testcgi::env_diff;
+use testcgi::forwarded;
+use testcgi::forwarded::ProxyHeader;
+use testcgi::html;
+use testcgi::json;
+use testcgi::json::Value;
+use testcgi::jwt;
+use testcgi::log;
+use testcgi::redact;
+use testcgi::redact::Redactor;
+use testcgi::request;
+use testcgi::request::Request;
+use testcgi::request_id;
+use testcgi::response::Response;
+use testcgi::route::Route;
+use testcgi::route::route;
+use testcgi::server;
+use testcgi::server::NormalizedPath;
+use testcgi::session;
+use testcgi::session::SessionStore;
+use testcgi::slow;
+use testcgi::slow::DripParameters;
+use testcgi::url::Url;
 
 #[derive(PartialEq)]
 enum RequestMethod {
@@ -41,8 +94,24 @@ enum RequestMethod {
 }
 
 fn main() -> io::Result<()> {
+    let started = SystemTime::now();
     let mut stdout: Stdout = io::stdout();
-    let mut output_buffer = Vec::<u8>::with_capacity(16 * 1024);
+
+    // A web server always sets GATEWAY_INTERFACE, so without it we were run
+    // from a shell and the arguments are a command.
+    if env::var_os("GATEWAY_INTERFACE").is_none() {
+        let args: Vec<String> = env::args().collect();
+        // Commands honour the configuration too, redact.patterns above all.
+        if args.len() > 1
+            && let Err(error) = config::init() {
+            eprintln!("testcgi: {error}");
+            process::exit(2);
+        }
+        if let Some(code) = command(&args) {
+            process::exit(code);
+        }
+    }
+
     let method: RequestMethod;
     match env::var("REQUEST_METHOD") {
         Ok(val) => {
@@ -52,6 +121,8 @@ fn main() -> io::Result<()> {
                 if val.eq_ignore_ascii_case("POST") {
                     method = RequestMethod::POST;
                 } else {
+                    log::warn("unsupported request method",
+                        &[("method", &val)]);
                     print!("Status: 501 Not Implemented\r\n");
                     stdout.flush()?;
                     return Err(io::Error::new(io::ErrorKind::Unsupported,
@@ -60,97 +131,936 @@ fn main() -> io::Result<()> {
             }
         }
         Err(e) => {
+            log::error("missing meta-variable",
+                &[("variable", &"REQUEST_METHOD"), ("error", &e)]);
             return Err(io::Error::new(io::ErrorKind::InvalidInput, e));
         }
     };
 
-    process_request(method, &mut output_buffer)?;
-    stdout.write_all(&output_buffer)?;
+    // A broken configuration stops everything rather than leaving some
+    // protection half switched on.
+    if let Err(error) = config::init() {
+        log::error("invalid configuration", &[("error", &error)]);
+        let response = Response::text(500,
+            &format!("testcgi is misconfigured. {error}"));
+        return send(&mut stdout, response, started);
+    }
+
+    // Before anything else, so a denied client never sees the environment.
+    if let Some(refusal) = access::enforce() {
+        return send(&mut stdout, refusal, started);
+    }
+
+    let method_name: String = env::var("REQUEST_METHOD").unwrap_or_default();
+    // Servers disagree about PATH_INFO, so route on the normalized one.
+    let path_info: String = NormalizedPath::from_env().path_info;
+    let script_name: String = env::var("SCRIPT_NAME").unwrap_or_default();
+    let query_string: String = env::var("QUERY_STRING").unwrap_or_default();
+    let cookie_header: String = env::var("HTTP_COOKIE").unwrap_or_default();
+    let query: HashMap<String, String> =
+        decode_query_parameters(query_string.as_bytes());
+    let cookie_path = if script_name.is_empty() { "/" } else { &script_name };
+
+    let max_body = config::max_body();
+    if content_length() > max_body {
+        log::warn("request body too large",
+            &[("content_length", &content_length()), ("limit", &max_body)]);
+        let response = Response::text(413, &format!("The request body is \
+            larger than the {max_body} byte limit."));
+        return send(&mut stdout, response, started);
+    }
+
+    // The body is read now so the capture has it even if the route never
+    // looks at it. /slow-body has to read it itself to do its job.
+    let mut body: Option<Vec<u8>> = None;
+    if capture::enabled() && route(&path_info) != Route::SlowBody {
+        match read_body(content_length()) {
+            Ok(content) => {
+                capture::begin(&content);
+                body = Some(content);
+            }
+            Err(e) => {
+                return fail(&mut stdout, started,
+                    "cannot read the request body", &e);
+            }
+        }
+    }
+
+    let csrf: Option<CsrfProtection> = match csrf::from_env(cookie_path) {
+        Ok(protection) => protection,
+        Err(message) => {
+            log::error("invalid CSRF settings", &[("error", &message)]);
+            return send(&mut stdout, Response::text(500, &message), started);
+        }
+    };
+    // The token can be in a form field, so a protected request has its body
+    // read here and handed on to whatever shows it.
+    if let Some(protection) = &csrf
+        && csrf::is_state_changing(&method_name) {
+        let content = match body.take() {
+            Some(content) => content,
+            None => match read_body(content_length()) {
+                Ok(content) => content,
+                Err(e) => {
+                    return fail(&mut stdout, started,
+                        "cannot read the request body", &e);
+                }
+            },
+        };
+        let form: HashMap<String, String> = if is_form_urlencoded(
+            &env::var("CONTENT_TYPE").unwrap_or_default()) {
+            decode_query_parameters(&content)
+        } else {
+            HashMap::new()
+        };
+        let submitted = csrf::submitted_token(&form);
+        if let Err(reason) = protection.verify(&method_name,
+            submitted.as_deref(), &cookie_header) {
+            log::warn("CSRF check failed", &[("reason", &reason)]);
+            let response = Response::text(403,
+                &format!("CSRF check failed. {reason}"));
+            return send(&mut stdout, response, started);
+        }
+        body = Some(content);
+    }
+
+    let selected = route(&path_info);
+    if !config::route_enabled(selected.name()) {
+        log::info("route is disabled", &[("route", &selected.name())]);
+        return send(&mut stdout, Response::text(404, "Not Found"), started);
+    }
+    let response: Response;
+    match selected {
+        Route::Diagnostic => {
+            // A bad pattern could leave a secret showing, so refuse to dump
+            // anything until it is fixed.
+            let redactor = match redact::from_env() {
+                Ok(redactor) => redactor,
+                Err(message) => {
+                    log::error("invalid redaction settings",
+                        &[("error", &message)]);
+                    let response = Response::text(500, &message);
+                    return send(&mut stdout, response, started);
+                }
+            };
+            let catalogs = match page_catalogs() {
+                Ok(catalogs) => catalogs,
+                Err(response) => return send(&mut stdout, response, started),
+            };
+            let translator = negotiate(&catalogs);
+            let mut page = translated_page(&translator);
+            let mut csrf_token: Option<String> = None;
+            if let Some(protection) = &csrf {
+                let (token, cookie) = match protection.token(&cookie_header) {
+                    Ok(issued) => issued,
+                    Err(e) => {
+                        return fail(&mut stdout, started,
+                            "cannot issue a CSRF token", &e);
+                    }
+                };
+                if let Some(cookie) = cookie {
+                    page.header("Set-Cookie", &cookie.to_string());
+                }
+                csrf_token = Some(token);
+            }
+            // process_request logs its own errors.
+            if let Err(e) = process_request(method, &mut page, body,
+                csrf_token, &redactor, &translator) {
+                let response = Response::text(500, &e.to_string());
+                return send(&mut stdout, response, started);
+            }
+            response = page;
+        }
+        Route::Delay(duration) => {
+            response = slow::delay(duration);
+        }
+        Route::Drip => {
+            match DripParameters::from_query(&query) {
+                Ok(parameters) => {
+                    // The drip writes its own headers as it goes.
+                    if let Err(e) = slow::drip(&parameters, &mut stdout) {
+                        // The headers may be out already, so all that is
+                        // left is to say so and stop.
+                        log::error("drip failed", &[("error", &e)]);
+                        return Err(e);
+                    }
+                    stdout.flush()?;
+                    log_access(200, parameters.numbytes, started);
+                    save_capture(None);
+                    return Ok(());
+                }
+                Err(message) => {
+                    log::info("bad drip parameters", &[("error", &message)]);
+                    response = Response::text(400, message);
+                }
+            }
+        }
+        Route::SlowBody => {
+            let rate: u64 = query.get("rate")
+                .and_then(|val| val.trim().parse().ok())
+                .unwrap_or(slow::DEFAULT_READ_RATE);
+            response = match slow::slow_body(rate) {
+                Ok(response) => response,
+                Err(e) => {
+                    return fail(&mut stdout, started,
+                        "cannot read the request body", &e);
+                }
+            };
+        }
+        Route::CookiesSet => {
+            let mut pairs: Vec<(String, String)> = query.into_iter().collect();
+            pairs.sort();
+            response = cookie::set_route(&pairs, &script_name);
+        }
+        Route::CookiesDelete => {
+            let names = decode_query_keys(query_string.as_bytes());
+            response = cookie::delete_route(&names, &script_name);
+        }
+        Route::Session => {
+            let mut set: Vec<(String, String)> = query.into_iter().collect();
+            set.sort();
+            response = match session_route(&script_name, &set, &[], false) {
+                Ok(response) => response,
+                Err(e) => {
+                    return fail(&mut stdout, started, "session store failed",
+                        &e);
+                }
+            };
+        }
+        Route::SessionDelete => {
+            let delete = decode_query_keys(query_string.as_bytes());
+            response = match session_route(&script_name, &[], &delete, false) {
+                Ok(response) => response,
+                Err(e) => {
+                    return fail(&mut stdout, started, "session store failed",
+                        &e);
+                }
+            };
+        }
+        Route::SessionDestroy => {
+            response = match session_route(&script_name, &[], &[], true) {
+                Ok(response) => response,
+                Err(e) => {
+                    return fail(&mut stdout, started, "session store failed",
+                        &e);
+                }
+            };
+        }
+        Route::BasicAuth(user, password) => {
+            response = basic_auth::fixed_route(&user, &password);
+        }
+        Route::BasicAuthFile => {
+            response = basic_auth::htpasswd_route();
+        }
+        Route::DigestAuth(qop, user, password, algorithm) => {
+            response = digest_auth::route(&qop, &user, &password,
+                algorithm.as_deref());
+        }
+        Route::Bearer => {
+            response = jwt::route();
+        }
+        Route::Conformance => {
+            let catalogs = match page_catalogs() {
+                Ok(catalogs) => catalogs,
+                Err(response) => return send(&mut stdout, response, started),
+            };
+            let translator = negotiate(&catalogs);
+            let variables: Vec<(String, String)> = request::variables();
+            let checks = conformance::check(&variables);
+            let mut page = translated_page(&translator);
+            page.header("Content-Type", "text/html; charset=utf-8");
+            page.body.extend_from_slice(
+                conformance::report_html(&checks, &translator).as_bytes());
+            response = page;
+        }
+        Route::Server => {
+            let catalogs = match page_catalogs() {
+                Ok(catalogs) => catalogs,
+                Err(response) => return send(&mut stdout, response, started),
+            };
+            let translator = negotiate(&catalogs);
+            let variables: Vec<(String, String)> = request::variables();
+            let mut page = translated_page(&translator);
+            page.header("Content-Type", "text/html; charset=utf-8");
+            page.body.extend_from_slice(
+                server::report_html(&variables, &translator).as_bytes());
+            response = page;
+        }
+        Route::Baseline => {
+            response = match baseline_route() {
+                Ok(response) => response,
+                Err(message) => {
+                    log::error("cannot compare with the baseline",
+                        &[("error", &message)]);
+                    Response::text(500, &message)
+                }
+            };
+        }
+        Route::Invalid(name, message) => {
+            log::info("invalid route arguments",
+                &[("route", &name), ("error", &message)]);
+            response = Response::text(400, message);
+        }
+    }
+
+    return send(&mut stdout, response, started);
+}
+
+/// The message catalogs for a translated page, or the 500 to send when the
+/// TESTCGI_CATALOGS directory has a bad catalog in it.
+fn page_catalogs() -> Result<Catalogs, Response> {
+    return match catalog::from_env() {
+        Ok(catalogs) => Ok(catalogs),
+        Err(error) => {
+            log::error("invalid message catalog", &[("error", &error)]);
+            Err(Response::text(500, &error.to_string()))
+        }
+    };
+}
+
+/// The catalog the client's Accept-Language asks for.
+fn negotiate(catalogs: &Catalogs) -> Translator<'_> {
+    return catalogs.negotiate(
+        &env::var("HTTP_ACCEPT_LANGUAGE").unwrap_or_default());
+}
+
+/// An empty 200 response that says which language it is in, and that it
+/// depends on Accept-Language.
+fn translated_page(tr: &Translator) -> Response {
+    let mut page = Response::new(200);
+    page.header("Content-Language", tr.language());
+    page.header("Vary", "Accept-Language");
+    return page;
+}
+
+/// Writes the response with its request ID and adds it to the access log.
+fn send(stdout: &mut Stdout, mut response: Response, started: SystemTime)
+    -> io::Result<()>
+{
+    response.header("X-Request-Id", &request_id::current().id);
+    response.write_to(stdout)?;
     stdout.flush()?;
+    log::debug("response sent", &[("status", &response.status),
+        ("bytes", &response.body.len())]);
+    log_access(response.status, response.body.len() as u64, started);
+    save_capture(Some(&response));
     return Ok(());
 }
 
+/// Logs an error that stopped the request and answers with a 500.
+fn fail(stdout: &mut Stdout, started: SystemTime, message: &str,
+    error: &io::Error) -> io::Result<()>
+{
+    log::error(message, &[("error", error)]);
+    let response = Response::text(500, &format!("{message}: {error}"));
+    return send(stdout, response, started);
+}
+
+/// The response has already gone out, so a log that cannot be written is
+/// only worth a complaint on stderr.
+fn log_access(status: u16, bytes: u64, started: SystemTime) {
+    if let Err(e) = access_log::record(status, bytes, started) {
+        log::error("cannot write the access log", &[("error", &e)]);
+    }
+}
+
+/// Like the access log, a capture that cannot be written is only logged.
+fn save_capture(response: Option<&Response>) {
+    match capture::finish(response) {
+        Ok(Some(path)) => {
+            log::debug("request captured",
+                &[("file", &path.display())]);
+        }
+        Ok(None) => {}
+        Err(e) => log::error("cannot write the capture", &[("error", &e)]),
+    }
+}
+
+/// Runs a command given on the command line, returning the exit status, or
+/// None if there is no command and this is a request after all.
+fn command(args: &[String]) -> Option<i32> {
+    let usage = "usage: testcgi replay <capture file> [program]\n       \
+        testcgi diff <old.json> <new.json>";
+    return match args.get(1).map(|a| a.as_str()) {
+        Some("replay") => match &args[2..] {
+            [file] => Some(replay_command(Path::new(file), None)),
+            [file, program] => {
+                Some(replay_command(Path::new(file), Some(Path::new(program))))
+            }
+            _ => {
+                eprintln!("{usage}");
+                Some(2)
+            }
+        },
+        Some("diff") => match &args[2..] {
+            [old, new] => Some(diff_command(Path::new(old), Path::new(new))),
+            _ => {
+                eprintln!("{usage}");
+                Some(2)
+            }
+        },
+        Some("help" | "--help" | "-h") => {
+            println!("{usage}");
+            Some(0)
+        }
+        _ => None,
+    };
+}
+
+/// `testcgi replay <file> [program]` runs the captured request through
+/// `program`, testcgi itself by default, and prints how the response differs
+/// from the captured one. Exits 0 if they match and 1 if they do not, like
+/// diff.
+fn replay_command(file: &Path, program: Option<&Path>) -> i32 {
+    let capture = match std::fs::read_to_string(file).map_err(|e| e.to_string())
+        .and_then(|text| Capture::from_json(&text)) {
+        Ok(capture) => capture,
+        Err(message) => {
+            eprintln!("testcgi: {}: {message}", file.display());
+            return 2;
+        }
+    };
+    let program: PathBuf = match program {
+        Some(program) => PathBuf::from(program),
+        None => match env::current_exe() {
+            Ok(program) => program,
+            Err(e) => {
+                eprintln!("testcgi: cannot find this program: {e}");
+                return 2;
+            }
+        },
+    };
+    if !capture.body_captured {
+        eprintln!("testcgi: the body was not captured; replaying without one");
+    }
+    let replayed = match capture::replay(&capture, &program) {
+        Ok(replayed) => replayed,
+        Err(e) => {
+            eprintln!("testcgi: {}: {e}", program.display());
+            return 2;
+        }
+    };
+    let Some(original) = &capture.response else {
+        eprintln!("testcgi: no response was captured; showing the new one");
+        print!("{}", replayed.lines().join("\n"));
+        println!();
+        return 1;
+    };
+    let (original, replayed) = (original.comparable(), replayed.comparable());
+    if original == replayed {
+        println!("The responses are identical.");
+        return 0;
+    }
+    let (old, new) = (original.lines(), replayed.lines());
+    println!("--- captured {}", capture.time);
+    println!("+++ replayed with {}", program.display());
+    print!("{}", capture::format_diff(&capture::diff_lines(&old, &new)));
+    return 1;
+}
+
+/// `testcgi diff <old> <new>` compares two environments, each a capture file
+/// or a JSON object of variables. Exits like diff does.
+fn diff_command(old: &Path, new: &Path) -> i32 {
+    let load = |path: &Path| {
+        return std::fs::read_to_string(path).map_err(|e| e.to_string())
+            .and_then(|text| env_diff::load(&text))
+            .map_err(|message| format!("{}: {message}", path.display()));
+    };
+    let (old, new) = match (load(old), load(new)) {
+        (Ok(old), Ok(new)) => (old, new),
+        (Err(message), _) | (_, Err(message)) => {
+            eprintln!("testcgi: {message}");
+            return 2;
+        }
+    };
+    let redactor = match redact::from_env() {
+        Ok(redactor) => redactor,
+        Err(message) => {
+            eprintln!("testcgi: {message}");
+            return 2;
+        }
+    };
+    let differences = env_diff::compare(&old, &new);
+    print!("{}", env_diff::format_text(&differences, &redactor));
+    return if differences.is_empty() { 0 } else { 1 };
+}
+
+/// `/baseline` compares this request's environment with the file named by
+/// TESTCGI_BASELINE.
+fn baseline_route() -> Result<Response, String> {
+    let Some(baseline) = config::get(env_diff::BASELINE_VARIABLE) else {
+        return Ok(Response::text(500,
+            "The baseline route needs TESTCGI_BASELINE to be set."));
+    };
+    let text = std::fs::read_to_string(&baseline)
+        .map_err(|e| format!("{baseline}: {e}"))?;
+    let old = env_diff::load(&text).map_err(|e| format!("{baseline}: {e}"))?;
+    let new: Vec<(String, String)> = request::variables();
+    let redactor = redact::from_env()?;
+    let differences = env_diff::compare(&old, &new);
+    let mut page = Response::new(200);
+    page.header("Content-Type", "text/html; charset=utf-8");
+    page.body.extend_from_slice(
+        env_diff::report_html(&differences, &baseline, &redactor).as_bytes());
+    return Ok(page);
+}
+
+/// Sessions are kept in TESTCGI_SESSION_DIR, or a directory under the system
+/// temporary directory, and signed with TESTCGI_SECRET.
+fn session_route(script_name: &str, set: &[(String, String)],
+    delete: &[String], destroy: bool) -> io::Result<Response>
+{
+    let secret = match config::get("TESTCGI_SECRET") {
+        Some(secret) => secret,
+        None => {
+            return Ok(Response::text(500,
+                "Sessions need TESTCGI_SECRET to be set."));
+        }
+    };
+    let directory: PathBuf = match config::get("TESTCGI_SESSION_DIR") {
+        Some(directory) => PathBuf::from(directory),
+        None => env::temp_dir().join("testcgi-sessions"),
+    };
+    let path = if script_name.is_empty() { "/" } else { script_name };
+    let store = SessionStore::new(directory, secret.as_bytes())
+        .cookie_path(path);
+    let cookie_header = env::var("HTTP_COOKIE").unwrap_or_default();
+    return session::demo_route(&store, &cookie_header, set, delete, destroy);
+}
+
+/// CONTENT_LENGTH as a number. Missing or garbled means no body.
+fn content_length() -> u64 {
+    return env::var("CONTENT_LENGTH").ok()
+        .and_then(|val| val.trim().parse().ok())
+        .unwrap_or(0);
+}
+
+fn read_body(content_length: u64) -> io::Result<Vec<u8>> {
+    let mut output: Vec<u8> = Vec::with_capacity(content_length as usize);
+    let mut content = io::stdin().lock().take(content_length);
+    let mut x: bool = true;
+    while x {
+        let buf = content.fill_buf()?;
+        let len = buf.len();
+        if buf.len() > 0 {
+            output.extend_from_slice(buf);
+            content.consume(len);
+        } else {
+            x = false;
+        }
+    }
+    return Ok(output);
+}
+
+/// Lists the fields of a form or query string with the character set they
+/// were decoded in and anything that went wrong decoding them.
+fn write_form(out: &mut Vec<u8>, form: Form, tr: &Translator) {
+    _ = write!(out, "<p>{}</p>", html::escape(&tr.format("form.charset",
+        &[form.charset.name(), &form.source.describe(tr)])));
+    _ = write!(out, "<dl>");
+    for (name, value) in &form.fields {
+        _ = write!(out, "<dt>{}</dt><dd>{}</dd>", html::escape(name),
+            html::escape(value));
+    }
+    _ = write!(out, "</dl>");
+    if !form.problems.is_empty() {
+        _ = write!(out, "<ul>");
+        for problem in &form.problems {
+            _ = write!(out, "<li>{}</li>",
+                html::escape(&problem.describe(tr)));
+        }
+        _ = write!(out, "</ul>");
+    }
+}
+
+/// Describes the body and shows it as text, or as a hex dump if it looks
+/// binary. MAX_DUMP_PAGES pages are dumped, starting at the one named by
+/// the body_page parameter of `query_string`.
+fn write_body(out: &mut Vec<u8>, content: &[u8], content_length: Option<u64>,
+    query_string: &str, tr: &Translator)
+{
+    let inspection = Inspection::new(content, content_length);
+    let endings = inspection.line_endings;
+    let mut rows = vec![
+        (tr.get("body.size"), inspection.size.to_string()),
+        (tr.get("body.content_length"),
+            content_length.map(|n| n.to_string()).unwrap_or_default()),
+        (tr.get("body.sha256"), inspection.sha256.clone()),
+        (tr.get("body.crc32"), format!("{:08x}", inspection.crc32)),
+        (tr.get("body.line_endings"), tr.format("body.line_counts",
+            &[&endings.crlf.to_string(), &endings.lf.to_string(),
+                &endings.cr.to_string()])),
+    ];
+    if inspection.invalid_utf8 > 0 {
+        rows.push((tr.get("body.invalid_utf8"),
+            inspection.invalid_utf8.to_string()));
+    }
+    _ = write!(out, "<dl>");
+    for (name, value) in rows {
+        _ = write!(out, "<dt>{}</dt><dd>{}</dd>", html::escape(name),
+            html::escape(&value));
+    }
+    _ = write!(out, "</dl>");
+    let mut notes: Vec<String> = Vec::new();
+    if inspection.length_mismatch() {
+        notes.push(tr.format("body.short", &[&inspection.size.to_string(),
+            &content_length.unwrap_or(0).to_string()]));
+    }
+    if endings.is_mixed() {
+        notes.push(String::from(tr.get("body.mixed")));
+    }
+    match inspection.binary {
+        Some(Binary::NulByte) => notes.push(String::from(tr.get("body.nul"))),
+        Some(Binary::ControlCharacters) => {
+            notes.push(String::from(tr.get("body.controls")));
+        }
+        None => {}
+    }
+    for note in notes {
+        _ = write!(out, "<p><strong>{}</strong></p>", html::escape(&note));
+    }
+
+    let hex = match body::view() {
+        View::Auto => inspection.binary.is_some(),
+        View::Text => false,
+        View::Hex => true,
+    };
+    if !hex {
+        _ = write!(out, "<pre>{}</pre>", body::text_html(content));
+        return;
+    }
+    let pages = body::page_count(content.len());
+    let start = decode_query_parameters(query_string.as_bytes())
+        .get(body::PAGE_PARAMETER)
+        .and_then(|page| page.trim().parse::<usize>().ok())
+        .unwrap_or(0)
+        .min(pages.saturating_sub(1));
+    let end = pages.min(start.saturating_add(body::MAX_DUMP_PAGES));
+    if start > 0 {
+        let previous = start.saturating_sub(body::MAX_DUMP_PAGES);
+        _ = write!(out, "<p>{} <a href=\"{}\">{}</a></p>",
+            html::escape(&tr.format("body.skipped",
+                &[&(start * body::DUMP_PAGE_SIZE).to_string()])),
+            html::escape(&page_link(query_string, previous)),
+            html::escape(&tr.format("body.previous",
+                &[&previous.to_string()])));
+    }
+    for page in start..end {
+        let first = page * body::DUMP_PAGE_SIZE;
+        let last = (first + body::DUMP_PAGE_SIZE).min(content.len()) - 1;
+        _ = write!(out, "<details{}><summary>{}</summary><pre>{}</pre>\
+            </details>", if page == start { " open" } else { "" },
+            html::escape(&tr.format("body.page",
+                &[&first.to_string(), &last.to_string()])),
+            html::escape(&body::hex_dump(content, page)));
+    }
+    if end < pages {
+        let shown = end * body::DUMP_PAGE_SIZE;
+        _ = write!(out, "<p>{} <a href=\"{}\">{}</a></p>",
+            html::escape(&tr.format("body.truncated",
+                &[&(content.len() - shown).to_string()])),
+            html::escape(&page_link(query_string, end)),
+            html::escape(&tr.format("body.next", &[&end.to_string()])));
+    }
+}
+
+/// A relative link to this request with body_page set to `page` and every
+/// other query parameter left as it was.
+fn page_link(query_string: &str, page: usize) -> String {
+    let mut pairs: Vec<String> = query_string.split('&')
+        .filter(|pair| !pair.is_empty())
+        .filter(|pair| pair.split('=').next() != Some(body::PAGE_PARAMETER))
+        .map(String::from)
+        .collect();
+    pairs.push(format!("{}={page}", body::PAGE_PARAMETER));
+    return format!("?{}", pairs.join("&"));
+}
+
+/// Shows a parsed JSON body as a tree, or where and why it failed to parse.
+fn write_json(out: &mut Vec<u8>, content: &[u8],
+    parsed: Result<Value, json::Error>, tr: &Translator)
+{
+    match parsed {
+        Ok(value) => _ = write!(out, "{}", value.to_html(tr)),
+        Err(error) => {
+            _ = write!(out, "<p>{}</p>", html::escape(&tr.format("json.error",
+                &[&error.describe(tr)])));
+            _ = write!(out, "<pre>{}</pre>",
+                html::escape(&error.excerpt(content)));
+        }
+    }
+}
+
 // out is a Vec<u8> and the Write trait implementations always return Ok().
-fn process_request(method: RequestMethod, out: &mut Vec<u8>) -> io::Result<()>
+//
+// body is the request body if main already had to read it.
+// csrf_token is set when CSRF protection is on, and gets a test form.
+// redactor decides which environment variables are too sensitive to show.
+// tr holds the page's text in the language the client asked for.
+fn process_request(method: RequestMethod, response: &mut Response,
+    body: Option<Vec<u8>>, csrf_token: Option<String>, redactor: &Redactor,
+    tr: &Translator) -> io::Result<()>
 {
-    _ = write!(out, "Status: 200 OK\r\n");
-    _ = write!(out, "Content-Type: text/html;\r\n");
-    _ = write!(out, "\r\n");
-    _ = write!(out, "<!DOCTYPE html><html lang=\"en\"><body>");
-    _ = write!(out, "<h1>Command Line Arguments</h1>");
+    response.header("Content-Type", "text/html; charset=utf-8");
+    let out: &mut Vec<u8> = &mut response.body;
+    let text = |key: &'static str| html::escape(tr.get(key));
+    _ = write!(out, "<!DOCTYPE html><html lang=\"{}\"><body>",
+        html::escape(tr.language()));
+    _ = write!(out, "<h1>{}</h1>", text("heading.arguments"));
     for argument in env::args() {
         _ = write!(out, "<li>{argument}</li>");
     }
     _ = write!(out, "<hr />");
-    _ = write!(out, "<h1>Environment Variables</h1>");
+    _ = write!(out, "<h1>{}</h1>", text("heading.environment"));
     _ = write!(out, "<dl>");
-    for (key, value) in env::vars() {
-        _ = write!(out, "<dt>{key}</dt><dd>{value}</dd>");
+    // Sorted, because the order depends on the server, and a replayed
+    // request gets its environment in name order.
+    let mut variables: Vec<(String, String)> = request::variables();
+    variables.sort();
+    for (key, value) in variables {
+        _ = write!(out, "<dt>{}</dt><dd>{}</dd>", html::escape(&key),
+            html::escape(&redactor.display(&key, &value)));
     }
     _ = write!(out, "</dl>");
     _ = write!(out, "<hr />");
+    _ = write!(out, "<h1>{}</h1>", text("heading.configuration"));
+    match config::loaded().and_then(|c| c.path.as_ref()) {
+        Some(path) => {
+            _ = write!(out, "<p>{}</p>", html::escape(&tr.format(
+                "config.read_from", &[&path.display().to_string()])));
+        }
+        None => _ = write!(out, "<p>{}</p>", text("config.no_file")),
+    }
+    _ = write!(out, "<dl>");
+    for setting in config::SETTINGS {
+        let (Some(value), Some(origin)) = (config::get(setting.variable),
+            config::origin(setting.variable)) else {
+            continue;
+        };
+        _ = write!(out, "<dt>{} ({})</dt><dd>{} <small>{}</small></dd>",
+            setting.key, setting.variable,
+            html::escape(&redactor.display(setting.variable, &value)),
+            html::escape(&tr.format("config.from", &[&origin])));
+    }
+    _ = write!(out, "</dl>");
+    _ = write!(out, "<hr />");
+    _ = write!(out, "<h1>{}</h1>", text("heading.request_id"));
+    let request = request_id::current();
+    _ = write!(out, "<dl>");
+    let optional = |v: &Option<String>| v.clone().unwrap_or_default();
+    let mut rows: Vec<(&str, String)> = vec![
+        (tr.get("request_id.id"), request.id.clone()),
+        (tr.get("request_id.source"), request.source.describe(tr)),
+        ("X-Request-Id", optional(&request.x_request_id)),
+        ("traceparent", optional(&request.traceparent_header)),
+        ("tracestate", optional(&request.tracestate)),
+    ];
+    match &request.traceparent {
+        Ok(parent) => {
+            rows.push((tr.get("request_id.trace_version"),
+                format!("{:02x}", parent.version)));
+            rows.push((tr.get("request_id.trace_id"), parent.trace_id.clone()));
+            rows.push((tr.get("request_id.parent_id"),
+                parent.parent_id.clone()));
+            let sampled = if parent.sampled() {
+                "common.yes"
+            } else {
+                "common.no"
+            };
+            rows.push((tr.get("request_id.sampled"),
+                String::from(tr.get(sampled))));
+        }
+        Err(reason) if request.traceparent_header.is_some() => {
+            rows.push((tr.get("request_id.problem"), reason.describe(tr)));
+        }
+        Err(_) => {}
+    }
+    for (name, value) in rows {
+        _ = write!(out, "<dt>{}</dt><dd>{}</dd>", html::escape(name),
+            html::escape(&value));
+    }
+    _ = write!(out, "</dl>");
+    _ = write!(out, "<hr />");
+    _ = write!(out, "<h1>{}</h1>", text("heading.url"));
+    let url = Url::from_env();
+    _ = write!(out, "<dl>");
+    for (name, value) in [
+        (tr.get("url.url"), url.to_string()),
+        (tr.get("url.scheme"), url.scheme.clone()),
+        (tr.get("url.host"), url.host.clone()),
+        (tr.get("url.port"),
+            url.port.map(|p| p.to_string()).unwrap_or_default()),
+        (tr.get("url.script_url"), url.script_url()),
+        (tr.get("url.path_info"), url.path_info.clone()),
+        (tr.get("url.query"), url.query.clone().unwrap_or_default()),
+        (tr.get("url.target"), url.request_target()),
+    ] {
+        _ = write!(out, "<dt>{}</dt><dd>{}</dd>", html::escape(name),
+            html::escape(&value));
+    }
+    _ = write!(out, "</dl>");
+    _ = write!(out, "<hr />");
+    _ = write!(out, "<h1>{}</h1>", text("heading.headers"));
+    _ = write!(out, "<p>{}</p>", text("headers.intro"));
+    _ = write!(out, "<dl>");
+    for header in Request::from_env().headers().iter() {
+        _ = write!(out, "<dt>{} <small>{}</small></dt><dd>{}",
+            html::escape(&header.name), html::escape(&header.variable),
+            html::escape(&redactor.display(&header.variable, &header.value)));
+        for note in header.notes() {
+            _ = write!(out, "<br /><small>{}</small>",
+                html::escape(&note.describe(tr)));
+        }
+        _ = write!(out, "</dd>");
+    }
+    _ = write!(out, "</dl>");
+    _ = write!(out, "<hr />");
+    _ = write!(out, "<h1>{}</h1>", text("heading.client"));
+    match forwarded::from_env() {
+        Err(message) => {
+            _ = write!(out, "<p>{}</p>", html::escape(&message));
+        }
+        Ok(client) => {
+            let trusted: Vec<String> = client.trusted.iter()
+                .map(|c| c.to_string())
+                .collect();
+            let optional = |v: &Option<String>| v.clone().unwrap_or_default();
+            _ = write!(out, "<h2>{}</h2><dl>", text("client.raw"));
+            for (name, value) in [
+                ("REMOTE_ADDR", client.remote_addr.clone()),
+                ("Forwarded", optional(&client.forwarded)),
+                ("X-Forwarded-For", optional(&client.x_forwarded_for)),
+                ("X-Forwarded-Proto", optional(&client.x_forwarded_proto)),
+                ("X-Forwarded-Host", optional(&client.x_forwarded_host)),
+                (tr.get("client.trusted"), trusted.join(", ")),
+            ] {
+                _ = write!(out, "<dt>{}</dt><dd>{}</dd>",
+                    html::escape(name), html::escape(&value));
+            }
+            _ = write!(out, "</dl>");
+            if let Some(error) = &client.error {
+                _ = write!(out, "<p>{}</p>",
+                    html::escape(&tr.format("client.ignored", &[error])));
+            }
+            let unused = match client.header {
+                ProxyHeader::Forwarded => &client.x_forwarded_for,
+                ProxyHeader::XForwardedFor => &client.forwarded,
+            };
+            if unused.is_some() {
+                let other = match client.header {
+                    ProxyHeader::Forwarded => ProxyHeader::XForwardedFor,
+                    ProxyHeader::XForwardedFor => ProxyHeader::Forwarded,
+                };
+                _ = write!(out, "<p>{}</p>", html::escape(&tr.format(
+                    "client.unused", &[other.name(), client.header.name()])));
+            }
+            let resolved = &client.resolved;
+            _ = write!(out, "<h2>{}</h2><dl>", text("client.resolved"));
+            for (name, value) in [
+                (tr.get("client.client"), resolved.client.clone()),
+                (tr.get("client.source"), resolved.source.describe(tr)),
+                (tr.get("client.scheme"), optional(&resolved.scheme)),
+                (tr.get("client.host"), optional(&resolved.host)),
+                (tr.get("client.proxies"), resolved.proxies.join(", ")),
+                (tr.get("client.url"),
+                    url.clone().behind_proxy(resolved).to_string()),
+            ] {
+                _ = write!(out, "<dt>{}</dt><dd>{}</dd>",
+                    html::escape(name), html::escape(&value));
+            }
+            _ = write!(out, "</dl>");
+        }
+    }
+    _ = write!(out, "<hr />");
+    _ = write!(out, "<h1>{}</h1>", text("heading.cookies"));
+    _ = write!(out, "<dl>");
+    let cookies = parse_cookie_header(
+        &env::var("HTTP_COOKIE").unwrap_or_default());
+    // Cookies are the Cookie header taken apart, so they are hidden if it is.
+    let hide_cookies = redactor.is_sensitive("HTTP_COOKIE");
+    for (name, value) in cookies {
+        let value = if hide_cookies { redactor.redact(&value) } else { value };
+        _ = write!(out, "<dt>{}</dt><dd>{}</dd>", html::escape(&name),
+            html::escape(&value));
+    }
+    _ = write!(out, "</dl>");
+    _ = write!(out, "<hr />");
+    if let Some(section) = jwt::diagnostic_html(tr) {
+        _ = write!(out, "{section}");
+    }
     match env::var("CONTENT_LENGTH") {
-        Err(_) => {
+        Err(e) => {
+            log::error("missing meta-variable",
+                &[("variable", &"CONTENT_LENGTH"), ("error", &e)]);
             return Err(
                 io::Error::new(io::ErrorKind::InvalidInput,
                     "We don't have a CONTENT_LENGTH meta variable.")
             );
         }
         Ok(val) => {
-            let content_length: u64 = val.parse().unwrap_or(0);
-            if content_length > 0 {
-                _ = write!(out, "<pre>");
-                let mut content = io::stdin().lock().take(content_length);
-                let mut x: bool = true;
-                while x {
-                    let buf = content.fill_buf()?;
-                    let len = buf.len();
-                    if buf.len() > 0 {
-                        _ = out.write(buf);
-                        content.consume(len);
-                    } else {
-                        x = false;
+            let content: Vec<u8> = match body {
+                Some(content) => content,
+                None => match read_body(val.parse().unwrap_or(0)) {
+                    Ok(content) => content,
+                    Err(e) => {
+                        log::error("cannot read the request body",
+                            &[("content_length", &val), ("error", &e)]);
+                        return Err(e);
                     }
-                }
-                _ = write!(out, "</pre>");
+                },
+            };
+            if content.len() > 0 {
+                _ = write!(out, "<h1>{}</h1>", text("heading.body"));
+                let query_string = env::var("QUERY_STRING")
+                    .unwrap_or_default();
+                write_body(out, &content, val.trim().parse().ok(),
+                    &query_string, tr);
+            }
+            let content_type = env::var("CONTENT_TYPE").unwrap_or_default();
+            if method == RequestMethod::POST
+                && is_form_urlencoded(&content_type) {
+                _ = write!(out, "<h1>{}</h1>", text("heading.form"));
+                write_form(out, decode_form(&content, &content_type, None), tr);
+            } else if method == RequestMethod::POST
+                && (content_type.is_empty() || is_text_plain(&content_type))
+                && looks_like_text_plain(&content) {
+                // Usually a form with enctype="text/plain" by mistake.
+                _ = write!(out, "<h1>{}</h1>", text("heading.form"));
+                _ = write!(out, "<p><strong>{}</strong></p>",
+                    text("form.text_plain"));
+                write_form(out,
+                    decode_text_plain(&content, &content_type, None), tr);
+            } else if content.len() > 0
+                && let Some(parsed) = Request::from_env().json(&content) {
+                _ = write!(out, "<h1>{}</h1>", text("heading.json"));
+                write_json(out, &content, parsed, tr);
             }
         }
     };
 
+    if let Some(token) = csrf_token {
+        _ = write!(out, "<h1>{}</h1>", text("heading.csrf"));
+        _ = write!(out, "<form method=\"post\">{}", csrf::hidden_field(&token));
+        _ = write!(out, "<input name=\"message\" /> ");
+        _ = write!(out, "<button type=\"submit\">{}</button></form>",
+            text("csrf.send"));
+        _ = write!(out, "<hr />");
+    }
+
     if method == RequestMethod::GET {
         match env::var("QUERY_STRING") {
-            Err(_) => {
+            Err(e) => {
+                log::error("missing meta-variable",
+                    &[("variable", &"QUERY_STRING"), ("error", &e)]);
                 return Err(
                     io::Error::new(io::ErrorKind::InvalidInput,
                         "We don't have a QUERY_STRING meta variable."));
             }
             Ok(val) => {
-                let data: HashMap<String, String> =
-                    decode_query_string(val.as_bytes());
-                _ = write!(out, "<dl>");
-                for (key, value) in data {
-                    match decode_urlencoded(key) {
-                        Some(key) => {
-                            _ = write!(out, "<dt>{key}</dt>");
-                        }
-                        None => {
-                            return
-                            Err(io::Error::new(io::ErrorKind::InvalidData,
-                                "A percent encoded value was invalid."));
-                        }
-                    }
-                    match decode_urlencoded(value) {
-                        Some(value) => {
-                            _ = write!(out, "<dd>{value}</dd>");
-                        }
-                        None => {
-                            return
-                            Err(io::Error::new(io::ErrorKind::InvalidData,
-                                "A percent encoded value was invalid."));
-                        }
-                    }
+                if !val.is_empty() {
+                    _ = write!(out, "<h1>{}</h1>", text("heading.query"));
+                    write_form(out, decode_form(val.as_bytes(), "", None), tr);
                 }
-                _ = write!(out, "</dl>");
             }
         }
     }
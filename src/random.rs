@@ -0,0 +1,35 @@
+// src/random.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+use std::fs::File;
+use std::io;
+use std::io::Read;
+
+/// Fills `buffer` from /dev/urandom.
+///
+/// There is no fallback. A CGI host without /dev/urandom cannot safely make
+/// session ids or nonces, so the error is passed back to the caller.
+pub fn fill(buffer: &mut [u8]) -> io::Result<()> {
+    let mut source = File::open("/dev/urandom")?;
+    source.read_exact(buffer)?;
+    return Ok(());
+}
+
+/// `count` random bytes.
+pub fn bytes(count: usize) -> io::Result<Vec<u8>> {
+    let mut output = vec![0u8; count];
+    fill(&mut output)?;
+    return Ok(output);
+}
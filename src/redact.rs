@@ -0,0 +1,301 @@
+// src/redact.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Keeping secrets out of the environment dump.
+//!
+//! Variables whose names match a redaction pattern are shown as
+//! `[REDACTED len=N]`. With hashes turned on a short keyed hash is added, so
+//! you can still tell whether two requests sent the same value without
+//! seeing it.
+
+use crate::config;
+use crate::hex;
+use crate::hmac::hmac;
+use crate::regex::Regex;
+use crate::sha256::Sha256;
+use crate::sha256::sha256;
+
+pub const PATTERNS_VARIABLE: &str = "TESTCGI_REDACT";
+pub const DEFAULTS_VARIABLE: &str = "TESTCGI_REDACT_DEFAULTS";
+pub const HASH_VARIABLE: &str = "TESTCGI_REDACT_HASH";
+
+/// Names that are redacted unless TESTCGI_REDACT_DEFAULTS is off.
+pub const DEFAULT_PATTERNS: &[&str] = &[
+    "*AUTHORIZATION*",
+    "HTTP_COOKIE",
+    "*PASSWORD*",
+    "*PASSWD*",
+    "*_PASS",
+    "*PASSPHRASE*",
+    "*SECRET*",
+    "*TOKEN*",
+    "*API_KEY*",
+    "*APIKEY*",
+    "*ACCESS_KEY*",
+    "*PRIVATE_KEY*",
+    "*CREDENTIAL*",
+];
+
+/// How many hex digits of the hash to show.
+const HASH_LENGTH: usize = 12;
+
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    /// `*` matches any run of characters and `?` any one. Case-insensitive.
+    Glob(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    /// `/.../` is a regular expression, anything else is a glob.
+    pub fn parse(input: &str) -> Result<Pattern, String> {
+        if input.len() >= 2 && input.starts_with('/') && input.ends_with('/') {
+            return match Regex::new(&input[1..input.len() - 1]) {
+                Ok(regex) => Ok(Pattern::Regex(regex)),
+                Err(error) => Err(format!("{input}: {error}")),
+            };
+        }
+        return Ok(Pattern::Glob(String::from(input)));
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        return match self {
+            Pattern::Glob(glob) => glob_match(glob, name),
+            Pattern::Regex(regex) => regex.is_match(name),
+        };
+    }
+}
+
+/// Matches a whole name against a glob with `*` and `?`, ignoring ASCII case.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where to go back to if the text after the last `*` stops matching.
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if p < pattern.len()
+            && (pattern[p] == '?' || pattern[p].eq_ignore_ascii_case(&name[n]))
+        {
+            p += 1;
+            n += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    while p < pattern.len() && pattern[p] == '*' {
+        p += 1;
+    }
+    return p == pattern.len();
+}
+
+/// Splits a pattern list on commas and whitespace. A `/regex/` runs to its
+/// closing slash, so it can contain either.
+pub fn split_patterns(input: &str) -> Result<Vec<String>, String> {
+    let mut output: Vec<String> = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c == ',' || c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut item = String::new();
+        if c == '/' {
+            item.push(chars.next().unwrap());
+            let mut closed = false;
+            while let Some(c) = chars.next() {
+                item.push(c);
+                if c == '\\' {
+                    if let Some(c) = chars.next() {
+                        item.push(c);
+                    }
+                } else if c == '/' {
+                    closed = true;
+                    break;
+                }
+            }
+            if !closed {
+                return Err(format!("{item} is missing its closing /"));
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| *c != ','
+                && !c.is_whitespace()) {
+                item.push(c);
+            }
+        }
+        output.push(item);
+    }
+    return Ok(output);
+}
+
+#[derive(Clone, Debug)]
+pub struct Redactor {
+    patterns: Vec<Pattern>,
+    /// None leaves hashes out. Some(empty) is a plain SHA-256.
+    hash_key: Option<Vec<u8>>,
+}
+
+impl Redactor {
+    pub fn new(patterns: Vec<Pattern>) -> Redactor {
+        return Redactor { patterns, hash_key: None };
+    }
+
+    pub fn with_defaults() -> Redactor {
+        let patterns = DEFAULT_PATTERNS
+            .iter()
+            .map(|glob| Pattern::Glob(String::from(*glob)))
+            .collect();
+        return Redactor::new(patterns);
+    }
+
+    pub fn pattern(mut self, pattern: Pattern) -> Redactor {
+        self.patterns.push(pattern);
+        return self;
+    }
+
+    /// Adds a short hash of each value. With a key it is an HMAC, so the
+    /// hash cannot be used to guess short secrets offline; with an empty key
+    /// it is plain SHA-256.
+    pub fn hash_with(mut self, key: &[u8]) -> Redactor {
+        self.hash_key = Some(key.to_vec());
+        return self;
+    }
+
+    pub fn patterns(&self) -> &[Pattern] {
+        return &self.patterns;
+    }
+
+    pub fn is_sensitive(&self, name: &str) -> bool {
+        return self.patterns.iter().any(|p| p.matches(name));
+    }
+
+    /// The placeholder for a value, e.g. `[REDACTED len=12]`.
+    pub fn redact(&self, value: &str) -> String {
+        let Some(key) = &self.hash_key else {
+            return format!("[REDACTED len={}]", value.len());
+        };
+        let digest = if key.is_empty() {
+            sha256(value.as_bytes())
+        } else {
+            hmac::<Sha256>(key, value.as_bytes())
+        };
+        let mut hash = hex::encode(&digest);
+        hash.truncate(HASH_LENGTH);
+        return format!("[REDACTED len={} hash={hash}]", value.len());
+    }
+
+    /// The value to show for `name`.
+    pub fn display(&self, name: &str, value: &str) -> String {
+        if self.is_sensitive(name) {
+            return self.redact(value);
+        }
+        return String::from(value);
+    }
+}
+
+/// Reads TESTCGI_REDACT, TESTCGI_REDACT_DEFAULTS and TESTCGI_REDACT_HASH.
+/// When hashes are on they are keyed with TESTCGI_SECRET if it is set.
+pub fn from_env() -> Result<Redactor, String> {
+    let defaults = config::get(DEFAULTS_VARIABLE).unwrap_or_default();
+    let parsed = config::parse_bool(&defaults);
+    let mut redactor = match (defaults.as_str(), parsed) {
+        ("", _) | (_, Some(true)) => Redactor::with_defaults(),
+        (_, Some(false)) => Redactor::new(Vec::new()),
+        (other, None) => {
+            return Err(format!("{DEFAULTS_VARIABLE} should be on or off, \
+                not {other:?}."));
+        }
+    };
+    let extra = config::get(PATTERNS_VARIABLE).unwrap_or_default();
+    let extra = match split_patterns(&extra) {
+        Ok(extra) => extra,
+        Err(message) => return Err(format!("{PATTERNS_VARIABLE}: {message}")),
+    };
+    for item in extra {
+        match Pattern::parse(&item) {
+            Ok(pattern) => redactor = redactor.pattern(pattern),
+            Err(message) => {
+                return Err(format!("{PATTERNS_VARIABLE}: {message}"));
+            }
+        }
+    }
+    let hash = config::get(HASH_VARIABLE).unwrap_or_default();
+    match (hash.as_str(), config::parse_bool(&hash)) {
+        ("", _) | (_, Some(false)) => {}
+        (_, Some(true)) => {
+            let key = config::get("TESTCGI_SECRET").unwrap_or_default();
+            redactor = redactor.hash_with(key.as_bytes());
+        }
+        (other, None) => {
+            return Err(format!("{HASH_VARIABLE} should be on or off, not \
+                {other:?}."));
+        }
+    }
+    return Ok(redactor);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_globs() {
+        assert!(glob_match("*TOKEN*", "HTTP_X_CSRF_TOKEN"));
+        assert!(glob_match("*token*", "Token"));
+        assert!(glob_match("*_PASS", "DB_PASS"));
+        assert!(!glob_match("*_PASS", "DB_PASSWORD"));
+        assert!(glob_match("HTTP_?", "HTTP_A"));
+        assert!(!glob_match("HTTP_?", "HTTP_"));
+        assert!(glob_match("a*b*c", "aXXbYbZc"));
+        assert!(!glob_match("a*b*c", "aXXbYbZ"));
+        assert!(glob_match("**", ""));
+        assert!(!glob_match("", "a"));
+        assert!(glob_match("É*", "É_X"));
+    }
+
+    #[test]
+    fn splits_pattern_lists() {
+        assert_eq!(split_patterns(" A, B\t/x, y/,/a\\/b/ ").unwrap(),
+            ["A", "B", "/x, y/", "/a\\/b/"]);
+        assert!(split_patterns("/open").is_err());
+        assert!(split_patterns("").unwrap().is_empty());
+        assert!(matches!(Pattern::parse("/^DB_/").unwrap(),
+            Pattern::Regex(_)));
+        assert!(matches!(Pattern::parse("/").unwrap(), Pattern::Glob(_)));
+        assert!(Pattern::parse("/(/").is_err());
+    }
+
+    #[test]
+    fn redacts_values() {
+        let redactor = Redactor::with_defaults()
+            .pattern(Pattern::parse("/^DB_/").unwrap());
+        assert_eq!(redactor.display("HTTP_AUTHORIZATION", "Basic abc"),
+            "[REDACTED len=9]");
+        assert_eq!(redactor.display("DB_HOST", "db"), "[REDACTED len=2]");
+        assert_eq!(redactor.display("PATH_INFO", "/x"), "/x");
+        let hashed = Redactor::with_defaults().hash_with(b"");
+        assert_eq!(hashed.redact("secret"),
+            "[REDACTED len=6 hash=2bb80d537b1d]");
+        let keyed = Redactor::with_defaults().hash_with(b"key");
+        assert_ne!(keyed.redact("secret"), hashed.redact("secret"));
+    }
+}
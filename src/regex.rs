@@ -0,0 +1,562 @@
+// src/regex.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! A small regular expression engine for matching names in configuration.
+//!
+//! It covers the everyday syntax: literals, `.`, `[a-z]` and `[^...]`
+//! classes, `\d \w \s` and their negations, `^` and `$`, groups with `|`,
+//! and the `* + ? {n} {n,} {n,m}` repeats. `(?i)` at the start makes the
+//! match case-insensitive. There are no captures or backreferences.
+//!
+//! Patterns are compiled to an NFA that is run over the input one character
+//! at a time, so matching time is linear in the input no matter what the
+//! pattern looks like.
+
+use std::fmt;
+
+/// The most a `{n,m}` repeat may ask for, to keep compiled patterns small.
+pub const MAX_REPEAT: u32 = 100;
+
+#[derive(Clone)]
+pub struct Regex {
+    source: String,
+    program: Vec<Instruction>,
+    case_insensitive: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    pub message: String,
+    /// Character offset into the pattern.
+    pub position: usize,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{} at character {}", self.message, self.position);
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat(Box<Node>, u32, Option<u32>),
+}
+
+#[derive(Clone, Debug)]
+struct Class {
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
+
+impl Class {
+    fn matches(&self, c: char, case_insensitive: bool) -> bool {
+        let hit = |c: char| self.ranges.iter().any(|(a, b)| *a <= c && c <= *b);
+        let mut found = hit(c);
+        if !found && case_insensitive {
+            found = hit(c.to_ascii_lowercase()) || hit(c.to_ascii_uppercase());
+        }
+        return found != self.negated;
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Instruction {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, Error> {
+        let (case_insensitive, body, offset) =
+            match pattern.strip_prefix("(?i)") {
+                Some(rest) => (true, rest, 4),
+                None => (false, pattern, 0),
+            };
+        let mut parser = Parser {
+            chars: body.chars().collect(),
+            position: 0,
+            offset,
+        };
+        let node = parser.alternation()?;
+        if parser.position < parser.chars.len() {
+            return Err(parser.error("Unmatched )"));
+        }
+        let mut program: Vec<Instruction> = Vec::new();
+        compile(&node, &mut program);
+        program.push(Instruction::Match);
+        return Ok(Regex {
+            source: String::from(pattern),
+            program,
+            case_insensitive,
+        });
+    }
+
+    pub fn as_str(&self) -> &str {
+        return &self.source;
+    }
+
+    /// True if the pattern matches anywhere in `text`. Use `^` and `$` to
+    /// match the whole thing.
+    pub fn is_match(&self, text: &str) -> bool {
+        let length = self.program.len();
+        let mut current: Vec<usize> = Vec::with_capacity(length);
+        let mut next: Vec<usize> = Vec::with_capacity(length);
+        let mut seen: Vec<usize> = vec![usize::MAX; length];
+        let chars: Vec<char> = text.chars().collect();
+        for position in 0..=chars.len() {
+            // Starting a new thread at every position makes this a search.
+            if self.add(&mut current, &mut seen, position, 0, &chars) {
+                return true;
+            }
+            let Some(&c) = chars.get(position) else {
+                break;
+            };
+            next.clear();
+            for &pc in &current {
+                let advances = match &self.program[pc] {
+                    Instruction::Char(expected) => {
+                        *expected == c || (self.case_insensitive
+                            && expected.eq_ignore_ascii_case(&c))
+                    }
+                    Instruction::Any => true,
+                    Instruction::Class(class) => {
+                        class.matches(c, self.case_insensitive)
+                    }
+                    _ => false,
+                };
+                if advances
+                    && self.add(&mut next, &mut seen, position + 1, pc + 1,
+                        &chars) {
+                    return true;
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+        }
+        return false;
+    }
+
+    /// Follows jumps, splits and assertions from `pc` and queues every
+    /// instruction that consumes a character. True if a Match is reached.
+    fn add(&self, list: &mut Vec<usize>, seen: &mut [usize], position: usize,
+        pc: usize, chars: &[char]) -> bool
+    {
+        if seen[pc] == position {
+            return false;
+        }
+        seen[pc] = position;
+        return match &self.program[pc] {
+            Instruction::Match => true,
+            Instruction::Jump(target) => {
+                self.add(list, seen, position, *target, chars)
+            }
+            Instruction::Split(a, b) => {
+                self.add(list, seen, position, *a, chars)
+                    || self.add(list, seen, position, *b, chars)
+            }
+            Instruction::Start => {
+                position == 0 && self.add(list, seen, position, pc + 1, chars)
+            }
+            Instruction::End => {
+                position == chars.len()
+                    && self.add(list, seen, position, pc + 1, chars)
+            }
+            _ => {
+                list.push(pc);
+                false
+            }
+        };
+    }
+}
+
+impl fmt::Debug for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "Regex({:?})", self.source);
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    offset: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> Error {
+        return Error {
+            message: String::from(message),
+            position: self.position + self.offset,
+        };
+    }
+
+    fn peek(&self) -> Option<char> {
+        return self.chars.get(self.position).copied();
+    }
+
+    fn alternation(&mut self) -> Result<Node, Error> {
+        let mut branches: Vec<Node> = vec![self.concatenation()?];
+        while self.peek() == Some('|') {
+            self.position += 1;
+            branches.push(self.concatenation()?);
+        }
+        if branches.len() == 1 {
+            return Ok(branches.pop().unwrap());
+        }
+        return Ok(Node::Alternate(branches));
+    }
+
+    fn concatenation(&mut self) -> Result<Node, Error> {
+        let mut items: Vec<Node> = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            items.push(self.repeat(atom)?);
+        }
+        return Ok(match items.len() {
+            0 => Node::Empty,
+            1 => items.pop().unwrap(),
+            _ => Node::Concat(items),
+        });
+    }
+
+    fn repeat(&mut self, atom: Node) -> Result<Node, Error> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => return self.counted(atom),
+            _ => return Ok(atom),
+        };
+        if matches!(atom, Node::Start | Node::End) {
+            return Err(self.error("Nothing to repeat"));
+        }
+        self.position += 1;
+        match self.peek() {
+            Some('?') => {
+                return Err(self.error("Lazy repeats are not supported"));
+            }
+            Some('+') => {
+                return Err(self.error("Possessive repeats are not supported"));
+            }
+            Some('*') => {
+                return Err(self.error("A repeat cannot be repeated again"));
+            }
+            _ => {}
+        }
+        return Ok(Node::Repeat(Box::new(atom), min, max));
+    }
+
+    fn counted(&mut self, atom: Node) -> Result<Node, Error> {
+        let start = self.position;
+        self.position += 1;
+        let min = self.number();
+        let max = if self.peek() == Some(',') {
+            self.position += 1;
+            self.number()
+        } else {
+            min
+        };
+        if self.peek() != Some('}') || min.is_none() {
+            // Like most engines, a brace that is not a valid repeat is a
+            // literal brace.
+            self.position = start + 1;
+            return Ok(Node::Concat(vec![atom, Node::Char('{')]));
+        }
+        self.position += 1;
+        let min = min.unwrap();
+        if min > MAX_REPEAT || max.is_some_and(|m| m > MAX_REPEAT || m < min)
+        {
+            return Err(self.error("Bad repeat count"));
+        }
+        return Ok(Node::Repeat(Box::new(atom), min, max));
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+        let digits: String = self.chars[start..self.position].iter().collect();
+        return digits.parse().ok();
+    }
+
+    fn atom(&mut self) -> Result<Node, Error> {
+        let c = self.peek().unwrap();
+        self.position += 1;
+        return match c {
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Start),
+            '$' => Ok(Node::End),
+            '(' => {
+                if self.chars[self.position..].starts_with(&['?', ':']) {
+                    self.position += 2;
+                } else if self.peek() == Some('?') {
+                    return Err(self.error("Unsupported group syntax"));
+                }
+                let inner = self.alternation()?;
+                if self.peek() != Some(')') {
+                    return Err(self.error("Missing )"));
+                }
+                self.position += 1;
+                Ok(inner)
+            }
+            '[' => self.class(),
+            '\\' => self.escape(false).map(|class| match class {
+                Ok(c) => Node::Char(c),
+                Err(class) => Node::Class(class),
+            }),
+            '*' | '+' | '?' => Err(Error {
+                message: String::from("Nothing to repeat"),
+                position: self.position - 1 + self.offset,
+            }),
+            c => Ok(Node::Char(c)),
+        };
+    }
+
+    /// A backslash escape, as a single character or a class like `\d`.
+    fn escape(&mut self, in_class: bool) -> Result<Result<char, Class>, Error> {
+        let Some(c) = self.peek() else {
+            return Err(self.error("Trailing backslash"));
+        };
+        self.position += 1;
+        let class = |ranges: &[(char, char)], negated: bool| Class {
+            ranges: ranges.to_vec(),
+            negated,
+        };
+        const DIGIT: &[(char, char)] = &[('0', '9')];
+        const WORD: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'),
+            ('a', 'z')];
+        const SPACE: &[(char, char)] = &[('\t', '\r'), (' ', ' ')];
+        return Ok(match c {
+            'd' => Err(class(DIGIT, false)),
+            'D' if !in_class => Err(class(DIGIT, true)),
+            'w' => Err(class(WORD, false)),
+            'W' if !in_class => Err(class(WORD, true)),
+            's' => Err(class(SPACE, false)),
+            'S' if !in_class => Err(class(SPACE, true)),
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            c if c.is_ascii_alphanumeric() => {
+                return Err(self.error("Unknown escape"));
+            }
+            c => Ok(c),
+        });
+    }
+
+    fn class(&mut self) -> Result<Node, Error> {
+        let mut class = Class { ranges: Vec::new(), negated: false };
+        if self.peek() == Some('^') {
+            class.negated = true;
+            self.position += 1;
+        }
+        let mut first = true;
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(self.error("Missing ]"));
+            };
+            if c == ']' && !first {
+                self.position += 1;
+                break;
+            }
+            first = false;
+            self.position += 1;
+            let low = if c == '\\' {
+                match self.escape(true)? {
+                    Ok(c) => c,
+                    Err(inner) => {
+                        class.ranges.extend(inner.ranges);
+                        continue;
+                    }
+                }
+            } else {
+                c
+            };
+            let is_range = self.peek() == Some('-')
+                && self.chars.get(self.position + 1).is_some_and(|c| *c != ']');
+            if !is_range {
+                class.ranges.push((low, low));
+                continue;
+            }
+            self.position += 1;
+            let mut high = self.chars[self.position];
+            self.position += 1;
+            if high == '\\' {
+                high = match self.escape(true)? {
+                    Ok(c) => c,
+                    Err(_) => return Err(self.error("Bad class range")),
+                };
+            }
+            if high < low {
+                return Err(self.error("Bad class range"));
+            }
+            class.ranges.push((low, high));
+        }
+        return Ok(Node::Class(class));
+    }
+}
+
+fn compile(node: &Node, program: &mut Vec<Instruction>) {
+    match node {
+        Node::Empty => {}
+        Node::Char(c) => program.push(Instruction::Char(*c)),
+        Node::Any => program.push(Instruction::Any),
+        Node::Class(class) => program.push(Instruction::Class(class.clone())),
+        Node::Start => program.push(Instruction::Start),
+        Node::End => program.push(Instruction::End),
+        Node::Concat(items) => {
+            for item in items {
+                compile(item, program);
+            }
+        }
+        Node::Alternate(branches) => {
+            // split L1, next; L1: branch; jump end; next: split ...
+            let mut jumps: Vec<usize> = Vec::new();
+            for (index, branch) in branches.iter().enumerate() {
+                if index + 1 == branches.len() {
+                    compile(branch, program);
+                    break;
+                }
+                let split = program.len();
+                program.push(Instruction::Split(split + 1, 0));
+                compile(branch, program);
+                jumps.push(program.len());
+                program.push(Instruction::Jump(0));
+                let next = program.len();
+                program[split] = Instruction::Split(split + 1, next);
+            }
+            let end = program.len();
+            for jump in jumps {
+                program[jump] = Instruction::Jump(end);
+            }
+        }
+        Node::Repeat(inner, min, max) => {
+            for _ in 0..*min {
+                compile(inner, program);
+            }
+            match max {
+                None => {
+                    // loop: split body, end; body; jump loop
+                    let split = program.len();
+                    program.push(Instruction::Split(split + 1, 0));
+                    compile(inner, program);
+                    program.push(Instruction::Jump(split));
+                    let end = program.len();
+                    program[split] = Instruction::Split(split + 1, end);
+                }
+                Some(max) => {
+                    let mut splits: Vec<usize> = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Instruction::Split(0, 0));
+                        compile(inner, program);
+                    }
+                    let end = program.len();
+                    for split in splits {
+                        program[split] = Instruction::Split(split + 1, end);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        return Regex::new(pattern).unwrap().is_match(text);
+    }
+
+    #[test]
+    fn literals_and_anchors() {
+        assert!(matches("abc", "xabcx"));
+        assert!(!matches("^abc", "xabc"));
+        assert!(!matches("abc$", "abcx"));
+        assert!(matches("^$", ""));
+        assert!(matches("a.c", "a\u{e9}c"));
+        assert!(matches("a\\.c", "a.c"));
+        assert!(!matches("a\\.c", "abc"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("^[a-c]+$", "abcabc"));
+        assert!(!matches("^[a-c]+$", "abcd"));
+        assert!(matches("^[^0-9]+$", "abc"));
+        assert!(!matches("^[^0-9]+$", "a1"));
+        assert!(matches("^\\d\\w\\s$", "1_ "));
+        assert!(matches("^\\D\\W\\S$", "a-b"));
+        assert!(matches("^[-a]$", "-"));
+    }
+
+    #[test]
+    fn alternation_and_repeats() {
+        assert!(matches("^(cat|dog)s?$", "dogs"));
+        assert!(!matches("^(cat|dog)s?$", "cow"));
+        assert!(matches("^a{3}$", "aaa"));
+        assert!(!matches("^a{3}$", "aa"));
+        assert!(matches("^a{2,}$", "aaaa"));
+        assert!(matches("^a{1,2}b$", "aab"));
+        assert!(!matches("^a{1,2}b$", "aaab"));
+        assert!(matches("^(ab)*$", ""));
+        assert!(matches("^x(ab)+$", "xabab"));
+    }
+
+    #[test]
+    fn case_insensitive() {
+        assert!(matches("(?i)^secret_[a-z]+$", "SECRET_Key"));
+        assert!(!matches("^secret$", "SECRET"));
+    }
+
+    /// The classic pattern that takes a backtracking engine exponential
+    /// time finishes at once here.
+    #[test]
+    fn no_catastrophic_backtracking() {
+        let text = "a".repeat(64);
+        assert!(!matches("^(a+)+$", &format!("{text}!")));
+        assert!(matches("^(a|aa)*$", &text));
+    }
+
+    #[test]
+    fn errors() {
+        for pattern in ["*a", "(a", "a)", "[a", "a{2,1}", "a{101}", "\\"] {
+            assert!(Regex::new(pattern).is_err(), "{pattern} was accepted");
+        }
+        assert_eq!(Regex::new("ab(").unwrap_err().position, 3);
+        assert_eq!(Regex::new("a**").unwrap_err().message,
+            "A repeat cannot be repeated again");
+        assert_eq!(Regex::new("a+?").unwrap_err().message,
+            "Lazy repeats are not supported");
+        assert_eq!(Regex::new("a?+").unwrap_err().message,
+            "Possessive repeats are not supported");
+    }
+}
@@ -0,0 +1,319 @@
+// src/request.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! The request as a script sees it, with the header fields put back
+//! together from the `HTTP_*` meta-variables.
+//!
+//! RFC 3875 section 4.1.18 has the server upper case each field name, turn
+//! `-` into `_` and put `HTTP_` in front. That loses the original case and
+//! the difference between `-` and `_`, so `X-Api-Key` and `X_Api_Key` arrive
+//! as the same `HTTP_X_API_KEY`. The names here are the usual spelling of
+//! each field, not necessarily what the client sent.
+
+use std::env;
+use std::fmt;
+
+use crate::catalog;
+use crate::catalog::Translator;
+use crate::json;
+use crate::json::Value;
+use crate::server::NormalizedPath;
+
+/// Words that are written in capitals in well known field names, such as
+/// `DNT`, `TE` and `Sec-CH-UA`.
+const UPPER_CASE_WORDS: &[&str] = &["CH", "CSRF", "DNT", "MD5", "TE", "UA",
+    "WWW", "XSRF", "XSS"];
+
+/// Turns a meta-variable name into a header field name: `HTTP_USER_AGENT`
+/// becomes `User-Agent`. CONTENT_TYPE and CONTENT_LENGTH are done too
+/// because RFC 3875 moves those two fields out of `HTTP_*`.
+pub fn header_name(variable: &str) -> Option<String> {
+    let rest = match variable {
+        "CONTENT_TYPE" | "CONTENT_LENGTH" => variable,
+        _ => variable.strip_prefix("HTTP_")?,
+    };
+    if rest.is_empty() {
+        return None;
+    }
+    let words: Vec<String> = rest.split('_')
+        .map(|word| {
+            if UPPER_CASE_WORDS.contains(&word) {
+                return String::from(word);
+            }
+            let lower = word.to_ascii_lowercase();
+            let mut chars = lower.chars();
+            return match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string()
+                    + chars.as_str(),
+                None => String::new(),
+            };
+        })
+        .collect();
+    return Some(words.join("-"));
+}
+
+/// One header field rebuilt from a meta-variable.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header {
+    /// The canonical spelling, e.g. `Accept-Language`.
+    pub name: String,
+    /// Where it came from, e.g. `HTTP_ACCEPT_LANGUAGE`.
+    pub variable: String,
+    pub value: String,
+}
+
+/// Something about a header field a script should not take on trust.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Note {
+    /// Could have been sent with `_` in place of `-`; the spelling is here.
+    Underscore(String),
+    SetByServer,
+    Credentials,
+    Httpoxy,
+    Joined,
+    /// Passed as `HTTP_*` although RFC 3875 has its own variable for it,
+    /// named here, which is preferred when both are set.
+    Duplicate(String),
+}
+
+impl Note {
+    pub fn describe(&self, tr: &Translator) -> String {
+        return match self {
+            Note::Underscore(name) => {
+                tr.format("headers.note.underscore", &[name])
+            }
+            Note::SetByServer => String::from(tr.get("headers.note.server")),
+            Note::Credentials => {
+                String::from(tr.get("headers.note.credentials"))
+            }
+            Note::Httpoxy => String::from(tr.get("headers.note.httpoxy")),
+            Note::Joined => String::from(tr.get("headers.note.joined")),
+            Note::Duplicate(variable) => {
+                tr.format("headers.note.duplicate", &[variable])
+            }
+        };
+    }
+}
+
+impl fmt::Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}", self.describe(&catalog::english()));
+    }
+}
+
+impl Header {
+    /// Anything about this field a script should not take on trust.
+    pub fn notes(&self) -> Vec<Note> {
+        let mut notes: Vec<Note> = Vec::new();
+        if self.name.contains('-') && self.variable.starts_with("HTTP_") {
+            notes.push(Note::Underscore(self.name.replace('-', "_")));
+        }
+        if self.variable == "CONTENT_TYPE"
+            || self.variable == "CONTENT_LENGTH" {
+            notes.push(Note::SetByServer);
+        }
+        if self.variable == "HTTP_CONTENT_TYPE"
+            || self.variable == "HTTP_CONTENT_LENGTH" {
+            notes.push(Note::Duplicate(String::from(&self.variable[5..])));
+        }
+        if self.name == "Authorization" || self.name == "Proxy-Authorization" {
+            notes.push(Note::Credentials);
+        }
+        if self.name == "Proxy" {
+            notes.push(Note::Httpoxy);
+        }
+        if self.value.contains(", ") {
+            notes.push(Note::Joined);
+        }
+        return notes;
+    }
+}
+
+/// The request header fields, looked up without regard to case.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Headers {
+    fields: Vec<Header>,
+}
+
+impl Headers {
+    /// Collects the header fields from meta-variables, sorted by name.
+    pub fn from_variables(variables: &[(String, String)]) -> Headers {
+        let mut fields: Vec<Header> = Vec::new();
+        for (variable, value) in variables {
+            let Some(name) = header_name(variable) else {
+                continue;
+            };
+            // The last value wins, as it would for getenv.
+            fields.retain(|f| f.variable != *variable);
+            fields.push(Header {
+                name,
+                variable: variable.clone(),
+                value: value.clone(),
+            });
+        }
+        fields.sort_by(|a, b| a.name.cmp(&b.name));
+        return Headers { fields };
+    }
+
+    /// The value of the field called `name`. `-` and `_` are treated as the
+    /// same character because the server already did.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        return self.find(name).map(|f| f.value.as_str());
+    }
+
+    /// The field called `name`. CONTENT_TYPE and CONTENT_LENGTH are taken
+    /// over HTTP_CONTENT_TYPE and HTTP_CONTENT_LENGTH when a server sets
+    /// both, because RFC 3875 says those are the ones that describe the
+    /// body.
+    pub fn find(&self, name: &str) -> Option<&Header> {
+        let wanted = name.replace('_', "-");
+        return self.fields.iter()
+            .filter(|f| f.name.eq_ignore_ascii_case(&wanted))
+            .min_by_key(|f| f.variable.starts_with("HTTP_"));
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        return self.find(name).is_some();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Header> {
+        return self.fields.iter();
+    }
+
+    pub fn len(&self) -> usize {
+        return self.fields.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.fields.is_empty();
+    }
+}
+
+/// The environment, with whatever is not UTF-8 converted lossily where
+/// `env::vars()` would panic.
+pub fn variables() -> Vec<(String, String)> {
+    return env::vars_os()
+        .map(|(n, v)| (n.to_string_lossy().into_owned(),
+            v.to_string_lossy().into_owned()))
+        .collect();
+}
+
+/// The parts of a request most scripts need, taken from the meta-variables.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub path: NormalizedPath,
+    pub query_string: String,
+    headers: Headers,
+}
+
+impl Request {
+    pub fn from_env() -> Request {
+        let variables = variables();
+        return Request::from_variables(&variables);
+    }
+
+    pub fn from_variables(variables: &[(String, String)]) -> Request {
+        let get = |name: &str| {
+            return variables.iter().rev()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.clone())
+                .unwrap_or_default();
+        };
+        return Request {
+            method: get("REQUEST_METHOD"),
+            path: NormalizedPath::from_variables(variables),
+            query_string: get("QUERY_STRING"),
+            headers: Headers::from_variables(variables),
+        };
+    }
+
+    pub fn headers(&self) -> &Headers {
+        return &self.headers;
+    }
+
+    /// Parses `body` if the Content-Type says it is JSON, within the limits
+    /// from json.max_depth and json.max_size. None for any other type.
+    pub fn json(&self, body: &[u8]) -> Option<Result<Value, json::Error>> {
+        if !json::is_json(self.headers.get("Content-Type")?) {
+            return None;
+        }
+        return Some(json::parse_with(body, &json::Limits::from_env()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        return pairs.iter()
+            .map(|(n, v)| (String::from(*n), String::from(*v)))
+            .collect();
+    }
+
+    #[test]
+    fn names_header_fields() {
+        let name = |variable: &str| header_name(variable);
+        assert_eq!(name("HTTP_USER_AGENT").as_deref(), Some("User-Agent"));
+        assert_eq!(name("HTTP_DNT").as_deref(), Some("DNT"));
+        assert_eq!(name("HTTP_SEC_CH_UA_MOBILE").as_deref(),
+            Some("Sec-CH-UA-Mobile"));
+        assert_eq!(name("HTTP_X_CSRF_TOKEN").as_deref(),
+            Some("X-CSRF-Token"));
+        assert_eq!(name("CONTENT_TYPE").as_deref(), Some("Content-Type"));
+        assert_eq!(name("HTTP_"), None);
+        assert_eq!(name("PATH_INFO"), None);
+        assert_eq!(name("http_host"), None);
+    }
+
+    #[test]
+    fn finds_headers() {
+        let headers = Headers::from_variables(&variables(&[
+            ("HTTP_CONTENT_TYPE", "text/plain"),
+            ("CONTENT_TYPE", "application/json"),
+            ("HTTP_X_API_KEY", "one"),
+            ("HTTP_X_API_KEY", "two"),
+            ("HTTP_ACCEPT", "a, b"),
+            ("REMOTE_ADDR", "192.0.2.1"),
+        ]));
+        assert_eq!(headers.len(), 4);
+        assert_eq!(headers.get("content-type"), Some("application/json"));
+        assert_eq!(headers.get("X_Api_Key"), Some("two"));
+        assert!(!headers.contains("Remote-Addr"));
+        let names: Vec<&str> = headers.iter().map(|h| h.name.as_str())
+            .collect();
+        assert_eq!(names, ["Accept", "Content-Type", "Content-Type",
+            "X-Api-Key"]);
+        assert_eq!(headers.find("Accept").unwrap().notes(), [Note::Joined]);
+        assert_eq!(headers.find("X-Api-Key").unwrap().notes(),
+            [Note::Underscore(String::from("X_Api_Key"))]);
+    }
+
+    #[test]
+    fn parses_json_bodies_only() {
+        let request = Request::from_variables(&variables(&[
+            ("REQUEST_METHOD", "POST"),
+            ("CONTENT_TYPE", "application/json"),
+        ]));
+        assert_eq!(request.method, "POST");
+        assert!(request.json(b"[1]").unwrap().is_ok());
+        assert!(request.json(b"[1").unwrap().is_err());
+        let form = Request::from_variables(&variables(&[
+            ("CONTENT_TYPE", "application/x-www-form-urlencoded"),
+        ]));
+        assert!(form.json(b"[1]").is_none());
+    }
+}
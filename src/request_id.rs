@@ -0,0 +1,280 @@
+// src/request_id.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! An ID for each request, so a testcgi page and log lines can be matched up
+//! with what the proxies and the web server logged.
+//!
+//! An `X-Request-Id` from upstream is kept if it looks sane. Failing that
+//! the trace-id of a W3C `traceparent` header is used, and failing that a
+//! new ID is made from /dev/urandom.
+
+use std::env;
+use std::fmt;
+use std::process;
+use std::sync::OnceLock;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use crate::catalog;
+use crate::catalog::Translator;
+use crate::hex;
+use crate::random;
+
+/// The longest X-Request-Id that is accepted from a client.
+pub const MAX_LENGTH: usize = 200;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+    Header,
+    TraceParent,
+    Generated,
+}
+
+impl Source {
+    pub fn describe(&self, tr: &Translator) -> String {
+        return String::from(tr.get(match self {
+            Source::Header => "request_id.from_header",
+            Source::TraceParent => "request_id.from_traceparent",
+            Source::Generated => "request_id.generated",
+        }));
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}", self.describe(&catalog::english()));
+    }
+}
+
+/// Why a `traceparent` header was not used.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TraceParentError {
+    Missing,
+    FieldCount,
+    Version,
+    VersionFf,
+    Version00Fields,
+    TraceId,
+    TraceIdZero,
+    ParentId,
+    ParentIdZero,
+    Flags,
+}
+
+impl TraceParentError {
+    pub fn describe(&self, tr: &Translator) -> String {
+        return String::from(tr.get(match self {
+            TraceParentError::Missing => "traceparent.missing",
+            TraceParentError::FieldCount => "traceparent.field_count",
+            TraceParentError::Version => "traceparent.version",
+            TraceParentError::VersionFf => "traceparent.version_ff",
+            TraceParentError::Version00Fields => "traceparent.version_00",
+            TraceParentError::TraceId => "traceparent.trace_id",
+            TraceParentError::TraceIdZero => "traceparent.trace_id_zero",
+            TraceParentError::ParentId => "traceparent.parent_id",
+            TraceParentError::ParentIdZero => "traceparent.parent_id_zero",
+            TraceParentError::Flags => "traceparent.flags",
+        }));
+    }
+}
+
+impl fmt::Display for TraceParentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}", self.describe(&catalog::english()));
+    }
+}
+
+/// A parsed W3C Trace Context `traceparent` header,
+/// `version-traceid-parentid-flags`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceParent {
+    pub version: u8,
+    /// 32 lowercase hex digits.
+    pub trace_id: String,
+    /// The span id of the caller, 16 lowercase hex digits.
+    pub parent_id: String,
+    pub flags: u8,
+}
+
+impl TraceParent {
+    pub fn parse(header: &str) -> Result<TraceParent, TraceParentError> {
+        let header = header.trim();
+        let parts: Vec<&str> = header.split('-').collect();
+        if parts.len() < 4 {
+            return Err(TraceParentError::FieldCount);
+        }
+        let is_hex = |s: &str, len: usize| s.len() == len
+            && s.bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+        if !is_hex(parts[0], 2) {
+            return Err(TraceParentError::Version);
+        }
+        let version = u8::from_str_radix(parts[0], 16).unwrap();
+        if version == 0xff {
+            return Err(TraceParentError::VersionFf);
+        }
+        // Version 00 has exactly four fields. Later versions may add more,
+        // which a version 00 parser is told to ignore.
+        if version == 0 && parts.len() != 4 {
+            return Err(TraceParentError::Version00Fields);
+        }
+        if !is_hex(parts[1], 32) {
+            return Err(TraceParentError::TraceId);
+        }
+        if parts[1].bytes().all(|b| b == b'0') {
+            return Err(TraceParentError::TraceIdZero);
+        }
+        if !is_hex(parts[2], 16) {
+            return Err(TraceParentError::ParentId);
+        }
+        if parts[2].bytes().all(|b| b == b'0') {
+            return Err(TraceParentError::ParentIdZero);
+        }
+        if !is_hex(parts[3], 2) {
+            return Err(TraceParentError::Flags);
+        }
+        return Ok(TraceParent {
+            version,
+            trace_id: String::from(parts[1]),
+            parent_id: String::from(parts[2]),
+            flags: u8::from_str_radix(parts[3], 16).unwrap(),
+        });
+    }
+
+    pub fn sampled(&self) -> bool {
+        return self.flags & 0x01 != 0;
+    }
+}
+
+/// Printable ASCII without spaces, so the ID cannot break a header or a
+/// log line.
+pub fn is_valid_id(id: &str) -> bool {
+    return !id.is_empty() && id.len() <= MAX_LENGTH
+        && id.bytes().all(|b| b.is_ascii_graphic());
+}
+
+#[derive(Clone, Debug)]
+pub struct RequestId {
+    pub id: String,
+    pub source: Source,
+    /// The raw headers, for the diagnostic page.
+    pub x_request_id: Option<String>,
+    pub traceparent_header: Option<String>,
+    pub tracestate: Option<String>,
+    pub traceparent: Result<TraceParent, TraceParentError>,
+}
+
+impl RequestId {
+    pub fn from_env() -> RequestId {
+        let get = |name: &str| env::var(name).ok().filter(|v| !v.is_empty());
+        let x_request_id = get("HTTP_X_REQUEST_ID");
+        let traceparent_header = get("HTTP_TRACEPARENT");
+        let traceparent = match &traceparent_header {
+            Some(header) => TraceParent::parse(header),
+            None => Err(TraceParentError::Missing),
+        };
+        let (id, source) = match (&x_request_id, &traceparent) {
+            (Some(id), _) if is_valid_id(id.trim()) => {
+                (String::from(id.trim()), Source::Header)
+            }
+            (_, Ok(parent)) => (parent.trace_id.clone(), Source::TraceParent),
+            _ => (generate(), Source::Generated),
+        };
+        return RequestId {
+            id,
+            source,
+            x_request_id,
+            traceparent_header,
+            tracestate: get("HTTP_TRACESTATE"),
+            traceparent,
+        };
+    }
+}
+
+/// 32 random hex digits, the same shape as a trace-id. An ID does not have
+/// to be secret, so if /dev/urandom is missing the time and process id do.
+pub fn generate() -> String {
+    if let Ok(bytes) = random::bytes(16) {
+        return hex::encode(&bytes);
+    }
+    let nanos = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_nanos(),
+        Err(_) => 0,
+    };
+    return format!("{nanos:024x}{:08x}", process::id());
+}
+
+static CURRENT: OnceLock<RequestId> = OnceLock::new();
+
+/// The ID of the request being handled, worked out the first time it is
+/// asked for.
+pub fn current() -> &'static RequestId {
+    return CURRENT.get_or_init(RequestId::from_env);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+    #[test]
+    fn parses_traceparent() {
+        let parent = TraceParent::parse(&format!(
+            " 00-{TRACE_ID}-00f067aa0ba902b7-01 ")).unwrap();
+        assert_eq!(parent.trace_id, TRACE_ID);
+        assert_eq!(parent.parent_id, "00f067aa0ba902b7");
+        assert!(parent.sampled());
+        let later = TraceParent::parse(&format!(
+            "cc-{TRACE_ID}-00f067aa0ba902b7-00-extra")).unwrap();
+        assert_eq!(later.version, 0xcc);
+        assert!(!later.sampled());
+    }
+
+    #[test]
+    fn rejects_bad_traceparent() {
+        let error = |header: &str| TraceParent::parse(header).err();
+        let parent = "00f067aa0ba902b7";
+        assert_eq!(error("00-abc"), Some(TraceParentError::FieldCount));
+        assert_eq!(error(&format!("0-{TRACE_ID}-{parent}-01")),
+            Some(TraceParentError::Version));
+        assert_eq!(error(&format!("ff-{TRACE_ID}-{parent}-01")),
+            Some(TraceParentError::VersionFf));
+        assert_eq!(error(&format!("00-{TRACE_ID}-{parent}-01-x")),
+            Some(TraceParentError::Version00Fields));
+        assert_eq!(error(&format!("00-{}-{parent}-01",
+            TRACE_ID.to_ascii_uppercase())), Some(TraceParentError::TraceId));
+        assert_eq!(error(&format!("00-{}-{parent}-01", "0".repeat(32))),
+            Some(TraceParentError::TraceIdZero));
+        assert_eq!(error(&format!("00-{TRACE_ID}-{}-01", "0".repeat(16))),
+            Some(TraceParentError::ParentIdZero));
+        assert_eq!(error(&format!("00-{TRACE_ID}-abc-01")),
+            Some(TraceParentError::ParentId));
+        assert_eq!(error(&format!("00-{TRACE_ID}-{parent}-1")),
+            Some(TraceParentError::Flags));
+    }
+
+    #[test]
+    fn checks_ids() {
+        assert!(is_valid_id("abc-123"));
+        assert!(!is_valid_id(""));
+        assert!(!is_valid_id("a b"));
+        assert!(!is_valid_id("a\r\nb"));
+        assert!(!is_valid_id(&"a".repeat(MAX_LENGTH + 1)));
+        let id = generate();
+        assert_eq!(id.len(), 32);
+        assert_ne!(id, generate());
+    }
+}
@@ -0,0 +1,126 @@
+// src/response.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+use std::io;
+use std::io::Write;
+
+/// A CGI response that is built up in memory before it is sent.
+///
+/// RFC 3875 section 6 lets us send the status as a `Status:` header field so
+/// the web server can build the real HTTP status line for us.
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: u16) -> Response {
+        return Response {
+            status,
+            headers: Vec::with_capacity(8),
+            body: Vec::<u8>::with_capacity(16 * 1024),
+        };
+    }
+
+    /// A response with a short plain text body.
+    pub fn text(status: u16, message: &str) -> Response {
+        let mut response = Response::new(status);
+        response.header("Content-Type", "text/plain; charset=utf-8");
+        response.body.extend_from_slice(message.as_bytes());
+        response.body.extend_from_slice(b"\n");
+        return response;
+    }
+
+    /// Adds a header field. Fields with the same name are all kept so that
+    /// things like `Set-Cookie` can be repeated.
+    ///
+    /// Values often carry text from the request, so CR and LF are replaced
+    /// with spaces; otherwise they would end the field and start a new one.
+    pub fn header(&mut self, name: &str, value: &str) {
+        let value = value.replace(['\r', '\n'], " ");
+        self.headers.push((String::from(name), value));
+    }
+
+    /// Writes the Status line, the header fields and the blank line that ends
+    /// them.
+    pub fn write_head(&self, out: &mut impl Write) -> io::Result<()> {
+        write!(out, "Status: {} {}\r\n", self.status,
+            reason_phrase(self.status))?;
+        for (name, value) in &self.headers {
+            write!(out, "{name}: {value}\r\n")?;
+        }
+        write!(out, "\r\n")?;
+        return Ok(());
+    }
+
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        self.write_head(out)?;
+        out.write_all(&self.body)?;
+        return Ok(());
+    }
+}
+
+pub fn reason_phrase(status: u16) -> &'static str {
+    return match status {
+        200 => "OK",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        411 => "Length Required",
+        413 => "Content Too Large",
+        415 => "Unsupported Media Type",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn head() {
+        let mut response = Response::text(404, "gone");
+        response.header("Set-Cookie", "a=1");
+        response.header("Set-Cookie", "b=2");
+        let mut out = Vec::new();
+        response.write_to(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Status: 404 Not Found\r\n\
+            Content-Type: text/plain; charset=utf-8\r\n\
+            Set-Cookie: a=1\r\nSet-Cookie: b=2\r\n\r\ngone\n");
+    }
+
+    #[test]
+    fn header_values_cannot_add_fields() {
+        let mut response = Response::new(200);
+        response.header("X-Test", "x\r\nSet-Cookie: pwned=1");
+        let mut out = Vec::new();
+        response.write_head(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Status: 200 OK\r\n\
+            X-Test: x  Set-Cookie: pwned=1\r\n\r\n");
+    }
+}
@@ -0,0 +1,164 @@
+// src/route.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+use std::time::Duration;
+
+/// The things testcgi can do, picked by the PATH_INFO meta-variable.
+///
+/// Anything that is not a known route gets the diagnostic page, so
+/// `/testcgi/some/extra/path` still shows you what the server sent.
+#[derive(Debug, PartialEq)]
+pub enum Route {
+    Diagnostic,
+    Delay(Duration),
+    Drip,
+    SlowBody,
+    CookiesSet,
+    CookiesDelete,
+    Session,
+    SessionDelete,
+    SessionDestroy,
+    /// `/basic-auth/{user}/{pass}`.
+    BasicAuth(String, String),
+    /// `/basic-auth` against the htpasswd file.
+    BasicAuthFile,
+    /// `/digest-auth/{qop}/{user}/{pass}` with an optional `/{algorithm}`.
+    DigestAuth(String, String, String, Option<String>),
+    Bearer,
+    Conformance,
+    Server,
+    Baseline,
+    /// A known route, by name, with arguments we could not make sense of.
+    Invalid(&'static str, &'static str),
+}
+
+/// The names routes.enabled accepts. `diagnostic` is the page everything
+/// else falls back to.
+pub const ROUTE_NAMES: &[&str] = &["diagnostic", "delay", "drip", "slow-body",
+    "cookies", "session", "basic-auth", "digest-auth", "bearer",
+    "conformance", "server", "baseline"];
+
+impl Route {
+    /// The name of the route in ROUTE_NAMES.
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Route::Diagnostic => "diagnostic",
+            Route::Delay(_) => "delay",
+            Route::Drip => "drip",
+            Route::SlowBody => "slow-body",
+            Route::CookiesSet | Route::CookiesDelete => "cookies",
+            Route::Session | Route::SessionDelete | Route::SessionDestroy => {
+                "session"
+            }
+            Route::BasicAuth(..) | Route::BasicAuthFile => "basic-auth",
+            Route::DigestAuth(..) => "digest-auth",
+            Route::Bearer => "bearer",
+            Route::Conformance => "conformance",
+            Route::Server => "server",
+            Route::Baseline => "baseline",
+            Route::Invalid(name, _) => name,
+        };
+    }
+}
+
+pub fn route(path_info: &str) -> Route {
+    let segments: Vec<&str> = path_info
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    return match segments.as_slice() {
+        ["delay", seconds] => match parse_seconds(seconds) {
+            Some(d) => Route::Delay(d),
+            None => {
+                Route::Invalid("delay",
+                    "The delay must be a number of seconds.")
+            }
+        },
+        ["drip"] => Route::Drip,
+        ["slow-body"] => Route::SlowBody,
+        ["cookies", "set"] => Route::CookiesSet,
+        ["cookies", "delete"] => Route::CookiesDelete,
+        ["session"] => Route::Session,
+        ["session", "delete"] => Route::SessionDelete,
+        ["session", "destroy"] => Route::SessionDestroy,
+        ["basic-auth", user, password] => {
+            Route::BasicAuth(user.to_string(), password.to_string())
+        }
+        ["basic-auth"] => Route::BasicAuthFile,
+        ["bearer"] => Route::Bearer,
+        ["conformance"] => Route::Conformance,
+        ["server"] => Route::Server,
+        ["baseline"] => Route::Baseline,
+        ["digest-auth", qop, user, password] => {
+            Route::DigestAuth(qop.to_string(), user.to_string(),
+                password.to_string(), None)
+        }
+        ["digest-auth", qop, user, password, algorithm] => {
+            Route::DigestAuth(qop.to_string(), user.to_string(),
+                password.to_string(), Some(algorithm.to_string()))
+        }
+        _ => Route::Diagnostic,
+    };
+}
+
+/// Parses a non-negative, possibly fractional, number of seconds.
+pub fn parse_seconds(input: &str) -> Option<Duration> {
+    let seconds: f64 = input.trim().parse().ok()?;
+    if !seconds.is_finite() || seconds < 0.0 {
+        return None;
+    }
+    return Duration::try_from_secs_f64(seconds).ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_paths() {
+        assert_eq!(route(""), Route::Diagnostic);
+        assert_eq!(route("/some/extra/path"), Route::Diagnostic);
+        assert_eq!(route("/delay/1.5"),
+            Route::Delay(Duration::from_millis(1500)));
+        assert_eq!(route("//drip/"), Route::Drip);
+        assert_eq!(route("/slow-body"), Route::SlowBody);
+        assert_eq!(route("/basic-auth/u/p"),
+            Route::BasicAuth(String::from("u"), String::from("p")));
+        assert_eq!(route("/digest-auth/auth/u/p/SHA-256"),
+            Route::DigestAuth(String::from("auth"), String::from("u"),
+                String::from("p"), Some(String::from("SHA-256"))));
+        assert!(matches!(route("/delay/soon"), Route::Invalid("delay", _)));
+    }
+
+    #[test]
+    fn names_every_route() {
+        for path in ["", "/delay/1", "/drip", "/slow-body", "/cookies/set",
+            "/session", "/basic-auth", "/digest-auth/auth/u/p", "/bearer",
+            "/conformance", "/server", "/baseline"] {
+            assert!(ROUTE_NAMES.contains(&route(path).name()), "{path}");
+        }
+    }
+
+    #[test]
+    fn parses_seconds() {
+        assert_eq!(parse_seconds(" 2 "), Some(Duration::from_secs(2)));
+        assert_eq!(parse_seconds("0.25"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_seconds("-1"), None);
+        assert_eq!(parse_seconds("inf"), None);
+        assert_eq!(parse_seconds("NaN"), None);
+        assert_eq!(parse_seconds("1e300"), None);
+    }
+}
@@ -0,0 +1,533 @@
+// src/server.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Which web server is running us, what it is known to do differently, and
+//! one way of getting at the request path that works on all of them.
+//!
+//! RFC 3875 leaves a lot to the server. Apache decodes PATH_INFO and refuses
+//! `%2F` unless told otherwise, nginx only sets what `fastcgi_param` lines
+//! pass on, IIS has been known to put SCRIPT_NAME at the front of PATH_INFO,
+//! and everybody adds their own variables. `NormalizedPath` irons those
+//! differences out.
+
+use std::fmt;
+
+use crate::catalog;
+use crate::catalog::Translator;
+use crate::decode_urlencoded::decode_percent_bytes;
+use crate::html;
+use crate::request;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Server {
+    Apache,
+    /// nginx, which needs fcgiwrap or similar to run CGI programs.
+    Nginx,
+    Lighttpd,
+    Caddy,
+    Iis,
+    Busybox,
+    Thttpd,
+    /// Python's http.server with CGIHTTPRequestHandler.
+    Python,
+    Unknown,
+}
+
+impl Server {
+    /// Product names stay as they are; the rest is translated.
+    pub fn describe(&self, tr: &Translator) -> String {
+        return String::from(match self {
+            Server::Apache => "Apache httpd",
+            Server::Nginx => tr.get("server.nginx"),
+            Server::Lighttpd => "lighttpd",
+            Server::Caddy => "Caddy",
+            Server::Iis => "Microsoft IIS",
+            Server::Busybox => "BusyBox httpd",
+            Server::Thttpd => "thttpd",
+            Server::Python => "Python http.server",
+            Server::Unknown => tr.get("server.unknown"),
+        });
+    }
+}
+
+impl fmt::Display for Server {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}", self.describe(&catalog::english()));
+    }
+}
+
+/// SERVER_SOFTWARE prefixes, compared without regard to case.
+const SOFTWARE: &[(&str, Server)] = &[
+    ("apache", Server::Apache),
+    ("nginx", Server::Nginx),
+    ("openresty", Server::Nginx),
+    ("lighttpd", Server::Lighttpd),
+    ("caddy", Server::Caddy),
+    ("microsoft-iis", Server::Iis),
+    ("busybox", Server::Busybox),
+    ("thttpd", Server::Thttpd),
+    ("simplehttp", Server::Python),
+    ("python", Server::Python),
+];
+
+/// Variables that only one server is known to set.
+const FINGERPRINTS: &[(&str, Server)] = &[
+    ("CONTEXT_PREFIX", Server::Apache),
+    ("CONTEXT_DOCUMENT_ROOT", Server::Apache),
+    ("SERVER_SIGNATURE", Server::Apache),
+    ("UNIQUE_ID", Server::Apache),
+    ("DOCUMENT_URI", Server::Nginx),
+    ("INSTANCE_ID", Server::Iis),
+    ("APPL_MD_PATH", Server::Iis),
+];
+
+/// A reason to think the server is what identify says it is.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Evidence {
+    /// SERVER_SOFTWARE, as it was set.
+    Software(String),
+    /// A variable only this server sets.
+    Fingerprint(&'static str, Server),
+}
+
+impl Evidence {
+    pub fn describe(&self, tr: &Translator) -> String {
+        return match self {
+            Evidence::Software(software) => {
+                tr.format("server.software", &[&format!("{software:?}")])
+            }
+            Evidence::Fingerprint(name, server) => {
+                tr.format("server.fingerprint", &[name, &server.describe(tr)])
+            }
+        };
+    }
+}
+
+impl fmt::Display for Evidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}", self.describe(&catalog::english()));
+    }
+}
+
+pub struct Identification {
+    pub server: Server,
+    /// The product version from SERVER_SOFTWARE, if it has one.
+    pub version: Option<String>,
+    /// Why we think so.
+    pub evidence: Vec<Evidence>,
+}
+
+/// Works out the server from SERVER_SOFTWARE, falling back on the variables
+/// it sets. Some servers let administrators change or hide SERVER_SOFTWARE,
+/// which is why the fingerprint is kept as evidence either way.
+pub fn identify(variables: &[(String, String)]) -> Identification {
+    let get = |name: &str| lookup(variables, name);
+    let mut identification = Identification {
+        server: Server::Unknown,
+        version: None,
+        evidence: Vec::new(),
+    };
+    if let Some(software) = get("SERVER_SOFTWARE") {
+        let lower = software.to_ascii_lowercase();
+        if let Some((_, server)) = SOFTWARE.iter()
+            .find(|(prefix, _)| lower.starts_with(prefix)) {
+            identification.server = *server;
+            identification.evidence.push(Evidence::Software(
+                String::from(software)));
+            identification.version = software.split_whitespace().next()
+                .and_then(|product| product.split_once('/'))
+                .map(|(_, version)| String::from(version));
+        }
+    }
+    for (name, server) in FINGERPRINTS {
+        if get(name).is_none() {
+            continue;
+        }
+        identification.evidence.push(Evidence::Fingerprint(name, *server));
+        if identification.server == Server::Unknown {
+            identification.server = *server;
+        }
+    }
+    return identification;
+}
+
+/// Something a server does that a script might not expect.
+pub struct Quirk {
+    /// Catalog key of the short name.
+    pub title: &'static str,
+    /// Catalog key of the explanation, filled in with `arguments`.
+    pub detail: &'static str,
+    pub arguments: Vec<String>,
+    /// True if this request shows the quirk, false if it is only known to
+    /// happen on this server.
+    pub observed: bool,
+}
+
+impl Quirk {
+    pub fn title(&self, tr: &Translator) -> String {
+        return String::from(tr.get(self.title));
+    }
+
+    pub fn detail(&self, tr: &Translator) -> String {
+        let arguments: Vec<&str> =
+            self.arguments.iter().map(String::as_str).collect();
+        return tr.format(self.detail, &arguments);
+    }
+}
+
+fn known(title: &'static str, detail: &'static str) -> Quirk {
+    return Quirk { title, detail, arguments: Vec::new(), observed: false };
+}
+
+fn seen(title: &'static str, detail: &'static str, arguments: &[&str])
+    -> Quirk
+{
+    let arguments = arguments.iter().map(|a| String::from(*a)).collect();
+    return Quirk { title, detail, arguments, observed: true };
+}
+
+/// The quirks the identified server is known for, followed by anything odd
+/// about this particular request.
+pub fn quirks(variables: &[(String, String)], server: Server) -> Vec<Quirk> {
+    let get = |name: &str| lookup(variables, name);
+    let mut output: Vec<Quirk> = Vec::new();
+    match server {
+        Server::Apache => {
+            output.push(known("quirk.authorization",
+                "quirk.authorization.detail"));
+            output.push(known("quirk.encoded_slash_refused",
+                "quirk.encoded_slash_refused.detail"));
+            output.push(known("quirk.redirect_prefix",
+                "quirk.redirect_prefix.detail"));
+        }
+        Server::Nginx => {
+            output.push(known("quirk.fastcgi_param",
+                "quirk.fastcgi_param.detail"));
+            output.push(known("quirk.empty_length",
+                "quirk.empty_length.known"));
+            output.push(known("quirk.document_uri",
+                "quirk.document_uri.detail"));
+        }
+        Server::Lighttpd => {
+            output.push(known("quirk.redirect_status",
+                "quirk.redirect_status.detail"));
+            output.push(known("quirk.root_script",
+                "quirk.root_script.detail"));
+        }
+        Server::Iis => {
+            output.push(known("quirk.path_info_script",
+                "quirk.path_info_script.detail"));
+        }
+        Server::Python => {
+            output.push(known("quirk.no_request_uri",
+                "quirk.no_request_uri.detail"));
+        }
+        Server::Caddy | Server::Busybox | Server::Thttpd | Server::Unknown => {}
+    }
+
+    let script_name = get("SCRIPT_NAME").unwrap_or_default();
+    let path_info = get("PATH_INFO").unwrap_or_default();
+    if after_script_name(path_info, script_name).is_some() {
+        output.push(seen("quirk.path_info_repeats",
+            "quirk.path_info_repeats.detail",
+            &[&format!("{path_info:?}"), &format!("{script_name:?}")]));
+    }
+    if let Some(raw) = get("REQUEST_URI") {
+        let raw_path = raw.split(['?', '#']).next().unwrap_or_default();
+        let decoded = decode_path(raw_path);
+        let expected = format!("{script_name}{path_info}");
+        let after = after_script_name(&decoded, script_name)
+            .filter(|rest| !rest.is_empty());
+        if path_info.is_empty() && let Some(rest) = after {
+            output.push(seen("quirk.path_info_missing",
+                "quirk.path_info_missing.detail", &[&format!("{rest:?}")]));
+        } else if decoded != expected {
+            output.push(seen("quirk.request_uri_mismatch",
+                "quirk.request_uri_mismatch.detail",
+                &[&format!("{decoded:?}"), &format!("{expected:?}")]));
+        }
+        if raw_path.to_ascii_lowercase().contains("%2f") {
+            output.push(seen("quirk.encoded_slash",
+                "quirk.encoded_slash.detail", &[]));
+        }
+    }
+    if get("CONTENT_LENGTH") == Some("") {
+        output.push(seen("quirk.empty_length", "quirk.empty_length.seen",
+            &[]));
+    }
+    let redirects: Vec<&str> = variables.iter()
+        .map(|(name, _)| name.as_str())
+        .filter(|name| name.starts_with("REDIRECT_"))
+        .collect();
+    if !redirects.is_empty() {
+        output.push(seen("quirk.redirects", "quirk.redirects.detail",
+            &[&redirects.join(", ")]));
+    }
+    return output;
+}
+
+/// The request path split the same way whatever the server did to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NormalizedPath {
+    /// Decoded.
+    pub script_name: String,
+    /// Decoded, empty or starting with `/`.
+    pub path_info: String,
+    /// PATH_INFO as the client sent it, when REQUEST_URI makes that
+    /// possible.
+    pub raw_path_info: Option<String>,
+}
+
+impl NormalizedPath {
+    pub fn from_env() -> NormalizedPath {
+        let variables: Vec<(String, String)> = request::variables();
+        return NormalizedPath::from_variables(&variables);
+    }
+
+    pub fn from_variables(variables: &[(String, String)]) -> NormalizedPath {
+        let get = |name: &str| lookup(variables, name).unwrap_or_default();
+        let script_name = String::from(get("SCRIPT_NAME"));
+        let mut path_info = String::from(get("PATH_INFO"));
+        let raw = get("REQUEST_URI");
+        let raw_path = raw.split(['?', '#']).next().unwrap_or_default();
+
+        // IIS style: PATH_INFO is the whole path. A script whose PATH_INFO
+        // really does repeat its name looks the same, so this needs
+        // REQUEST_URI to show the path was not longer than PATH_INFO.
+        if decode_path(raw_path) == path_info
+            && let Some(rest) = after_script_name(&path_info, &script_name) {
+            path_info = String::from(rest);
+        }
+
+        let mut raw_path_info: Option<String> = None;
+        if !raw_path.is_empty() {
+            // Walk the raw path one segment at a time until the decoded
+            // prefix covers SCRIPT_NAME. What is left is the raw PATH_INFO.
+            let script_segments = script_name.split('/')
+                .filter(|s| !s.is_empty())
+                .count();
+            let mut rest = raw_path;
+            let mut taken = 0;
+            while taken < script_segments {
+                rest = rest.trim_start_matches('/');
+                rest = match rest.find('/') {
+                    Some(index) => &rest[index..],
+                    None => "",
+                };
+                taken += 1;
+            }
+            if decode_path(&raw_path[..raw_path.len() - rest.len()])
+                .trim_end_matches('/') == script_name.trim_end_matches('/') {
+                raw_path_info = Some(String::from(rest));
+                // nginx without fastcgi_split_path_info leaves PATH_INFO out.
+                if path_info.is_empty() {
+                    path_info = decode_path(rest);
+                }
+            }
+        }
+        return NormalizedPath { script_name, path_info, raw_path_info };
+    }
+
+    /// The PATH_INFO segments, decoded one by one from the raw path when it
+    /// is known so that an encoded slash stays inside its segment.
+    pub fn segments(&self) -> Vec<String> {
+        let (source, decode) = match &self.raw_path_info {
+            Some(raw) => (raw.as_str(), true),
+            None => (self.path_info.as_str(), false),
+        };
+        return source.split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| if decode { decode_path(s) } else { String::from(s) })
+            .collect();
+    }
+}
+
+/// The `/server` page: what we think the server is, its quirks and the
+/// request path before and after normalizing.
+pub fn report_html(variables: &[(String, String)], tr: &Translator)
+    -> String
+{
+    let identification = identify(variables);
+    let path = NormalizedPath::from_variables(variables);
+    let mut out = String::with_capacity(4096);
+    out.push_str(&format!("<!DOCTYPE html><html lang=\"{}\"><body>",
+        html::escape(tr.language())));
+    out.push_str(&format!("<h1>{}</h1>", html::escape(&tr.format(
+        "server.title", &[&identification.server.describe(tr)]))));
+    if let Some(version) = &identification.version {
+        out.push_str(&format!("<p>{}</p>",
+            html::escape(&tr.format("server.version", &[version]))));
+    }
+    out.push_str(&format!("<h2>{}</h2><ul>",
+        html::escape(tr.get("server.evidence"))));
+    if identification.evidence.is_empty() {
+        out.push_str(&format!("<li>{}</li>",
+            html::escape(tr.get("server.no_evidence"))));
+    }
+    for evidence in &identification.evidence {
+        out.push_str(&format!("<li>{}</li>",
+            html::escape(&evidence.describe(tr))));
+    }
+    out.push_str(&format!("</ul><h2>{}</h2><dl>",
+        html::escape(tr.get("server.quirks"))));
+    for quirk in quirks(variables, identification.server) {
+        let label = tr.get(if quirk.observed {
+            "server.seen"
+        } else {
+            "server.known"
+        });
+        out.push_str(&format!("<dt>{} ({})</dt><dd>{}</dd>",
+            html::escape(&quirk.title(tr)), html::escape(label),
+            html::escape(&quirk.detail(tr))));
+    }
+    out.push_str(&format!("</dl><h2>{}</h2><dl>",
+        html::escape(tr.get("server.path"))));
+    let raw = path.raw_path_info.as_deref()
+        .unwrap_or(tr.get("server.raw_unknown"));
+    for (name, value) in [
+        ("server.script_name", path.script_name.as_str()),
+        ("server.path_info", path.path_info.as_str()),
+        ("server.raw_path_info", raw),
+    ] {
+        out.push_str(&format!("<dt>{}</dt><dd>{}</dd>",
+            html::escape(tr.get(name)), html::escape(value)));
+    }
+    out.push_str(&format!("<dt>{}</dt><dd><ol>",
+        html::escape(tr.get("server.segments"))));
+    for segment in path.segments() {
+        out.push_str(&format!("<li>{}</li>", html::escape(&segment)));
+    }
+    out.push_str("</ol></dd></dl></body></html>");
+    return out;
+}
+
+/// What is left of `path` after `script_name`, if `path` is `script_name`
+/// or carries on from it with a `/`. `/s` is not the start of `/server`.
+fn after_script_name<'a>(path: &'a str, script_name: &str) -> Option<&'a str> {
+    let script_name = script_name.trim_end_matches('/');
+    if script_name.is_empty() {
+        return None;
+    }
+    let rest = path.strip_prefix(script_name)?;
+    if rest.is_empty() || rest.starts_with('/') {
+        return Some(rest);
+    }
+    return None;
+}
+
+/// Percent decodes a path. Invalid escapes are left as they are, which is
+/// what servers do too.
+pub fn decode_path(raw: &str) -> String {
+    return match decode_percent_bytes(raw) {
+        Some(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        None => String::from(raw),
+    };
+}
+
+fn lookup<'a>(variables: &'a [(String, String)], name: &str)
+    -> Option<&'a str>
+{
+    return variables.iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_str());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::Catalogs;
+
+    fn variables(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        return pairs.iter()
+            .map(|(n, v)| (String::from(*n), String::from(*v)))
+            .collect();
+    }
+
+    #[test]
+    fn identifies_by_software_and_fingerprint() {
+        let found = identify(&variables(&[
+            ("SERVER_SOFTWARE", "Apache/2.4.62 (Debian)"),
+            ("UNIQUE_ID", "abc"),
+        ]));
+        assert_eq!(found.server, Server::Apache);
+        assert_eq!(found.version.as_deref(), Some("2.4.62"));
+        assert_eq!(found.evidence, [
+            Evidence::Software(String::from("Apache/2.4.62 (Debian)")),
+            Evidence::Fingerprint("UNIQUE_ID", Server::Apache),
+        ]);
+
+        let hidden = identify(&variables(&[("DOCUMENT_URI", "/x")]));
+        assert_eq!(hidden.server, Server::Nginx);
+        assert_eq!(hidden.version, None);
+        assert_eq!(identify(&[]).server, Server::Unknown);
+    }
+
+    #[test]
+    fn quirks_of_this_request() {
+        let found = quirks(&variables(&[
+            ("SCRIPT_NAME", "/cgi-bin/t"),
+            ("PATH_INFO", ""),
+            ("REQUEST_URI", "/cgi-bin/t/a%2Fb?q"),
+            ("CONTENT_LENGTH", ""),
+        ]), Server::Unknown);
+        let titles: Vec<&str> = found.iter().map(|q| q.title).collect();
+        assert_eq!(titles, ["quirk.path_info_missing", "quirk.encoded_slash",
+            "quirk.empty_length"]);
+        assert!(found.iter().all(|q| q.observed));
+        assert_eq!(found[0].detail(&catalog::english()),
+            "REQUEST_URI has \"/a/b\" after the script but PATH_INFO is \
+            empty.");
+
+        let known = quirks(&[], Server::Iis);
+        assert_eq!(known.len(), 1);
+        assert!(!known[0].observed);
+    }
+
+    #[test]
+    fn normalizes_iis_path_info() {
+        let path = NormalizedPath::from_variables(&variables(&[
+            ("SCRIPT_NAME", "/t.cgi"),
+            ("PATH_INFO", "/t.cgi/a/b"),
+            ("REQUEST_URI", "/t.cgi/a/b"),
+        ]));
+        assert_eq!(path.path_info, "/a/b");
+        assert_eq!(path.raw_path_info.as_deref(), Some("/a/b"));
+    }
+
+    #[test]
+    fn segments_keep_encoded_slashes() {
+        let path = NormalizedPath::from_variables(&variables(&[
+            ("SCRIPT_NAME", "/cgi-bin/t"),
+            ("REQUEST_URI", "/cgi-bin/t/a%2Fb/c"),
+        ]));
+        assert_eq!(path.path_info, "/a/b/c");
+        assert_eq!(path.segments(), ["a/b", "c"]);
+    }
+
+    #[test]
+    fn decodes_paths() {
+        assert_eq!(decode_path("/a%20b"), "/a b");
+        assert_eq!(decode_path("/100%"), "/100%");
+    }
+
+    #[test]
+    fn report_is_translated() {
+        let catalogs = Catalogs::built_in();
+        let page = report_html(&[], &catalogs.translator("de"));
+        assert!(page.contains("<html lang=\"de\">"));
+        assert!(page.contains("Server: ein unbekannter Server"));
+        assert!(page.contains("Nichts verrät den Server."));
+    }
+}
@@ -0,0 +1,555 @@
+// src/session.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! File-backed sessions for CGI programs.
+//!
+//! Every CGI request is a fresh process, so session data lives in a
+//! directory with one file per session. The browser only gets the session id
+//! in a cookie, signed with HMAC-SHA256 so a client cannot guess or forge
+//! somebody else's id. While a request has a session open the file is held
+//! with an exclusive `flock`, which makes two requests for the same session
+//! take turns instead of overwriting each other.
+//!
+//! ```ignore
+//! let store = SessionStore::new("/var/lib/testcgi/sessions", secret);
+//! let mut session = store.open(&env::var("HTTP_COOKIE").unwrap_or_default())?;
+//! session.set("user", "robert");
+//! session.save()?;
+//! response.header("Set-Cookie", &session.cookie().to_string());
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::DirBuilder;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::fs::TryLockError;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use crate::cookie::SameSite;
+use crate::cookie::SetCookie;
+use crate::cookie::is_token_char;
+use crate::cookie::parse_cookie_header;
+use crate::decode_urlencoded::decode_percent_bytes;
+use crate::encode_urlencoded::encode_urlencoded;
+use crate::hex;
+use crate::hmac::constant_time_eq;
+use crate::hmac::hmac;
+use crate::http_date::format_http_date;
+use crate::http_date::unix_seconds;
+use crate::random;
+use crate::response::Response;
+use crate::sha256::Sha256;
+use crate::url::Url;
+
+pub const DEFAULT_COOKIE_NAME: &str = "testcgi_session";
+
+pub const DEFAULT_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// Roughly one request in this many sweeps the directory for expired
+/// sessions, the same trick PHP uses since there is no background process.
+const GC_ONE_IN: u32 = 100;
+
+const FILE_PREFIX: &str = "sess_";
+
+/// New session files are written under this prefix and linked into place
+/// once they hold an expiry time, so collect_garbage never sees them empty.
+const NEW_FILE_PREFIX: &str = "new_";
+
+/// The first line of every session file.
+const FILE_MAGIC: &str = "testcgi-session 1";
+
+/// 128 bits of /dev/urandom per session id.
+const ID_BYTES: usize = 16;
+
+pub struct SessionStore {
+    directory: PathBuf,
+    secret: Vec<u8>,
+    lifetime: Duration,
+    cookie_name: String,
+    cookie_path: String,
+}
+
+impl SessionStore {
+    /// `secret` signs the session id cookie. Changing it logs everybody out.
+    pub fn new(directory: impl Into<PathBuf>, secret: &[u8]) -> SessionStore {
+        return SessionStore {
+            directory: directory.into(),
+            secret: secret.to_vec(),
+            lifetime: DEFAULT_LIFETIME,
+            cookie_name: String::from(DEFAULT_COOKIE_NAME),
+            cookie_path: String::from("/"),
+        };
+    }
+
+    /// How long a session lives after it was last saved.
+    pub fn lifetime(mut self, lifetime: Duration) -> SessionStore {
+        self.lifetime = lifetime;
+        return self;
+    }
+
+    /// Fails if the name is not a cookie token, see cookie::is_token_char.
+    pub fn cookie_name(mut self, name: &str)
+        -> Result<SessionStore, &'static str>
+    {
+        if name.is_empty() || !name.chars().all(is_token_char) {
+            return Err("A cookie name must be a non-empty token.");
+        }
+        self.cookie_name = String::from(name);
+        return Ok(self);
+    }
+
+    pub fn cookie_path(mut self, path: &str) -> SessionStore {
+        self.cookie_path = String::from(path);
+        return self;
+    }
+
+    /// Opens the session named in `cookie_header`, the value of HTTP_COOKIE,
+    /// or starts a new one if there is no valid, unexpired session cookie.
+    ///
+    /// The returned session holds the lock on its file until it is dropped.
+    pub fn open(&self, cookie_header: &str) -> io::Result<Session> {
+        DirBuilder::new().recursive(true).mode(0o700)
+            .create(&self.directory)?;
+        for (name, value) in parse_cookie_header(cookie_header) {
+            if name != self.cookie_name {
+                continue;
+            }
+            let id = match self.verify(&value) {
+                Some(id) => id,
+                None => continue,
+            };
+            match self.load(&id)? {
+                Some(session) => return Ok(session),
+                None => continue,
+            }
+        }
+        return self.create();
+    }
+
+    /// Removes every expired session file that is not in use right now.
+    /// Returns how many were removed.
+    pub fn collect_garbage(&self) -> io::Result<usize> {
+        let now = unix_seconds(SystemTime::now());
+        let mut removed: usize = 0;
+        let entries = match fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            if !name.to_string_lossy().starts_with(FILE_PREFIX) {
+                continue;
+            }
+            let mut file = match File::open(entry.path()) {
+                Ok(file) => file,
+                Err(_) => continue,
+            };
+            match file.try_lock() {
+                Ok(()) => {}
+                // Somebody is using it, so it is not abandoned.
+                Err(TryLockError::WouldBlock) => continue,
+                Err(TryLockError::Error(e)) => return Err(e),
+            }
+            let mut contents = String::new();
+            if file.read_to_string(&mut contents).is_err() {
+                continue;
+            }
+            let expired = match parse_file(&contents) {
+                Some((expires, _)) => expires <= now,
+                // Not something we can read, so nothing can use it either.
+                None => true,
+            };
+            if expired {
+                fs::remove_file(entry.path())?;
+                removed += 1;
+            }
+        }
+        return Ok(removed);
+    }
+
+    /// Runs collect_garbage on about one call in a hundred.
+    pub fn maybe_collect_garbage(&self) -> io::Result<usize> {
+        let mut roll = [0u8; 4];
+        random::fill(&mut roll)?;
+        if u32::from_le_bytes(roll) % GC_ONE_IN != 0 {
+            return Ok(0);
+        }
+        return self.collect_garbage();
+    }
+
+    fn sign(&self, id: &str) -> String {
+        let mac = hmac::<Sha256>(&self.secret, id.as_bytes());
+        return format!("{id}.{}", hex::encode(&mac));
+    }
+
+    /// The session id from a cookie value, if the signature checks out.
+    fn verify(&self, cookie_value: &str) -> Option<String> {
+        let (id, signature) = cookie_value.split_once('.')?;
+        if id.len() != ID_BYTES * 2
+            || !id.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let signature = hex::decode(signature)?;
+        let expected = hmac::<Sha256>(&self.secret, id.as_bytes());
+        if !constant_time_eq(&signature, &expected) {
+            return None;
+        }
+        return Some(id.to_ascii_lowercase());
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        return self.directory.join(format!("{FILE_PREFIX}{id}"));
+    }
+
+    fn load(&self, id: &str) -> io::Result<Option<Session>> {
+        let path = self.path(id);
+        let mut file = match OpenOptions::new().read(true).write(true)
+            .open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        file.lock()?;
+
+        // Garbage collection may have removed the file between our open and
+        // our lock, in which case we hold a lock on a file nobody can find.
+        let still_there = match fs::metadata(&path) {
+            Ok(metadata) => metadata.ino() == file.metadata()?.ino(),
+            Err(_) => false,
+        };
+        if !still_there {
+            return Ok(None);
+        }
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        let (expires, data) = match parse_file(&contents) {
+            Some(parsed) => parsed,
+            None => return Ok(None),
+        };
+        if expires <= unix_seconds(SystemTime::now()) {
+            fs::remove_file(&path)?;
+            return Ok(None);
+        }
+        return Ok(Some(self.session(String::from(id), file, data, expires,
+            false)));
+    }
+
+    fn create(&self) -> io::Result<Session> {
+        loop {
+            let id = hex::encode(&random::bytes(ID_BYTES)?);
+            let new_path = self.directory
+                .join(format!("{NEW_FILE_PREFIX}{id}"));
+            let file = match OpenOptions::new().read(true).write(true)
+                .create_new(true).mode(0o600).open(&new_path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            };
+            file.lock()?;
+            let mut session = self.session(id, file, BTreeMap::new(), 0, true);
+            // hard_link, unlike rename, will not replace a session that
+            // already has this id.
+            let path = self.path(&session.id);
+            let linked = session.save()
+                .and_then(|()| fs::hard_link(&new_path, path));
+            fs::remove_file(&new_path)?;
+            match linked {
+                Ok(()) => return Ok(session),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn session(&self, id: String, file: File, data: BTreeMap<String, String>,
+        expires: i64, is_new: bool) -> Session
+    {
+        return Session {
+            cookie_value: self.sign(&id),
+            id,
+            file,
+            data,
+            expires,
+            is_new,
+            lifetime: self.lifetime,
+            cookie_name: self.cookie_name.clone(),
+            cookie_path: self.cookie_path.clone(),
+        };
+    }
+}
+
+/// An open session. Changes only reach the disk when save is called.
+pub struct Session {
+    id: String,
+    cookie_value: String,
+    file: File,
+    data: BTreeMap<String, String>,
+    /// Unix seconds.
+    expires: i64,
+    is_new: bool,
+    lifetime: Duration,
+    cookie_name: String,
+    cookie_path: String,
+}
+
+impl Session {
+    pub fn id(&self) -> &str {
+        return &self.id;
+    }
+
+    /// True if this request started the session, meaning the browser does
+    /// not have the cookie yet.
+    pub fn is_new(&self) -> bool {
+        return self.is_new;
+    }
+
+    pub fn expires(&self) -> SystemTime {
+        return UNIX_EPOCH + Duration::from_secs(self.expires.max(0) as u64);
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        return self.data.get(key).map(|v| v.as_str());
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.data.insert(String::from(key), String::from(value));
+    }
+
+    pub fn delete(&mut self, key: &str) -> Option<String> {
+        return self.data.remove(key);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        return self.data.iter();
+    }
+
+    /// Writes the data out and pushes the expiry a full lifetime into the
+    /// future.
+    pub fn save(&mut self) -> io::Result<()> {
+        self.expires = unix_seconds(SystemTime::now())
+            + self.lifetime.as_secs() as i64;
+        let mut contents = format!("{FILE_MAGIC}\nexpires {}\n", self.expires);
+        for (key, value) in &self.data {
+            contents.push_str(&encode_urlencoded(key));
+            contents.push('=');
+            contents.push_str(&encode_urlencoded(value));
+            contents.push('\n');
+        }
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.set_len(0)?;
+        self.file.write_all(contents.as_bytes())?;
+        self.file.sync_data()?;
+        return Ok(());
+    }
+
+    /// Removes the session file. Send `destroy_cookie` to the browser too.
+    pub fn destroy(self, store: &SessionStore) -> io::Result<()> {
+        match fs::remove_file(store.path(&self.id)) {
+            Ok(()) => return Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        }
+    }
+
+    /// The signed session id cookie. It is HttpOnly and SameSite=Lax; chain
+    /// `.secure(true)` onto it when the request came in over HTTPS.
+    pub fn cookie(&self) -> SetCookie {
+        return SetCookie::new(&self.cookie_name, &self.cookie_value)
+            .expect("cookie_name checked the name and sign() writes hex")
+            .path(&self.cookie_path)
+            .max_age(self.lifetime.as_secs() as i64)
+            .http_only(true)
+            .same_site(SameSite::Lax);
+    }
+
+    /// A cookie that removes the session cookie from the browser.
+    pub fn destroy_cookie(&self) -> SetCookie {
+        return SetCookie::delete(&self.cookie_name)
+            .expect("cookie_name checked the name")
+            .path(&self.cookie_path);
+    }
+}
+
+/// The `/session` routes, for poking at the store from a browser.
+///
+/// - `/session?key=value&...` stores each parameter.
+/// - `/session/delete?key&...` removes the named keys.
+/// - `/session/destroy` throws the whole session away.
+///
+/// Every visit also bumps a `visits` counter, and the page lists what the
+/// session holds afterwards.
+pub fn demo_route(store: &SessionStore, cookie_header: &str,
+    set: &[(String, String)], delete: &[String], destroy: bool)
+    -> io::Result<Response>
+{
+    store.maybe_collect_garbage()?;
+    let mut session = store.open(cookie_header)?;
+    let mut response = Response::new(200);
+    response.header("Content-Type", "text/plain; charset=utf-8");
+    response.header("Cache-Control", "no-store");
+
+    if destroy {
+        response.header("Set-Cookie", &session.destroy_cookie().to_string());
+        response.body.extend_from_slice(
+            format!("Session {} destroyed.\n", session.id()).as_bytes());
+        session.destroy(store)?;
+        return Ok(response);
+    }
+
+    for (key, value) in set {
+        session.set(key, value);
+    }
+    for key in delete {
+        session.delete(key);
+    }
+    let visits: u64 = session.get("visits")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    session.set("visits", &(visits + 1).to_string());
+    session.save()?;
+
+    let https = Url::from_env().scheme == "https";
+    response.header("Set-Cookie",
+        &session.cookie().secure(https).to_string());
+    let mut body = format!("Session {}{}\n", session.id(),
+        if session.is_new() { " (new)" } else { "" });
+    body.push_str(&format!("Expires {}\n\n",
+        format_http_date(session.expires())));
+    for (key, value) in session.iter() {
+        body.push_str(&format!("{key} = {value}\n"));
+    }
+    response.body.extend_from_slice(body.as_bytes());
+    return Ok(response);
+}
+
+/// Returns the expiry time and the key/value pairs from a session file.
+fn parse_file(contents: &str) -> Option<(i64, BTreeMap<String, String>)> {
+    let mut lines = contents.lines();
+    if lines.next()? != FILE_MAGIC {
+        return None;
+    }
+    let expires: i64 = lines.next()?.strip_prefix("expires ")?.parse().ok()?;
+    let mut data: BTreeMap<String, String> = BTreeMap::new();
+    for line in lines {
+        let (key, value) = line.split_once('=')?;
+        let key = String::from_utf8(decode_percent_bytes(key)?).ok()?;
+        let value = String::from_utf8(decode_percent_bytes(value)?).ok()?;
+        data.insert(key, value);
+    }
+    return Some((expires, data));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(name: &str) -> SessionStore {
+        let directory = std::env::temp_dir().join(format!(
+            "testcgi-session-{}-{name}", std::process::id()));
+        return SessionStore::new(directory, b"secret");
+    }
+
+    /// The `name=value` part of a Set-Cookie value, as a Cookie header.
+    fn cookie_header(session: &Session) -> String {
+        let set_cookie = session.cookie().to_string();
+        return String::from(set_cookie.split(';').next().unwrap());
+    }
+
+    #[test]
+    fn round_trip() {
+        let store = store("round-trip");
+        let mut session = store.open("").unwrap();
+        assert!(session.is_new());
+        session.set("colour", "blue; green=");
+        session.save().unwrap();
+        let header = cookie_header(&session);
+        let id = String::from(session.id());
+        drop(session);
+
+        let session = store.open(&format!("other=1; {header}")).unwrap();
+        assert!(!session.is_new());
+        assert_eq!(session.id(), id);
+        assert_eq!(session.get("colour"), Some("blue; green="));
+        drop(session);
+
+        let tampered = header.replace(&id[..4], "ffff");
+        let session = store.open(&tampered).unwrap();
+        assert!(session.is_new());
+        assert_ne!(session.id(), id);
+        drop(session);
+        _ = fs::remove_dir_all(&store.directory);
+    }
+
+    #[test]
+    fn garbage_collection() {
+        let store = store("gc");
+        let session = store.open("").unwrap();
+        // A new session already has an expiry on disk and is locked.
+        assert_eq!(store.collect_garbage().unwrap(), 0);
+        drop(session);
+        assert_eq!(store.collect_garbage().unwrap(), 0);
+        let names: Vec<String> = fs::read_dir(&store.directory).unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert_eq!(names.len(), 1);
+        assert!(names[0].starts_with(FILE_PREFIX));
+
+        let expired = store.lifetime(Duration::ZERO);
+        let session = expired.open("").unwrap();
+        assert_eq!(expired.collect_garbage().unwrap(), 0);
+        drop(session);
+        // Only the new one; the first session has not expired.
+        assert_eq!(expired.collect_garbage().unwrap(), 1);
+        _ = fs::remove_dir_all(&expired.directory);
+    }
+
+    #[test]
+    fn cookie() {
+        assert!(store("cookie").cookie_name("a b").is_err());
+        assert!(store("cookie").cookie_name("").is_err());
+        let store = store("cookie").cookie_name("sid").unwrap();
+        let session = store.open("").unwrap();
+        let cookie = session.cookie().to_string();
+        assert!(cookie.starts_with(&format!("sid={}.", session.id())));
+        assert!(cookie.contains("HttpOnly"));
+        assert!(cookie.contains("SameSite=Lax"));
+        drop(session);
+        _ = fs::remove_dir_all(&store.directory);
+    }
+
+    #[test]
+    fn file_format() {
+        assert_eq!(parse_file("testcgi-session 1\nexpires 5\na%3D=b%0A\n"),
+            Some((5, BTreeMap::from([(String::from("a="),
+                String::from("b\n"))]))));
+        assert_eq!(parse_file(""), None);
+        assert_eq!(parse_file("testcgi-session 1\nexpires x\n"), None);
+    }
+}
@@ -0,0 +1,213 @@
+// src/slow.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Deliberately slow endpoints for checking proxy and gateway timeouts.
+//!
+//! - `/delay/{seconds}` sleeps and then answers.
+//! - `/drip?numbytes=&duration=&delay=` waits `delay` seconds, sends the
+//!   headers and then trickles `numbytes` bytes out over `duration` seconds.
+//! - `/slow-body?rate=` reads the request body at no more than `rate` bytes
+//!   per second.
+
+use std::collections::HashMap;
+use std::env;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::request_id;
+use crate::response::Response;
+use crate::route::parse_seconds;
+
+/// Nobody should need a CGI process to hang around longer than this.
+pub const MAX_DELAY: Duration = Duration::from_secs(600);
+
+/// The most bytes `/drip` will send.
+pub const MAX_DRIP_BYTES: u64 = 10 * 1024 * 1024;
+
+/// The `/slow-body` read rate in bytes per second when none is given.
+pub const DEFAULT_READ_RATE: u64 = 1024;
+
+/// The fastest `/slow-body` read rate. Anything higher is not slow.
+pub const MAX_READ_RATE: u64 = 10 * 1024 * 1024;
+
+/// How often `/slow-body` wakes up to read more of the body.
+const READ_TICK: Duration = Duration::from_millis(100);
+
+/// The most `/slow-body` reads at once, whatever the rate.
+const MAX_READ_BUFFER: u64 = 64 * 1024;
+
+pub fn delay(requested: Duration) -> Response {
+    let started = Instant::now();
+    thread::sleep(requested.min(MAX_DELAY));
+    return Response::text(200, &format!("Slept for {:.3} seconds.",
+        started.elapsed().as_secs_f64()));
+}
+
+pub struct DripParameters {
+    pub numbytes: u64,
+    pub duration: Duration,
+    pub delay: Duration,
+}
+
+impl DripParameters {
+    /// Reads numbytes, duration and delay out of the decoded query string.
+    /// Missing values default to 10 bytes over 2 seconds with no delay.
+    pub fn from_query(query: &HashMap<String, String>)
+        -> Result<DripParameters, &'static str>
+    {
+        let numbytes: u64 = match query.get("numbytes") {
+            None => 10,
+            Some(val) => match val.trim().parse() {
+                Ok(n) if n <= MAX_DRIP_BYTES => n,
+                _ => {
+                    return Err("numbytes must be a whole number no larger \
+                        than 10485760.");
+                }
+            },
+        };
+        let duration: Duration = match query.get("duration") {
+            None => Duration::from_secs(2),
+            Some(val) => match parse_seconds(val) {
+                Some(d) => d.min(MAX_DELAY),
+                None => {
+                    return Err("duration must be a number of seconds.");
+                }
+            },
+        };
+        let delay: Duration = match query.get("delay") {
+            None => Duration::ZERO,
+            Some(val) => match parse_seconds(val) {
+                Some(d) => d.min(MAX_DELAY),
+                None => {
+                    return Err("delay must be a number of seconds.");
+                }
+            },
+        };
+        return Ok(DripParameters { numbytes, duration, delay });
+    }
+}
+
+/// Streams the drip response straight to `out`.
+///
+/// This cannot go through a buffered Response because the whole point is for
+/// the web server to see each byte as it is written.
+pub fn drip(parameters: &DripParameters, out: &mut impl Write)
+    -> io::Result<()>
+{
+    thread::sleep(parameters.delay);
+
+    let mut response = Response::new(200);
+    response.header("Content-Type", "application/octet-stream");
+    response.header("Content-Length", &parameters.numbytes.to_string());
+    response.header("X-Request-Id", &request_id::current().id);
+    response.write_head(out)?;
+    out.flush()?;
+
+    if parameters.numbytes == 0 {
+        return Ok(());
+    }
+    let pause = parameters.duration / parameters.numbytes.max(1) as u32;
+    for i in 0..parameters.numbytes {
+        out.write_all(b"*")?;
+        out.flush()?;
+        if i + 1 < parameters.numbytes {
+            thread::sleep(pause);
+        }
+    }
+    return Ok(());
+}
+
+/// Reads up to CONTENT_LENGTH bytes from stdin without going faster than
+/// `rate` bytes per second, then reports how long it took. The rate is held
+/// between 1 and MAX_READ_RATE.
+pub fn slow_body(rate: u64) -> io::Result<Response> {
+    let rate = rate.clamp(1, MAX_READ_RATE);
+    let content_length: u64 = env::var("CONTENT_LENGTH")
+        .ok()
+        .and_then(|val| val.trim().parse().ok())
+        .unwrap_or(0);
+
+    let per_tick = (rate.saturating_mul(READ_TICK.as_millis() as u64) / 1000)
+        .clamp(1, MAX_READ_BUFFER);
+    let mut buffer = vec![0u8; per_tick as usize];
+    let mut stdin = io::stdin().lock().take(content_length);
+    let started = Instant::now();
+    let mut total: u64 = 0;
+    loop {
+        let count = stdin.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        total += count as u64;
+        // Sleep until the average rate is back under the cap.
+        let due = Duration::from_secs_f64(total as f64 / rate as f64);
+        let elapsed = started.elapsed();
+        if due > elapsed {
+            thread::sleep(due - elapsed);
+        }
+    }
+
+    let elapsed = started.elapsed().as_secs_f64();
+    return Ok(Response::text(200, &format!(
+        "Read {total} of {content_length} bytes in {elapsed:.3} seconds \
+        at no more than {rate} bytes per second.")));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        return pairs.iter()
+            .map(|(n, v)| (String::from(*n), String::from(*v)))
+            .collect();
+    }
+
+    #[test]
+    fn reads_drip_parameters() {
+        let defaults = DripParameters::from_query(&query(&[])).unwrap();
+        assert_eq!(defaults.numbytes, 10);
+        assert_eq!(defaults.duration, Duration::from_secs(2));
+        assert_eq!(defaults.delay, Duration::ZERO);
+        let capped = DripParameters::from_query(&query(&[
+            ("duration", "100000"), ("delay", "0.5")])).unwrap();
+        assert_eq!(capped.duration, MAX_DELAY);
+        assert_eq!(capped.delay, Duration::from_millis(500));
+        assert!(DripParameters::from_query(&query(&[
+            ("numbytes", "10485761")])).is_err());
+        assert!(DripParameters::from_query(&query(&[
+            ("duration", "-1")])).is_err());
+        assert!(DripParameters::from_query(&query(&[
+            ("delay", "x")])).is_err());
+    }
+
+    #[test]
+    fn drips_every_byte() {
+        let parameters = DripParameters {
+            numbytes: 3,
+            duration: Duration::ZERO,
+            delay: Duration::ZERO,
+        };
+        let mut out: Vec<u8> = Vec::new();
+        drip(&parameters, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("Content-Length: 3\r\n"));
+        assert!(text.ends_with("\r\n\r\n***"));
+    }
+}
@@ -0,0 +1,306 @@
+// src/url.rs
+// Copyright (C) 2025 Robert R. Russell
+//
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, version 3.
+//
+// This program is distributed in the hope that it will be usefull, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE. See the GNU General Public License for more
+// details.
+//
+// You should have received a copy of the GNU General Public License along with
+// this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! The URL a CGI request was made to, put back together from the
+//! meta-variables.
+//!
+//! RFC 3875 scatters it over SERVER_NAME, SERVER_PORT, SCRIPT_NAME,
+//! PATH_INFO and QUERY_STRING, with HTTPS and REQUEST_URI as widely used
+//! extras. SCRIPT_NAME and PATH_INFO arrive percent decoded, so they are
+//! encoded again here; QUERY_STRING arrives as the client sent it and is
+//! used unchanged.
+
+use std::env;
+use std::fmt;
+
+use crate::forwarded::Resolved;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Url {
+    /// `http` or `https`.
+    pub scheme: String,
+    /// A host name or IP address. IPv6 literals are kept without brackets.
+    pub host: String,
+    /// None when the port is the default for the scheme.
+    pub port: Option<u16>,
+    /// Decoded, as the server gave it.
+    pub script_name: String,
+    /// Decoded, as the server gave it.
+    pub path_info: String,
+    /// Undecoded, without the `?`. None when QUERY_STRING is empty.
+    pub query: Option<String>,
+    /// The request target from REQUEST_URI, when the server sets it. It
+    /// keeps the client's own percent encoding.
+    pub request_uri: Option<String>,
+}
+
+impl Url {
+    pub fn from_env() -> Url {
+        return Url::from_lookup(|name| env::var(name).ok());
+    }
+
+    /// Builds the URL from any source of meta-variables, such as a recorded
+    /// request.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Url {
+        let get = |name: &str| lookup(name).filter(|v| !v.is_empty());
+
+        let https = get("HTTPS")
+            .map(|v| v.eq_ignore_ascii_case("on") || v == "1")
+            .unwrap_or(false);
+        let scheme = if https {
+            String::from("https")
+        } else {
+            match get("REQUEST_SCHEME") {
+                Some(scheme) => scheme.to_ascii_lowercase(),
+                None => String::from("http"),
+            }
+        };
+
+        // SERVER_NAME is required by RFC 3875, but fall back to the Host
+        // header rather than produce a URL with no host.
+        let host = match get("SERVER_NAME") {
+            Some(name) => String::from(name.trim_matches(['[', ']'])),
+            None => match get("HTTP_HOST") {
+                Some(host) => String::from(split_host_port(&host).0),
+                None => String::from("localhost"),
+            },
+        };
+
+        let port: Option<u16> = get("SERVER_PORT")
+            .and_then(|p| p.trim().parse().ok())
+            .filter(|p| Some(*p) != default_port(&scheme));
+
+        return Url {
+            scheme,
+            host,
+            port,
+            script_name: get("SCRIPT_NAME").unwrap_or_default(),
+            path_info: get("PATH_INFO").unwrap_or_default(),
+            query: get("QUERY_STRING"),
+            request_uri: get("REQUEST_URI"),
+        };
+    }
+
+    /// The URL as the client saw it, using the scheme and Host a trusted
+    /// proxy reported instead of the ones the proxy used to reach us.
+    pub fn behind_proxy(mut self, resolved: &Resolved) -> Url {
+        if let Some(scheme) = &resolved.scheme {
+            self.scheme = scheme.to_ascii_lowercase();
+            self.port = None;
+        }
+        if let Some(host) = &resolved.host {
+            let (name, port) = split_host_port(host);
+            self.host = String::from(name);
+            self.port = port
+                .and_then(|p| p.parse().ok())
+                .filter(|p| Some(*p) != default_port(&self.scheme));
+        }
+        return self;
+    }
+
+    /// `scheme://host[:port]` with IPv6 hosts in brackets.
+    pub fn origin(&self) -> String {
+        let mut output = format!("{}://", self.scheme);
+        if self.host.contains(':') {
+            output.push('[');
+            output.push_str(&self.host);
+            output.push(']');
+        } else {
+            output.push_str(&self.host);
+        }
+        if let Some(port) = self.port {
+            output.push_str(&format!(":{port}"));
+        }
+        return output;
+    }
+
+    /// The encoded SCRIPT_NAME followed by PATH_INFO.
+    pub fn path(&self) -> String {
+        return format!("{}{}", encode_path(&self.script_name),
+            encode_path(&self.path_info));
+    }
+
+    /// The path and query as they would appear on the request line. This is
+    /// REQUEST_URI when the server provides it.
+    pub fn request_target(&self) -> String {
+        if let Some(uri) = &self.request_uri {
+            return uri.clone();
+        }
+        let mut path = self.path();
+        if path.is_empty() {
+            path.push('/');
+        }
+        return match &self.query {
+            Some(query) => format!("{path}?{query}"),
+            None => path,
+        };
+    }
+
+    /// The absolute URL of the script itself, without PATH_INFO or query.
+    pub fn script_url(&self) -> String {
+        return format!("{}{}", self.origin(), encode_path(&self.script_name));
+    }
+
+    /// An absolute URL for `path_info` under this script, e.g.
+    /// `url.for_path_info("/cookies/set", Some("a=b"))`. The path is encoded,
+    /// the query is used as given.
+    pub fn for_path_info(&self, path_info: &str, query: Option<&str>)
+        -> String
+    {
+        let mut output = format!("{}{}", self.script_url(),
+            encode_path(path_info));
+        if let Some(query) = query {
+            output.push('?');
+            output.push_str(query);
+        }
+        return output;
+    }
+
+    /// Resolves a link the way a page served by the script would want:
+    ///
+    /// - `https://other/x` and `//other/x` stay absolute.
+    /// - `/x` is relative to the server root.
+    /// - `?a=b` replaces the query on the current path.
+    /// - anything else is taken as under the script, so `cookies/set`
+    ///   becomes `{script_url}/cookies/set`.
+    pub fn join(&self, relative: &str) -> String {
+        if let Some(rest) = relative.strip_prefix("//") {
+            return format!("{}://{rest}", self.scheme);
+        }
+        let first_delimiter = relative.find(['/', '?', '#']);
+        if let Some(colon) = relative.find(':')
+            && first_delimiter.is_none_or(|d| colon < d) {
+            return String::from(relative);
+        }
+        if relative.starts_with('/') {
+            return format!("{}{relative}", self.origin());
+        }
+        if relative.starts_with('?') || relative.starts_with('#') {
+            return format!("{}{}{relative}", self.origin(), self.path());
+        }
+        return format!("{}/{relative}", self.script_url());
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}{}", self.origin(), self.request_target());
+    }
+}
+
+pub fn default_port(scheme: &str) -> Option<u16> {
+    return match scheme {
+        "http" => Some(80),
+        "https" => Some(443),
+        _ => None,
+    };
+}
+
+/// Splits `host:port`, allowing for `[v6::address]:port`. The host comes
+/// back without brackets.
+pub fn split_host_port(authority: &str) -> (&str, Option<&str>) {
+    if let Some(rest) = authority.strip_prefix('[') {
+        return match rest.split_once(']') {
+            Some((host, tail)) => (host, tail.strip_prefix(':')),
+            None => (rest, None),
+        };
+    }
+    // More than one colon without brackets is a bare IPv6 address.
+    if authority.matches(':').count() > 1 {
+        return (authority, None);
+    }
+    return match authority.rsplit_once(':') {
+        Some((host, port)) => (host, Some(port)),
+        None => (authority, None),
+    };
+}
+
+/// Percent encodes a decoded path, leaving `/` and the other characters
+/// RFC 3986 allows in a path segment alone.
+pub fn encode_path(path: &str) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+    let mut output = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@/".contains(&byte)
+        {
+            output.push(byte as char);
+        } else {
+            output.push('%');
+            output.push(DIGITS[(byte >> 4) as usize] as char);
+            output.push(DIGITS[(byte & 0x0f) as usize] as char);
+        }
+    }
+    return output;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(pairs: &[(&str, &str)]) -> Url {
+        return Url::from_lookup(|name| pairs.iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| String::from(*v)));
+    }
+
+    #[test]
+    fn rebuilds_the_url() {
+        let url = url(&[
+            ("HTTPS", "on"),
+            ("SERVER_NAME", "example.com"),
+            ("SERVER_PORT", "443"),
+            ("SCRIPT_NAME", "/cgi-bin/a b"),
+            ("PATH_INFO", "/x/ü"),
+            ("QUERY_STRING", "q=1%202"),
+        ]);
+        assert_eq!(url.port, None);
+        assert_eq!(url.to_string(),
+            "https://example.com/cgi-bin/a%20b/x/%C3%BC?q=1%202");
+        assert_eq!(url.script_url(), "https://example.com/cgi-bin/a%20b");
+    }
+
+    #[test]
+    fn keeps_odd_ports_and_ipv6_hosts() {
+        let url = url(&[("SERVER_NAME", "[::1]"), ("SERVER_PORT", "8080"),
+            ("REQUEST_URI", "/raw%2Fpath")]);
+        assert_eq!(url.origin(), "http://[::1]:8080");
+        assert_eq!(url.to_string(), "http://[::1]:8080/raw%2Fpath");
+        assert_eq!(Url::from_lookup(|_| None).to_string(),
+            "http://localhost/");
+    }
+
+    #[test]
+    fn joins_links() {
+        let url = url(&[("SERVER_NAME", "h"), ("SCRIPT_NAME", "/s"),
+            ("PATH_INFO", "/p")]);
+        assert_eq!(url.join("https://o/x"), "https://o/x");
+        assert_eq!(url.join("//o/x"), "http://o/x");
+        assert_eq!(url.join("/x"), "http://h/x");
+        assert_eq!(url.join("?a=b"), "http://h/s/p?a=b");
+        assert_eq!(url.join("cookies/set"), "http://h/s/cookies/set");
+        assert_eq!(url.join("a/b:c"), "http://h/s/a/b:c");
+        assert_eq!(url.for_path_info("/a b", Some("x=1")),
+            "http://h/s/a%20b?x=1");
+    }
+
+    #[test]
+    fn splits_host_and_port() {
+        assert_eq!(split_host_port("h:80"), ("h", Some("80")));
+        assert_eq!(split_host_port("h"), ("h", None));
+        assert_eq!(split_host_port("[::1]:80"), ("::1", Some("80")));
+        assert_eq!(split_host_port("[::1]"), ("::1", None));
+        assert_eq!(split_host_port("::1"), ("::1", None));
+    }
+}